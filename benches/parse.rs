@@ -0,0 +1,107 @@
+//! Times parsing a long 1 Hz activity in full against reading only its summary, and with
+//! the `rayon` feature, parsing a folder of them one file at a time against in parallel.
+//! Run with `cargo bench`, or `cargo bench --features rayon`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Trackpoints in the generated activity: two hours at one per second.
+const TRACKPOINTS: usize = 7200;
+/// Times each function is run for its average.
+const RUNS: u32 = 10;
+/// Copies of the activity in the folder benchmark.
+#[cfg(feature = "rayon")]
+const FOLDER_FILES: usize = 8;
+
+/// A two-hour run with a trackpoint every second, like a watch records.
+fn long_run() -> String {
+    let trackpoints: String = (0..TRACKPOINTS)
+        .map(|i| {
+            format!(
+                "<Trackpoint><Time>2024-03-01T{:02}:{:02}:{:02}Z</Time>\
+                 <Position><LatitudeDegrees>{:.6}</LatitudeDegrees>\
+                 <LongitudeDegrees>-75.000000</LongitudeDegrees></Position>\
+                 <AltitudeMeters>{:.1}</AltitudeMeters><DistanceMeters>{:.1}</DistanceMeters>\
+                 <HeartRateBpm><Value>{}</Value></HeartRateBpm>\
+                 <Extensions><ns3:TPX><ns3:Speed>3.0</ns3:Speed><ns3:RunCadence>85</ns3:RunCadence></ns3:TPX></Extensions>\
+                 </Trackpoint>",
+                12 + i / 3600,
+                i / 60 % 60,
+                i % 60,
+                40.0 + i as f64 * 0.000027,
+                100.0 + (i % 600) as f64 / 10.0,
+                i as f64 * 3.0,
+                140 + i % 20
+            )
+        })
+        .collect();
+    format!(
+        "<TrainingCenterDatabase xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\">\
+         <Activities><Activity Sport=\"Running\"><Id>2024-03-01T12:00:00Z</Id>\
+         <Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>{}</TotalTimeSeconds>\
+         <DistanceMeters>{}</DistanceMeters><Calories>1500</Calories>\
+         <Track>{}</Track></Lap></Activity></Activities></TrainingCenterDatabase>",
+        TRACKPOINTS,
+        TRACKPOINTS * 3,
+        trackpoints
+    )
+}
+
+/// Average time of [RUNS] calls of `f`, after one to warm up.
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    f();
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / RUNS
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!("{:<28} {:>8.2}ms", name, elapsed.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    let folder: PathBuf = std::env::temp_dir().join(format!("tcxrs-bench-{}", std::process::id()));
+    fs::create_dir_all(&folder).unwrap();
+    let file = folder.join("long-run.tcx");
+    fs::write(&file, long_run()).unwrap();
+    bench_file(&file);
+    #[cfg(feature = "rayon")]
+    bench_folder(&folder, &file);
+    fs::remove_dir_all(folder).unwrap();
+}
+
+fn bench_file(file: &Path) {
+    report(
+        "parse_file_sync",
+        time(|| tcxrs::parse_file_sync(file).unwrap()),
+    );
+    report(
+        "parse_file_summary_sync",
+        time(|| tcxrs::parse_file_summary_sync(file).unwrap()),
+    );
+}
+
+#[cfg(feature = "rayon")]
+fn bench_folder(folder: &Path, file: &Path) {
+    for i in 1..FOLDER_FILES {
+        fs::copy(file, folder.join(format!("long-run-{}.tcx", i))).unwrap();
+    }
+    println!(
+        "{} files, {} cores available:",
+        FOLDER_FILES,
+        std::thread::available_parallelism().map_or(1, |cores| cores.get())
+    );
+    report(
+        "parse_folder_sync",
+        time(|| tcxrs::parse_folder_sync(folder, None).unwrap()),
+    );
+    report(
+        "parse_folder_parallel",
+        time(|| tcxrs::parse_folder_parallel(folder).unwrap()),
+    );
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use flate2::read::GzDecoder;
+use tracing::{info, instrument};
+use zip::ZipArchive;
+
+use crate::{decode_tcx, is_tcx_file, parse_str, ParsedFiles, TrainingCenterDatabase};
+
+/// Whether `path` is a zip archive to read activities from in place of a folder.
+pub(crate) fn is_zip(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|e| e == "zip")
+}
+
+/// The contents of a gzipped file, decompressed.
+pub(crate) fn gunzip(data: &[u8]) -> Result<String> {
+    let mut decompressed = String::new();
+    GzDecoder::new(data).read_to_string(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Parses every `.tcx` and `.tcx.gz` file in the zip archive at `path`, e.g. a Strava bulk
+/// export, or only the first `limit` of them. Files are read in path order within the
+/// archive, like the files of a folder, and anything else in it is ignored. Fails on the
+/// first file that can't be parsed; see [parse_zip_files] to skip those instead.
+pub fn parse_zip(path: &Path, limit: Option<usize>) -> Result<Vec<TrainingCenterDatabase>> {
+    parse_zip_files(path, limit)?
+        .into_iter()
+        .map(|(name, parsed)| {
+            parsed.map_err(|e| anyhow!("Error parsing {}: {}", name.display(), e))
+        })
+        .collect()
+}
+
+/// Like [parse_zip], but with each file's own result paired with its path (the archive's
+/// path joined with the file's path within it), as [crate::parse_folder] returns them.
+#[instrument]
+pub fn parse_zip_files(path: &Path, limit: Option<usize>) -> Result<ParsedFiles> {
+    let file = File::open(path).map_err(|e| anyhow!("Unable to read {}: {}", path.display(), e))?;
+    let mut archive = ZipArchive::new(file)?;
+
+    let mut entries: Vec<(PathBuf, usize)> = (0..archive.len())
+        .filter_map(|i| Some((PathBuf::from(archive.name_for_index(i)?.ok()?.as_ref()), i)))
+        .filter(|(name, _)| is_tcx_file(name))
+        .collect();
+    entries.sort();
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+
+    let mut parsed_results = vec![];
+    for (name, index) in entries {
+        let parsed =
+            read_entry(&mut archive, index).and_then(|data| parse_str(&decode_tcx(&name, data)?));
+        parsed_results.push((path.join(name), parsed));
+    }
+    info!(
+        "Parsed {} files from {}",
+        parsed_results.len(),
+        path.display()
+    );
+    Ok(parsed_results)
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, index: usize) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    archive.by_index(index)?.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+    use crate::test_support::{one_lap_tcx, temp_path};
+
+    /// A zip archive under the system temp dir holding `files`, by name within it.
+    fn archive(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let path = temp_path(&format!("{}.zip", name));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn parse_zip_files_keeps_each_file_result() {
+        let run = one_lap_tcx("Running", "2024-03-01T12:00:00Z", 2000.0, "");
+        let path = archive(
+            "zip-files",
+            &[
+                ("b/run.tcx", &run),
+                ("a/bad.tcx", "<TrainingCenterDatabase>"),
+                ("notes.txt", "not an activity"),
+            ],
+        );
+        let parsed = parse_zip_files(&path, None).unwrap();
+        let names: Vec<(PathBuf, bool)> = parsed
+            .iter()
+            .map(|(name, parsed)| {
+                (
+                    name.strip_prefix(&path).unwrap().to_path_buf(),
+                    parsed.is_ok(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                (PathBuf::from("a/bad.tcx"), false),
+                (PathBuf::from("b/run.tcx"), true)
+            ]
+        );
+
+        let error = parse_zip(&path, None).unwrap_err().to_string();
+        assert!(error.contains("a/bad.tcx"), "{}", error);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,833 @@
+use std::{ops::Range, path::Path, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::{Days, NaiveDate};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use tracing::{info, warn};
+
+use crate::{
+    stats::{ActivityStats, FolderTotals},
+    units::{FEET_PER_METER, METERS_PER_MILE},
+    Activity,
+};
+
+/// Grade is measured over at least this many meters so GPS/baro jitter between
+/// neighboring trackpoints doesn't show up as steep spikes.
+static GRADE_MIN_DISTANCE: f64 = 20.0;
+/// Grade (in percent) at which the color ramp reaches full red.
+static STEEP_GRADE: f64 = 10.0;
+/// Trailing trackpoints pace is smoothed over on comparison charts.
+static COMPARE_PACE_WINDOW: usize = 30;
+/// How far, as a fraction of the average split time, a split has to be from it for its bar
+/// to be fully green or red on the splits chart.
+static SPLIT_COLOR_SPREAD: f64 = 0.1;
+/// Height in pixels added below the pace vs. heart rate chart for the totals footer.
+static FOOTER_HEIGHT: u32 = 40;
+
+/// How activities are spread along the X axis of the pace vs. heart rate chart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChartXAxis {
+    /// One step per activity, labeled with the activity number.
+    #[default]
+    Index,
+    /// Spaced by calendar days between activities and labeled with dates, so gaps in
+    /// training show up. Falls back to [ChartXAxis::Index] if any activity's id isn't a
+    /// timestamp.
+    Date,
+}
+
+/// Options for the pace vs. heart rate chart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChartOptions {
+    pub x_axis: ChartXAxis,
+    /// Add a footer with the number of activities, total distance and date range.
+    pub totals_footer: bool,
+}
+
+/// The series plotted by the pace vs. heart rate chart, independent of any rendering
+/// backend so other plotting libraries can draw the same data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChartData {
+    /// X axis positions: activity indexes, or days since [ChartData::first_date].
+    pub x_range: Range<usize>,
+    /// Average pace (seconds per mile) of each activity that recorded a distance.
+    pub pace: Vec<(usize, u64)>,
+    /// Average heart rate of each activity.
+    pub hr: Vec<(usize, usize)>,
+    /// Y axis range covering every pace value, with some padding.
+    pub pace_bounds: Range<u64>,
+    /// Secondary Y axis range covering every heart rate value, with some padding.
+    pub hr_bounds: Range<usize>,
+    /// Date of the first activity when the X axis counts days rather than activities.
+    pub first_date: Option<NaiveDate>,
+}
+
+impl ChartData {
+    /// Label for an X axis position: its date for dated charts, otherwise the position.
+    pub fn x_label(&self, x: usize) -> String {
+        match self.first_date {
+            Some(first) => (first + Days::new(x as u64)).format("%Y-%m-%d").to_string(),
+            None => x.to_string(),
+        }
+    }
+}
+
+/// Prepares the pace and heart rate series for `stats`, scaling each axis to its data.
+pub fn build_chart_data(stats: &[ActivityStats]) -> ChartData {
+    build_chart_data_with(stats, ChartXAxis::Index)
+}
+
+/// Same as [build_chart_data], with activities spread along the X axis by `x_axis`.
+pub fn build_chart_data_with(stats: &[ActivityStats], x_axis: ChartXAxis) -> ChartData {
+    let dates: Option<Vec<NaiveDate>> = match x_axis {
+        ChartXAxis::Index => None,
+        ChartXAxis::Date => stats.iter().map(ActivityStats::start_date).collect(),
+    };
+    let first_date = dates.as_ref().and_then(|dates| dates.iter().min().copied());
+    let xs: Vec<usize> = match (&dates, first_date) {
+        (Some(dates), Some(first)) => dates
+            .iter()
+            .map(|date| (*date - first).num_days() as usize)
+            .collect(),
+        _ => (0..stats.len()).collect(),
+    };
+
+    // Activities without distance have no pace, their average being u64::MAX seconds.
+    let pace: Vec<(usize, u64)> = xs
+        .iter()
+        .zip(stats)
+        .filter(|(_, stats)| stats.has_distance())
+        .map(|(x, stats)| (*x, stats.average_pace_seconds.as_secs()))
+        .collect();
+
+    let hr: Vec<(usize, usize)> = xs
+        .iter()
+        .zip(stats)
+        .map(|(x, stats)| (*x, stats.average_hr))
+        .collect();
+
+    ChartData {
+        x_range: 0..xs.iter().max().map_or(0, |x| x + 1),
+        pace_bounds: padded_bounds(pace.iter().map(|(_, p)| *p)).unwrap_or(420..550),
+        hr_bounds: padded_bounds(hr.iter().map(|(_, hr)| *hr as u64))
+            .map(|r| r.start as usize..r.end as usize)
+            .unwrap_or(115..180),
+        pace,
+        hr,
+        first_date,
+    }
+}
+
+/// Number of activities, total distance and date range of `stats`, e.g.
+/// `12 activities, 48.20mi / 77.57km, 2024-03-01 to 2024-03-29`.
+fn totals_footer(stats: &[ActivityStats]) -> String {
+    let totals = FolderTotals::new(stats);
+    let mut footer = format!(
+        "{} activities, {:.2}mi / {:.2}km",
+        totals.activities, totals.distance_mi, totals.distance_km
+    );
+    let dates: Vec<NaiveDate> = stats.iter().filter_map(ActivityStats::start_date).collect();
+    if let (Some(first), Some(last)) = (dates.iter().min(), dates.iter().max()) {
+        footer.push_str(&format!(", {} to {}", first, last));
+    }
+    footer
+}
+
+/// The min..max of `values` widened by 10% of their spread (at least 5) on each side.
+fn padded_bounds(values: impl Iterator<Item = u64>) -> Option<Range<u64>> {
+    let (min, max) = values.fold(None, |bounds, v| match bounds {
+        None => Some((v, v)),
+        Some((min, max)) => Some((v.min(min), v.max(max))),
+    })?;
+    let padding = ((max - min) / 10).max(5);
+    Some(min.saturating_sub(padding)..max.saturating_add(padding))
+}
+
+/// Which per-trackpoint metric [chart_compare] and [chart_activity] plot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartMetric {
+    /// Smoothed pace, in seconds per mile.
+    Pace,
+    HeartRate,
+}
+
+impl ChartMetric {
+    /// This metric for `activity`, against elapsed minutes or, when `by_distance`, against
+    /// the miles covered by then.
+    fn series(&self, activity: &Activity, by_distance: bool) -> Vec<(f64, f64)> {
+        let distances = activity.distance_series();
+        let x = |elapsed: Duration| {
+            if by_distance {
+                // The last trackpoint at or before `elapsed`.
+                let i = distances.partition_point(|(t, _)| *t <= elapsed);
+                let meters = i.checked_sub(1).map_or(0.0, |i| distances[i].1);
+                meters as f64 / METERS_PER_MILE as f64
+            } else {
+                elapsed.as_secs_f64() / 60.0
+            }
+        };
+        match self {
+            ChartMetric::Pace => activity
+                .smoothed_pace_series(COMPARE_PACE_WINDOW)
+                .into_iter()
+                .map(|(elapsed, pace)| (x(elapsed), pace as f64))
+                .collect(),
+            ChartMetric::HeartRate => activity
+                .hr_series()
+                .into_iter()
+                .map(|(elapsed, hr)| (x(elapsed), hr as f64))
+                .collect(),
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            ChartMetric::Pace => "Pace (seconds per mile)",
+            ChartMetric::HeartRate => "Heart rate",
+        }
+    }
+}
+
+/// Overlays `metric` for activities `a` (blue) and `b` (red) against elapsed time, e.g. to
+/// compare this week's tempo run with last week's. Each line covers its own activity's
+/// duration, and the X axis fits the longer of the two.
+pub fn chart_compare(
+    a: &Activity,
+    b: &Activity,
+    metric: ChartMetric,
+    filename: &str,
+) -> Result<()> {
+    let (series_a, series_b) = (metric.series(a, false), metric.series(b, false));
+    let all = || series_a.iter().chain(series_b.iter());
+    if all().next().is_none() {
+        return Err(anyhow!(
+            "Neither activity has {} data",
+            metric.description()
+        ));
+    }
+    let max_minutes = all().map(|(t, _)| *t).fold(0.0, f64::max).max(1.0);
+    let (min_y, max_y) = all().fold((f64::MAX, f64::MIN), |(min, max), (_, y)| {
+        (min.min(*y), max.max(*y))
+    });
+    let padding = ((max_y - min_y) * 0.1).max(5.0);
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .margin(5)
+        .caption(
+            format!("{} comparison", metric.description()),
+            ("sans-serif", 50.0).into_font(),
+        )
+        .build_cartesian_2d(0f64..max_minutes, min_y - padding..max_y + padding)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_desc("Elapsed time (minutes)")
+        .y_desc(metric.description())
+        .draw()?;
+
+    for (activity, series, color) in [(a, series_a, BLUE), (b, series_b, RED)] {
+        chart
+            .draw_series(LineSeries::new(series, &color))?
+            .label(activity.id.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    info!("Comparison chart has been saved to {}", filename);
+
+    Ok(())
+}
+
+/// Plots `metric` over the course of `activity`, against elapsed time or, when
+/// `by_distance`, against distance so routes can be compared mile by mile. The X axis
+/// spans the whole activity.
+pub fn chart_activity(
+    activity: &Activity,
+    metric: ChartMetric,
+    by_distance: bool,
+    filename: &str,
+) -> Result<()> {
+    let series = metric.series(activity, by_distance);
+    if series.is_empty() {
+        return Err(anyhow!(
+            "Activity {} has no {} data",
+            activity.id,
+            metric.description()
+        ));
+    }
+    let (max_x, x_desc) = match activity.distance_series().last() {
+        Some((_, meters)) if by_distance => {
+            (*meters as f64 / METERS_PER_MILE as f64, "Distance (miles)")
+        }
+        Some((elapsed, _)) => (elapsed.as_secs_f64() / 60.0, "Elapsed time (minutes)"),
+        None => (0.0, ""),
+    };
+    let (min_y, max_y) = series
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (_, y)| {
+            (min.min(*y), max.max(*y))
+        });
+    let padding = ((max_y - min_y) * 0.1).max(5.0);
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .margin(5)
+        .caption(metric.description(), ("sans-serif", 50.0).into_font())
+        .build_cartesian_2d(
+            0f64..max_x.max(f64::EPSILON),
+            min_y - padding..max_y + padding,
+        )?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_desc(x_desc)
+        .y_desc(metric.description())
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(series, &BLUE))?;
+
+    root.present()?;
+    info!("Activity chart has been saved to {}", filename);
+
+    Ok(())
+}
+
+/// Renders a shareable summary card for one activity: its sport and date, then distance,
+/// pace, average HR and elevation gain in large type on a fixed-size PNG.
+pub fn render_summary_card(stats: &ActivityStats, filename: &str) -> Result<()> {
+    let root = BitMapBackend::new(filename, (800, 480)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (header, body) = root.split_vertically(120);
+
+    let title = match stats.start_date() {
+        Some(date) => format!("{} - {}", stats.sport(), date.format("%B %-d, %Y")),
+        None => stats.sport().to_string(),
+    };
+    header.draw_text(
+        &title,
+        &("sans-serif", 44.0).into_font().color(&BLACK),
+        (30, 40),
+    )?;
+
+    let pace = stats.average_pace_seconds.as_secs();
+    let tiles = [
+        ("Distance", format!("{:.2} mi", stats.distance_mi)),
+        ("Pace", format!("{}:{:02} /mi", pace / 60, pace % 60)),
+        ("Avg HR", format!("{} bpm", stats.average_hr)),
+        ("Elevation", format!("{} ft", stats.elevation_gain)),
+    ];
+    for (area, (label, value)) in body.split_evenly((2, 2)).iter().zip(tiles) {
+        area.draw_text(
+            label,
+            &("sans-serif", 24.0)
+                .into_font()
+                .color(&RGBColor(110, 110, 110)),
+            (30, 20),
+        )?;
+        area.draw_text(
+            &value,
+            &("sans-serif", 56.0).into_font().color(&BLUE),
+            (30, 60),
+        )?;
+    }
+
+    root.present()?;
+    info!("Summary card has been saved to {}", filename);
+    Ok(())
+}
+
+/// Draws the elevation profile of `activity` (elevation in feet over distance in miles),
+/// with each section colored by its grade: green for flat or downhill, through yellow, to
+/// red for climbs of [STEEP_GRADE]% or more.
+pub fn chart_elevation(activity: &Activity, filename: &str) -> Result<()> {
+    let mut profile: Vec<(f64, f64)> = vec![];
+    for (distance, altitude) in activity.elevation_profile() {
+        let distance = distance as f64;
+        let far_enough = profile
+            .last()
+            .is_none_or(|(last, _)| distance - last >= GRADE_MIN_DISTANCE);
+        if far_enough {
+            profile.push((distance, altitude));
+        }
+    }
+    if profile.len() < 2 {
+        return Err(anyhow!("Activity {} has no elevation data", activity.id));
+    }
+
+    let meters_per_mile = METERS_PER_MILE as f64;
+    let max_distance = profile[profile.len() - 1].0 / meters_per_mile;
+    let (min_alt, max_alt) = profile
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), (_, alt)| {
+            (min.min(*alt), max.max(*alt))
+        });
+    let padding = ((max_alt - min_alt) * 0.1).max(5.0);
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .margin(5)
+        .caption("Elevation by grade", ("sans-serif", 50.0).into_font())
+        .build_cartesian_2d(
+            0f64..max_distance,
+            (min_alt - padding) * FEET_PER_METER..(max_alt + padding) * FEET_PER_METER,
+        )?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_desc("Distance (miles)")
+        .y_desc("Elevation (feet)")
+        .draw()?;
+
+    chart.draw_series(profile.windows(2).map(|pair| {
+        let ((d1, a1), (d2, a2)) = (pair[0], pair[1]);
+        let grade = (a2 - a1) / (d2 - d1) * 100.0;
+        PathElement::new(
+            vec![
+                (d1 / meters_per_mile, a1 * FEET_PER_METER),
+                (d2 / meters_per_mile, a2 * FEET_PER_METER),
+            ],
+            grade_color(grade).stroke_width(3),
+        )
+    }))?;
+
+    root.present()?;
+    info!("Elevation chart has been saved to {}", filename);
+
+    Ok(())
+}
+
+/// Green for flat (or downhill), ramping through yellow to red at [STEEP_GRADE].
+fn grade_color(grade: f64) -> RGBColor {
+    let t = (grade / STEEP_GRADE).clamp(0.0, 1.0);
+    if t < 0.5 {
+        RGBColor((t * 2.0 * 255.0) as u8, 200, 0)
+    } else {
+        RGBColor(255, ((1.0 - t) * 2.0 * 200.0) as u8, 0)
+    }
+}
+
+/// Draws a bar chart of the minutes `activity` spent in each HR zone to `filename`, for
+/// zones split at the ascending bpm `boundaries` as in [Activity::hr_zones]. Bars are
+/// colored from blue for the easiest zone to red for the hardest.
+pub fn chart_hr_zones(activity: &Activity, boundaries: &[usize], filename: &str) -> Result<()> {
+    let minutes: Vec<f64> = activity
+        .hr_zones(boundaries)
+        .iter()
+        .map(|time| time.as_secs_f64() / 60.0)
+        .collect();
+    let max_minutes = minutes.iter().cloned().fold(0.0, f64::max);
+    if max_minutes <= 0.0 {
+        return Err(anyhow!("Activity {} has no heart rate data", activity.id));
+    }
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .margin(5)
+        .caption("Time in HR zones", ("sans-serif", 50.0).into_font())
+        .build_cartesian_2d(
+            // Segmented ranges include their end, so this is one segment per zone.
+            (0..boundaries.len()).into_segmented(),
+            0f64..max_minutes * 1.1,
+        )?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_desc("Heart rate zone (bpm)")
+        .y_desc("Minutes")
+        .x_label_formatter(&|zone| match zone {
+            SegmentValue::CenterOf(zone) if *zone <= boundaries.len() => {
+                zone_label(*zone, boundaries)
+            }
+            _ => String::new(),
+        })
+        .draw()?;
+
+    chart.draw_series(minutes.iter().enumerate().map(|(zone, minutes)| {
+        let mut bar = Rectangle::new(
+            [
+                (SegmentValue::Exact(zone), 0.0),
+                (SegmentValue::Exact(zone + 1), *minutes),
+            ],
+            zone_color(zone, boundaries.len()).filled(),
+        );
+        bar.set_margin(0, 0, 10, 10);
+        bar
+    }))?;
+
+    root.present()?;
+    info!("HR zone chart has been saved to {}", filename);
+
+    Ok(())
+}
+
+/// Draws a bar chart of the [Activity::distance_splits] of `activity` every `split_meters`
+/// to `filename`. Bar height is speed, so faster splits are taller, and each bar is labeled
+/// with its split time. Bars are green for splits faster than the average split and red
+/// for slower ones, fully saturated at [SPLIT_COLOR_SPREAD] off the average.
+pub fn chart_splits(activity: &Activity, split_meters: f32, filename: &str) -> Result<()> {
+    let splits = activity.distance_splits(split_meters);
+    if splits.is_empty() {
+        return Err(anyhow!(
+            "Activity {} is shorter than one {}m split",
+            activity.id,
+            split_meters
+        ));
+    }
+    let seconds: Vec<f64> = splits
+        .iter()
+        .map(|split| split.as_secs_f64().max(1.0))
+        .collect();
+    let average = seconds.iter().sum::<f64>() / seconds.len() as f64;
+    let meters_per_mile = METERS_PER_MILE as f64;
+    let mph: Vec<f64> = seconds
+        .iter()
+        .map(|s| split_meters as f64 / meters_per_mile / (s / 3600.0))
+        .collect();
+    let max_mph = mph.iter().cloned().fold(0.0, f64::max);
+
+    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(50)
+        .margin(5)
+        .caption("Splits", ("sans-serif", 50.0).into_font())
+        .build_cartesian_2d((0..splits.len() - 1).into_segmented(), 0f64..max_mph * 1.15)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .x_desc(format!("Split ({}m)", split_meters))
+        .y_desc("Speed (mph)")
+        .x_label_formatter(&|split| match split {
+            SegmentValue::CenterOf(split) => (split + 1).to_string(),
+            _ => String::new(),
+        })
+        .draw()?;
+
+    chart.draw_series(seconds.iter().zip(&mph).enumerate().map(|(i, (s, mph))| {
+        let mut bar = Rectangle::new(
+            [
+                (SegmentValue::Exact(i), 0.0),
+                (SegmentValue::Exact(i + 1), *mph),
+            ],
+            split_color(*s / average - 1.0).filled(),
+        );
+        bar.set_margin(0, 0, 5, 5);
+        bar
+    }))?;
+
+    chart.draw_series(seconds.iter().zip(&mph).enumerate().map(|(i, (s, mph))| {
+        let seconds = s.round() as u64;
+        Text::new(
+            format!("{}:{:02}", seconds / 60, seconds % 60),
+            (SegmentValue::CenterOf(i), mph + max_mph * 0.02),
+            TextStyle::from(("sans-serif", 14.0).into_font())
+                .pos(Pos::new(HPos::Center, VPos::Bottom)),
+        )
+    }))?;
+
+    root.present()?;
+    info!("Splits chart has been saved to {}", filename);
+
+    Ok(())
+}
+
+/// Green for a split `deviation` (as a fraction) faster than average, red for slower,
+/// ramping from gray at the average to full color at [SPLIT_COLOR_SPREAD].
+fn split_color(deviation: f64) -> RGBColor {
+    let t = (deviation / SPLIT_COLOR_SPREAD).clamp(-1.0, 1.0);
+    let fade = |full: f64| (160.0 + (full - 160.0) * t.abs()) as u8;
+    if t < 0.0 {
+        RGBColor(fade(0.0), fade(180.0), fade(0.0))
+    } else {
+        RGBColor(fade(220.0), fade(0.0), fade(0.0))
+    }
+}
+
+/// Label for zone `zone` split at `boundaries`, e.g. `Z2 120-139`.
+fn zone_label(zone: usize, boundaries: &[usize]) -> String {
+    let range = match (
+        zone.checked_sub(1).map(|i| boundaries[i]),
+        boundaries.get(zone),
+    ) {
+        (None, Some(upper)) => format!("<{}", upper),
+        (Some(lower), Some(upper)) => format!("{}-{}", lower, upper.saturating_sub(1)),
+        (Some(lower), None) => format!("{}+", lower),
+        (None, None) => String::from("all"),
+    };
+    format!("Z{} {}", zone + 1, range)
+}
+
+/// Blue for the first of `boundary_count + 1` zones, ramping to red for the last.
+fn zone_color(zone: usize, boundary_count: usize) -> RGBColor {
+    let t = if boundary_count == 0 {
+        0.0
+    } else {
+        zone as f64 / boundary_count as f64
+    };
+    RGBColor((t * 255.0) as u8, 0, ((1.0 - t) * 255.0) as u8)
+}
+
+/// What the pace vs. heart rate chart would be missing for `stats`, if it would come out
+/// blank or with only one line, e.g. for a folder of indoor activities without distance.
+/// Activities without distance among ones with it are only left out of the pace line.
+fn missing_chart_series(stats: &[ActivityStats]) -> Option<&'static str> {
+    if stats.is_empty() {
+        Some("there are no activities")
+    } else if !stats.iter().any(|s| s.distance_mi > 0.0) {
+        Some("no activity recorded a distance to plot pace from")
+    } else if !stats.iter().any(|s| s.average_hr > 0) {
+        Some("no activity recorded heart rate")
+    } else {
+        None
+    }
+}
+
+/// Renders the pace vs. heart rate chart of `activity_stats` to `chart_filename`, as an SVG
+/// for a `.svg` file and a PNG (or the bitmap format of its extension) otherwise. Both axes
+/// are scaled to the data, see [build_chart_data_with]. No chart is written, only a warning
+/// logged, when there's no pace or HR to plot.
+pub fn render_chart(
+    activity_stats: &[ActivityStats],
+    chart_filename: &str,
+    options: ChartOptions,
+) -> Result<()> {
+    if let Some(missing) = missing_chart_series(activity_stats) {
+        warn!("Skipping chart {}: {}", chart_filename, missing);
+        return Ok(());
+    }
+    let footer_height = if options.totals_footer {
+        FOOTER_HEIGHT
+    } else {
+        0
+    };
+    let size = (1024, 768 + footer_height);
+    let is_svg = Path::new(chart_filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+    if is_svg {
+        let root = SVGBackend::new(chart_filename, size).into_drawing_area();
+        draw_chart(root, activity_stats, options, chart_filename)?;
+    } else {
+        let root = BitMapBackend::new(chart_filename, size).into_drawing_area();
+        draw_chart(root, activity_stats, options, chart_filename)?;
+    }
+    info!("Chart has been saved to {}", chart_filename);
+
+    Ok(())
+}
+
+/// Draws the [render_chart] chart on `root`, whichever backend it's drawn by.
+fn draw_chart<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    activity_stats: &[ActivityStats],
+    options: ChartOptions,
+    chart_filename: &str,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let data = build_chart_data_with(activity_stats, options.x_axis);
+    let x_desc = match data.first_date {
+        Some(_) => "Date",
+        None => "Activity number",
+    };
+
+    root.fill(&WHITE)?;
+    let (root, footer) = root.split_vertically(768);
+    if options.totals_footer {
+        footer.draw_text(
+            &totals_footer(activity_stats),
+            &("sans-serif", 20.0).into_font().color(&BLACK),
+            (10, (FOOTER_HEIGHT / 4) as i32),
+        )?;
+    }
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(35)
+        .y_label_area_size(40)
+        .right_y_label_area_size(40)
+        .margin(5)
+        .caption(
+            "Avg pace vs. Avg heart rate",
+            ("sans-serif", 50.0).into_font(),
+        )
+        .build_cartesian_2d(data.x_range.clone(), data.pace_bounds.clone())?
+        .set_secondary_coord(data.x_range.clone(), data.hr_bounds.clone());
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .disable_y_mesh()
+        .x_desc(x_desc)
+        .x_label_formatter(&|x| data.x_label(*x))
+        .y_desc("Pace (seconds per mile)")
+        .draw()?;
+
+    chart
+        .configure_secondary_axes()
+        .y_desc("Heart rate")
+        .draw()?;
+
+    chart
+        .draw_series(LineSeries::new(data.pace.clone(), &BLUE))?
+        .label("Seconds per mile")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+
+    chart
+        .draw_secondary_series(LineSeries::new(data.hr.clone(), &RED))?
+        .label("Heart rate")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperRight)
+        .background_style(RGBColor(128, 128, 128))
+        .draw()?;
+
+    root.present()
+        .map_err(|e| anyhow!("Unable to write chart to {}: {}", chart_filename, e))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_support::{one_lap_tcx, stats_of, temp_path};
+
+    /// Stats of a 10 minute run starting at `start` covering `meters`, averaging `hr`.
+    fn run(start: &str, meters: f64, hr: usize) -> ActivityStats {
+        let hr = format!("<AverageHeartRateBpm><Value>{hr}</Value></AverageHeartRateBpm>");
+        stats_of(&one_lap_tcx("Running", start, meters, &hr))
+    }
+
+    /// 8:03/mi, 4:01/mi and a treadmill run without distance.
+    fn runs() -> Vec<ActivityStats> {
+        vec![
+            run("2024-03-01T12:00:00Z", 2000.0, 150),
+            run("2024-03-04T12:00:00Z", 4000.0, 160),
+            run("2024-03-05T12:00:00Z", 0.0, 140),
+        ]
+    }
+
+    #[test]
+    fn chart_data_scales_each_axis_to_the_stats() {
+        let data = build_chart_data(&runs());
+        assert_eq!(data.x_range, 0..3);
+        assert_eq!(data.pace, [(0, 483), (1, 241)]);
+        assert_eq!(data.hr, [(0, 150), (1, 160), (2, 140)]);
+        // Padded by a tenth of the spread, or at least 5.
+        assert_eq!(data.pace_bounds, 217..507);
+        assert_eq!(data.hr_bounds, 135..165);
+        assert_eq!(data.first_date, None);
+        assert_eq!(data.x_label(2), "2");
+    }
+
+    #[test]
+    fn dated_chart_data_counts_days_from_the_first_activity() {
+        let data = build_chart_data_with(&runs(), ChartXAxis::Date);
+        assert_eq!(data.x_range, 0..5);
+        assert_eq!(data.pace, [(0, 483), (3, 241)]);
+        assert_eq!(data.first_date, NaiveDate::from_ymd_opt(2024, 3, 1));
+        assert_eq!(data.x_label(3), "2024-03-04");
+    }
+
+    #[test]
+    fn chart_data_without_stats_falls_back_to_typical_bounds() {
+        let data = build_chart_data(&[]);
+        assert_eq!(data.x_range, 0..0);
+        assert!(data.pace.is_empty() && data.hr.is_empty());
+        assert_eq!(data.pace_bounds, 420..550);
+        assert_eq!(data.hr_bounds, 115..180);
+    }
+
+    #[test]
+    fn padded_bounds_saturate() {
+        assert_eq!(
+            padded_bounds([u64::MAX].into_iter()),
+            Some(u64::MAX - 5..u64::MAX)
+        );
+        assert_eq!(padded_bounds([2, 3].into_iter()), Some(0..8));
+        assert_eq!(padded_bounds(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn charts_without_pace_or_hr_are_skipped() {
+        let treadmill = || run("2024-03-01T12:00:00Z", 0.0, 140);
+        let no_hr = run("2024-03-01T12:00:00Z", 2000.0, 0);
+        assert_eq!(
+            missing_chart_series(&[treadmill()]),
+            Some("no activity recorded a distance to plot pace from")
+        );
+        assert_eq!(
+            missing_chart_series(&[no_hr]),
+            Some("no activity recorded heart rate")
+        );
+        assert_eq!(missing_chart_series(&runs()), None);
+
+        let path = temp_path("skipped-chart.svg");
+        let filename = path.to_str().unwrap();
+        render_chart(&[treadmill()], filename, ChartOptions::default()).unwrap();
+        assert!(!path.exists());
+        render_chart(&[], filename, ChartOptions::default()).unwrap();
+        assert!(!path.exists());
+    }
+
+    /// Renders the chart of [runs] to a temp file `name` and checks something was written.
+    fn assert_renders(name: &str) {
+        let path = temp_path(name);
+        render_chart(&runs(), path.to_str().unwrap(), ChartOptions::default()).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0, "{name} is empty");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn render_chart_writes_svg() {
+        assert_renders("chart.svg");
+    }
+
+    // font-kit trips a debug-build precondition check rasterizing glyphs, so the bitmap
+    // backend is only tested in release builds, e.g. `cargo test --release`.
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn render_chart_writes_png() {
+        assert_renders("chart.png");
+    }
+}
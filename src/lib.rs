@@ -1,27 +1,140 @@
+#[cfg(feature = "async")]
+use futures::stream::{self, BoxStream, StreamExt};
+#[cfg(all(feature = "charts", feature = "async"))]
+use std::collections::BTreeMap;
 use std::{
     fs::{self, File},
+    io::BufReader,
     path::{Path, PathBuf},
 };
 
 use anyhow::{anyhow, Result};
-use plotters::prelude::*;
+#[cfg(all(feature = "charts", feature = "async"))]
+use chart::{render_chart, ChartOptions};
+use chrono::{DateTime, TimeDelta, Utc};
+use profile::AthleteProfile;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
 use serde_xml_rs::from_str;
-use stats::ActivityStats;
-use tracing::{info, instrument};
+use stats::{summary_by_sport, ActivityStats, SummaryStats};
+#[cfg(feature = "async")]
+use stats::{DecimalSeparator, LapStats};
+use summary::ActivitySummary;
+use tracing::{debug, info, instrument, warn};
+#[cfg(feature = "async")]
+use units::Units;
 
+#[cfg(feature = "archives")]
+pub use crate::archive::{parse_zip, parse_zip_files};
 pub use crate::tcx::*;
+#[cfg(feature = "archives")]
+mod archive;
+/// Only available with the `charts` feature, which is enabled by default.
+#[cfg(feature = "charts")]
+pub mod chart;
+pub mod load;
+pub mod profile;
+pub mod report;
 pub mod stats;
+pub mod summary;
 pub mod tcx;
+#[cfg(test)]
+mod test_support;
+pub mod units;
+/// Only available with the `async` feature, which is enabled by default.
+#[cfg(feature = "async")]
+pub mod watch;
+pub mod zones;
 
+/// Activities starting this close together may be duplicates of each other.
+static DUPLICATE_START_TOLERANCE: TimeDelta = TimeDelta::seconds(60);
+/// Fraction by which the distances of duplicated activities may differ.
+static DUPLICATE_DISTANCE_TOLERANCE: f32 = 0.01;
+/// First two bytes of every gzip stream.
+static GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[cfg(feature = "async")]
 #[instrument]
 pub async fn parse_file(filename: &Path) -> Result<TrainingCenterDatabase> {
     info!("Begin parsing");
-    let file_data = tokio::fs::read_to_string(filename).await?;
-    let tcb = from_str(file_data.as_str())?;
+    let file_data = decode_tcx(filename, tokio::fs::read(filename).await?)?;
+    let tcb = parse_str(file_data.as_str())?;
+    info!("Successfully parsed");
+    Ok(tcb)
+}
+
+/// Blocking equivalent of [parse_file], available without the `async` feature.
+#[instrument]
+pub fn parse_file_sync(filename: &Path) -> Result<TrainingCenterDatabase> {
+    info!("Begin parsing");
+    let tcb = parse_str(&decode_tcx(filename, fs::read(filename)?)?)?;
     info!("Successfully parsed");
     Ok(tcb)
 }
 
+/// Reads only the id, sport and lap-level aggregates of the first activity in `filename`,
+/// skipping over every trackpoint, for scans over a whole history that don't need
+/// per-second data. Trackpoints make up nearly all of a TCX file: a two-hour 1 Hz run
+/// takes 3ms to summarize against 265ms for [parse_file] (`cargo bench`).
+#[cfg(feature = "async")]
+#[instrument]
+pub async fn parse_file_summary(filename: &Path) -> Result<ActivitySummary> {
+    let file_data = decode_tcx(filename, tokio::fs::read(filename).await?)?;
+    ActivitySummary::from_tcx(&file_data)
+}
+
+/// Blocking equivalent of [parse_file_summary], available without the `async` feature.
+#[instrument]
+pub fn parse_file_summary_sync(filename: &Path) -> Result<ActivitySummary> {
+    ActivitySummary::from_tcx(&decode_tcx(filename, fs::read(filename)?)?)
+}
+
+/// The TCX document read from `path`, decompressed first if it's gzipped: a `.tcx.gz`
+/// file, or any file starting with the gzip magic bytes, as some exports gzip files
+/// without renaming them. Gzipped files need the `archives` feature.
+fn decode_tcx(path: &Path, data: Vec<u8>) -> Result<String> {
+    if is_gzipped_tcx(path) || data.starts_with(&GZIP_MAGIC) {
+        #[cfg(feature = "archives")]
+        return archive::gunzip(&data);
+        #[cfg(not(feature = "archives"))]
+        return Err(anyhow!(
+            "{} is gzipped, which needs the `archives` feature",
+            path.display()
+        ));
+    }
+    Ok(String::from_utf8(data)?)
+}
+
+/// Whether `path` names a TCX file to parse: `.tcx`, or `.tcx.gz` with the `archives`
+/// feature.
+pub(crate) fn is_tcx_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "tcx")
+        || (cfg!(feature = "archives") && is_gzipped_tcx(path))
+}
+
+fn is_gzipped_tcx(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".tcx.gz"))
+}
+
+/// Parses a TCX document that's already in memory, e.g. read from stdin. Laps of every
+/// activity are put in chronological order, and trackpoints missing their time get one
+/// interpolated from their neighbours. Activities with a blank id get their
+/// [Activity::effective_id].
+pub fn parse_str(data: &str) -> Result<TrainingCenterDatabase> {
+    let mut tcb: TrainingCenterDatabase = from_str(data)?;
+    for activity in tcb.activities_iter_mut() {
+        activity.interpolate_missing_times();
+        activity.sort_laps();
+        activity.id = activity.effective_id();
+    }
+    Ok(tcb)
+}
+
+/// Every TCX file (see [is_tcx_file]) in `dir` and its subfolders, sorted by path.
+/// `fs::read_dir` order depends on the OS and filesystem, so sorting keeps parsing,
+/// `--limit` and the order of activities with equal ids reproducible between runs.
 fn all_tcx_paths(dir: &Path) -> Result<Vec<PathBuf>> {
     if !dir.is_dir() {
         return Err(anyhow!("Directory {} is not a folder.", dir.display()));
@@ -33,140 +146,1096 @@ fn all_tcx_paths(dir: &Path) -> Result<Vec<PathBuf>> {
         let path = entry.path();
         if path.is_dir() {
             paths.extend(all_tcx_paths(&path)?);
-        } else if path.extension().is_some_and(|e| e == "tcx") {
+        } else if is_tcx_file(&path) {
             paths.push(path);
         }
     }
+    paths.sort();
     Ok(paths)
 }
 
-#[cfg(not(feature = "slow"))]
+/// Each file parsed from a folder, paired with its path, in path order. A file that
+/// couldn't be read or parsed has the error instead, so one malformed file doesn't stop the
+/// rest of the folder being read. See [successfully_parsed].
+pub type ParsedFiles = Vec<(PathBuf, Result<TrainingCenterDatabase>)>;
+
+/// The files of `parsed` that were parsed, logging a warning for each that couldn't be.
+pub fn successfully_parsed(parsed: ParsedFiles) -> Vec<TrainingCenterDatabase> {
+    parsed
+        .into_iter()
+        .filter_map(|(path, result)| parsed_or_warn(&path, result))
+        .collect()
+}
+
+/// The database parsed from `path`, or `None` after logging a warning if it couldn't be.
+fn parsed_or_warn(
+    path: &Path,
+    result: Result<TrainingCenterDatabase>,
+) -> Option<TrainingCenterDatabase> {
+    result
+        .map_err(|e| warn!("Skipping {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Parses `folder` with [parse_zip_files] instead when it's a zip archive, and the
+/// `archives` feature is enabled.
+#[cfg_attr(not(feature = "archives"), allow(unused_variables))]
+fn parse_if_zip(folder: &Path, limit: Option<usize>) -> Option<Result<ParsedFiles>> {
+    #[cfg(feature = "archives")]
+    if archive::is_zip(folder) {
+        return Some(parse_zip_files(folder, limit));
+    }
+    None
+}
+
+/// Paths of the `.tcx` files to parse in `folder`, in path order, only keeping the first
+/// `limit` when one is given.
+fn limited_tcx_paths(folder: &Path, limit: Option<usize>) -> Result<Vec<PathBuf>> {
+    let mut paths = all_tcx_paths(folder)?;
+    if let Some(limit) = limit {
+        paths.truncate(limit);
+    }
+    Ok(paths)
+}
+
+/// Parses every TCX file in `folder` (and its subfolders), or only the first `limit` of
+/// them in path order. With the `archives` feature `folder` may also be a zip archive of
+/// them, and gzipped `.tcx.gz` files are read too. Only fails if `folder` itself can't be
+/// read; files that fail to parse are returned with their error.
+#[cfg(all(feature = "async", not(feature = "slow")))]
 #[instrument]
-pub async fn parse_folder(folder: &Path) -> Result<Vec<TrainingCenterDatabase>> {
-    let paths: Vec<PathBuf> = all_tcx_paths(folder)?;
+pub async fn parse_folder(folder: &Path, limit: Option<usize>) -> Result<ParsedFiles> {
+    if let Some(parsed_results) = parse_if_zip(folder, limit) {
+        return parsed_results;
+    }
+    let paths: Vec<PathBuf> = limited_tcx_paths(folder, limit)?;
 
     let mut join_handles = vec![];
-    for path in paths {
-        join_handles.push(tokio::spawn(async move {
-            parse_file(&path)
-                .await
-                .expect(format!("Error parsing {}", path.display()).as_str())
-        }));
+    for path in paths.clone() {
+        join_handles.push(tokio::spawn(async move { parse_file(&path).await }));
     }
     let parsed_results = futures::future::join_all(join_handles)
         .await
         .into_iter()
-        .map(|tcx| tcx.unwrap())
+        .zip(paths)
+        .map(|(tcx, path)| (path, tcx.map_err(anyhow::Error::from).and_then(|tcx| tcx)))
         .collect();
     Ok(parsed_results)
 }
 
+/// Like [parse_folder], but yields each file as soon as it's parsed instead of holding
+/// every database in memory at once, with at most `concurrency` files being parsed at a
+/// time. Files are still yielded in path order, so a caller that keeps only something
+/// small per file (e.g. [ActivityStats]) gets the same results as from [parse_folder].
+/// Zip archives are read whole up front.
+#[cfg(feature = "async")]
+pub fn parse_folder_stream(
+    folder: &Path,
+    limit: Option<usize>,
+    concurrency: usize,
+) -> Result<BoxStream<'static, (PathBuf, Result<TrainingCenterDatabase>)>> {
+    if let Some(parsed_results) = parse_if_zip(folder, limit) {
+        return Ok(stream::iter(parsed_results?).boxed());
+    }
+    let paths = limited_tcx_paths(folder, limit)?;
+    Ok(stream::iter(paths)
+        .map(|path| async move {
+            let task_path = path.clone();
+            let parsed = tokio::spawn(async move { parse_file(&task_path).await })
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|tcx| tcx);
+            (path, parsed)
+        })
+        .buffered(concurrency.max(1))
+        .boxed())
+}
+
 #[cfg(feature = "slow")]
 #[instrument]
-pub async fn parse_folder(folder: &Path) -> Result<Vec<TrainingCenterDatabase>> {
-    let paths = all_tcx_paths(folder)?;
+pub async fn parse_folder(folder: &Path, limit: Option<usize>) -> Result<ParsedFiles> {
+    if let Some(parsed_results) = parse_if_zip(folder, limit) {
+        return parsed_results;
+    }
+    let paths = limited_tcx_paths(folder, limit)?;
 
     let mut parsed_results = vec![];
     for path in paths {
-        parsed_results.push(parse_file(&path).await?);
+        let parsed = parse_file(&path).await;
+        parsed_results.push((path, parsed));
     }
     Ok(parsed_results)
 }
 
+/// Blocking equivalent of [parse_folder], parsing one file at a time. Available without
+/// the `async` feature.
+#[instrument]
+pub fn parse_folder_sync(folder: &Path, limit: Option<usize>) -> Result<ParsedFiles> {
+    if let Some(parsed_results) = parse_if_zip(folder, limit) {
+        return parsed_results;
+    }
+    Ok(limited_tcx_paths(folder, limit)?
+        .into_iter()
+        .map(|path| {
+            let parsed = parse_file_sync(&path);
+            (path, parsed)
+        })
+        .collect())
+}
+
+/// Parses every `.tcx` file in `folder` (and its subfolders) in parallel on rayon's thread
+/// pool, for synchronous callers that want the speedup of [parse_folder] without an async
+/// runtime. Results are in path order, like [parse_folder_sync]. Requires the `rayon`
+/// feature. Any speedup depends on the cores available: on a single core it's slower,
+/// 2.25s against 1.93s for [parse_folder_sync] on 8 two-hour 1 Hz files
+/// (`cargo bench --features rayon`).
+#[cfg(feature = "rayon")]
+#[instrument]
+pub fn parse_folder_parallel(folder: &Path) -> Result<ParsedFiles> {
+    if let Some(parsed_results) = parse_if_zip(folder, None) {
+        return parsed_results;
+    }
+    Ok(all_tcx_paths(folder)?
+        .into_par_iter()
+        .map(|path| {
+            let parsed = parse_file_sync(&path);
+            (path, parsed)
+        })
+        .collect())
+}
+
+/// Every activity of every parsed file (see [TrainingCenterDatabase::activities_iter]),
+/// with lap elevations calculated using `elevation`
+/// (or left as they are for `None`), in the order they started. See [activity_order] for
+/// activities without a timestamp id.
+fn sorted_activities(
+    parsed_results: &mut [TrainingCenterDatabase],
+    elevation: Option<ElevationOptions>,
+) -> Vec<&Activity> {
+    let mut activities: Vec<_> = parsed_results
+        .iter_mut()
+        .flat_map(|tcb| tcb.activities_iter_mut())
+        .map(|activity| {
+            if let Some(elevation) = elevation {
+                activity.calc_lap_elevations_with(elevation);
+            }
+            // Return an immutable activity after mutating.
+            &*activity
+        })
+        .collect();
+
+    activities.sort_by(|a1, a2| activity_order(a1).cmp(&activity_order(a2)));
+    activities
+}
+
+/// Sort key putting activities in chronological order by their [Activity::start_time],
+/// even when their ids are timestamps in different formats or offsets. Those without a
+/// start time go last, by id.
+fn activity_order(activity: &Activity) -> (bool, Option<DateTime<Utc>>, &str) {
+    let start = activity.start_time();
+    (start.is_none(), start, &activity.id)
+}
+
+/// Parses every activity in `folder` and computes its stats for the athlete in `profile`,
+/// in activity order.
+#[cfg(feature = "async")]
+pub async fn folder_stats(folder: &Path, profile: &AthleteProfile) -> Result<Vec<ActivityStats>> {
+    let mut parsed_results = successfully_parsed(parse_folder(folder, None).await?);
+    Ok(activity_stats(
+        &mut parsed_results,
+        profile,
+        Some(ElevationOptions::default()),
+    ))
+}
+
+/// Stats for every activity in `folder` combined into one, as [ActivityStats::combined],
+/// for a grand total over e.g. a training block.
+#[cfg(feature = "async")]
+pub async fn folder_combined_stats(
+    folder: &Path,
+    profile: &AthleteProfile,
+) -> Result<ActivityStats> {
+    let stats = folder_stats(folder, profile).await?;
+    ActivityStats::combined(&stats)
+        .ok_or_else(|| anyhow!("No activities found in {}", folder.display()))
+}
+
+/// Blocking equivalent of [folder_stats], available without the `async` feature.
+pub fn folder_stats_sync(folder: &Path, profile: &AthleteProfile) -> Result<Vec<ActivityStats>> {
+    let mut parsed_results = successfully_parsed(parse_folder_sync(folder, None)?);
+    Ok(activity_stats(
+        &mut parsed_results,
+        profile,
+        Some(ElevationOptions::default()),
+    ))
+}
+
+/// Stats of every activity in activity order, with elevation measured using `elevation`.
+/// `None` computes [ActivityStats::from_laps] instead, without walking any trackpoints.
+fn activity_stats(
+    parsed_results: &mut [TrainingCenterDatabase],
+    profile: &AthleteProfile,
+    elevation: Option<ElevationOptions>,
+) -> Vec<ActivityStats> {
+    let activity_stats: Vec<_> = sorted_activities(parsed_results, elevation)
+        .into_iter()
+        .map(|activity| stats_of(activity, profile, elevation.is_some()))
+        .collect();
+
+    info!("Processed {} activities", activity_stats.len());
+    activity_stats
+}
+
+/// [ActivityStats::with_profile] of `activity`, or with `full` off [ActivityStats::from_laps].
+fn stats_of(activity: &Activity, profile: &AthleteProfile, full: bool) -> ActivityStats {
+    debug!(
+        "Data coverage of {}: {}",
+        activity.id,
+        activity.data_coverage()
+    );
+    if full {
+        ActivityStats::with_profile(activity, profile)
+    } else {
+        ActivityStats::from_laps(activity, profile)
+    }
+}
+
+/// Removes activities duplicating one in an earlier file, e.g. the same run saved by both
+/// the watch and a sync tool, and then files left without any activity. Activities are
+/// duplicates when they started within [DUPLICATE_START_TOLERANCE] of each other and their
+/// distances differ by less than [DUPLICATE_DISTANCE_TOLERANCE]. Each activity of a
+/// multisport file is checked on its own, and files that never had an activity are kept.
+pub fn dedupe_activities(activities: Vec<TrainingCenterDatabase>) -> Vec<TrainingCenterDatabase> {
+    let mut kept = vec![];
+    activities
+        .into_iter()
+        .filter_map(|mut tcb| remove_duplicate_activities(&mut tcb, &mut kept).then_some(tcb))
+        .collect()
+}
+
+/// Removes the activities of `tcb` duplicating one of the `kept` keys of earlier files, see
+/// [dedupe_activities], and adds the keys of the rest to `kept`. Whether `tcb` is worth
+/// keeping: it has an activity left, or never had any.
+fn remove_duplicate_activities(
+    tcb: &mut TrainingCenterDatabase,
+    kept: &mut Vec<(DateTime<Utc>, f32)>,
+) -> bool {
+    let Some(activities) = tcb.activities.as_mut() else {
+        return true;
+    };
+    if activities.activities.is_empty() {
+        return true;
+    }
+    let earlier = kept.len();
+    activities.activities.retain(|activity| {
+        let Some(key) = duplicate_key(activity) else {
+            return true;
+        };
+        if kept[..earlier].iter().any(|k| is_duplicate_key(key, *k)) {
+            info!("Skipping duplicate of activity {}", activity.id);
+            return false;
+        }
+        kept.push(key);
+        true
+    });
+    !activities.activities.is_empty()
+}
+
+/// What [dedupe_activities] compares: the start time and total distance. `None` for
+/// activities without a start time, which aren't duplicates of anything.
+fn duplicate_key(activity: &Activity) -> Option<(DateTime<Utc>, f32)> {
+    Some((activity.start_time()?, activity.total_distance_meters()))
+}
+
+fn is_duplicate_key(
+    (start_a, distance_a): (DateTime<Utc>, f32),
+    (start_b, distance_b): (DateTime<Utc>, f32),
+) -> bool {
+    let same_start = (start_a - start_b).abs() <= DUPLICATE_START_TOLERANCE;
+    let same_distance = (distance_a - distance_b).abs()
+        <= distance_a.max(distance_b) * DUPLICATE_DISTANCE_TOLERANCE;
+    same_start && same_distance
+}
+
+/// Which files of a folder [display_folder_stats] reads, how it measures their activities,
+/// and which format it writes their stats in.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FolderOptions {
+    /// Skip activities duplicated across files, see [dedupe_activities].
+    pub dedupe: bool,
+    /// Only read the first N files found, in path order.
+    pub limit: Option<usize>,
+    pub elevation: ElevationOptions,
+    /// Compute stats only from lap totals, see [ActivityStats::from_laps], at the cost of
+    /// elevation, best efforts and relative effort. Computing the stats of 40 one-hour files
+    /// drops from 153ms to 0.2ms, but parsing the XML still takes most of the time.
+    pub lap_only: bool,
+    /// Format of the stats. Only [OutputFormat::Text] is followed by the totals of each sport.
+    pub format: OutputFormat,
+    /// Only include activities of this sport. Without it, a folder of several sports gets
+    /// a chart for each, see [sport_chart_filename].
+    pub sport: Option<Sport>,
+}
+
+/// Writes the stats of every activity in `folder` to `output` and renders the pace vs.
+/// heart rate chart, reading and measuring the activities as set by `options`. Fails
+/// without writing anything if `folder` holds no TCX files.
+#[cfg(all(feature = "charts", feature = "async"))]
 pub async fn display_folder_stats(
     folder: &Path,
     output: &Path,
     chart_filename: String,
+    options: FolderOptions,
+    profile: &AthleteProfile,
+    decimal_separator: DecimalSeparator,
+    units: Units,
 ) -> Result<()> {
-    let mut parsed_results = parse_folder(folder).await?;
+    let activity_stats: Vec<ActivityStats> = streamed_folder_stats(folder, options, profile)
+        .await?
+        .into_iter()
+        .map(|stats| {
+            stats
+                .with_decimal_separator(decimal_separator)
+                .with_units(units)
+        })
+        .collect();
 
-    let mut activities: Vec<_> = parsed_results
-        .iter_mut()
-        .filter_map(|tcb| {
-            let activity = tcb.get_activity_mut(0)?;
-            activity.calc_lap_elevations();
-            // Return an immutable activity after mutating.
-            Some(&*activity)
+    options.format.write(&activity_stats, output)?;
+
+    // Pace per mile of different sports, e.g. running and cycling, doesn't belong on one chart.
+    let mut by_sport: BTreeMap<Sport, Vec<ActivityStats>> = BTreeMap::new();
+    for stats in activity_stats {
+        by_sport.entry(stats.sport()).or_default().push(stats);
+    }
+    if by_sport.len() <= 1 {
+        let activity_stats = by_sport.into_values().next().unwrap_or_default();
+        render_chart(&activity_stats, &chart_filename, ChartOptions::default())?;
+    } else {
+        for (sport, activity_stats) in by_sport {
+            render_chart(
+                &activity_stats,
+                &sport_chart_filename(&chart_filename, sport),
+                ChartOptions::default(),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Stats of the activities in `folder` as [display_folder_stats] reads and measures them,
+/// in activity order. Files are streamed from [parse_folder_stream] and each is dropped
+/// once its stats are taken, giving the same stats as parsing the whole folder with
+/// [parse_folder] first. Fails if `folder` holds no TCX files.
+#[cfg(feature = "async")]
+// Only called by display_folder_stats, which also needs the charts feature.
+#[cfg_attr(not(feature = "charts"), allow(dead_code))]
+async fn streamed_folder_stats(
+    folder: &Path,
+    options: FolderOptions,
+    profile: &AthleteProfile,
+) -> Result<Vec<ActivityStats>> {
+    let elevation = (!options.lap_only).then_some(options.elevation);
+    let mut parsed_files = parse_folder_stream(folder, options.limit, default_parse_concurrency())?;
+    // Each file is dropped once its stats are taken, so only one stats per activity and
+    // the duplicate keys of kept files are held onto.
+    let (mut file_count, mut kept) = (0, vec![]);
+    let mut activity_stats: Vec<ActivityStats> = vec![];
+    while let Some((path, parsed)) = parsed_files.next().await {
+        file_count += 1;
+        let Some(mut tcb) = parsed_or_warn(&path, parsed) else {
+            continue;
+        };
+        if options.dedupe && !remove_duplicate_activities(&mut tcb, &mut kept) {
+            continue;
+        }
+        for activity in tcb.activities_iter_mut() {
+            if options.sport.is_some_and(|sport| activity.sport != sport) {
+                continue;
+            }
+            if let Some(elevation) = elevation {
+                activity.calc_lap_elevations_with(elevation);
+            }
+            activity_stats.push(stats_of(activity, profile, elevation.is_some()));
+        }
+    }
+    if file_count == 0 {
+        return Err(anyhow!("no TCX files found in {}", folder.display()));
+    }
+    activity_stats.sort_by(|a, b| a.order_key().cmp(&b.order_key()));
+    info!("Processed {} activities", activity_stats.len());
+    Ok(activity_stats)
+}
+
+/// How many files [display_folder_stats] parses at once: parsing is CPU-bound, so one per
+/// core.
+#[cfg(feature = "async")]
+#[cfg_attr(not(feature = "charts"), allow(dead_code))]
+fn default_parse_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(1, |cores| cores.get())
+}
+
+/// The file the [display_folder_stats] chart of `sport`'s activities is written to when
+/// a folder holds several sports: `chart_filename` with the sport added to its name, e.g.
+/// `output-bitmap-running.png`.
+pub fn sport_chart_filename(chart_filename: &str, sport: Sport) -> String {
+    let path = Path::new(chart_filename);
+    let stem = path.file_stem().map_or_else(
+        || chart_filename.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let name = match path.extension() {
+        Some(extension) => format!(
+            "{}-{}.{}",
+            stem,
+            sport.to_string().to_lowercase(),
+            extension.to_string_lossy()
+        ),
+        None => format!("{}-{}", stem, sport.to_string().to_lowercase()),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Formats the stats of a folder's activities can be written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    /// File extension used for outputs in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+
+    /// Writes `activity_stats` to `output` in this format. Text is followed by the totals
+    /// of each sport and the weekly and overall [SummaryStats].
+    pub fn write(&self, activity_stats: &[ActivityStats], output: &Path) -> Result<()> {
+        let mut output_file = File::create(output)?;
+        match self {
+            OutputFormat::Text => {
+                for activity_stat in activity_stats.iter() {
+                    activity_stat.write_to(&mut output_file)?;
+                }
+                for (sport, totals) in summary_by_sport(activity_stats) {
+                    totals.write_to(&format!("{} totals", sport), &mut output_file)?;
+                }
+                SummaryStats::new(activity_stats).write_to(&mut output_file)?;
+            }
+            OutputFormat::Json => serde_json::to_writer_pretty(output_file, activity_stats)?,
+            OutputFormat::Csv => {
+                ActivityStats::write_csv_header(&mut output_file)?;
+                for activity_stat in activity_stats.iter() {
+                    activity_stat.write_csv_row(&mut output_file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the stats of every activity in `folder` in each of `formats`, parsing the folder
+/// only once. With a single format the stats are written to `output`; with several, each
+/// is written next to it with the format's extension, e.g. `output.txt` and `output.json`.
+#[cfg(feature = "async")]
+pub async fn write_folder_summaries(
+    folder: &Path,
+    output: &Path,
+    formats: &[OutputFormat],
+    profile: &AthleteProfile,
+    decimal_separator: DecimalSeparator,
+    units: Units,
+) -> Result<()> {
+    let activity_stats: Vec<ActivityStats> = folder_stats(folder, profile)
+        .await?
+        .into_iter()
+        .map(|stats| {
+            stats
+                .with_decimal_separator(decimal_separator)
+                .with_units(units)
         })
         .collect();
+    for format in formats {
+        if formats.len() == 1 {
+            format.write(&activity_stats, output)?;
+        } else {
+            format.write(&activity_stats, &output.with_extension(format.extension()))?;
+        }
+    }
+    Ok(())
+}
 
-    activities.sort_by(|a1, a2| a1.id.cmp(&a2.id));
+/// Writes the stats of every activity in `folder` to `output` as a JSON array.
+#[cfg(feature = "async")]
+pub async fn write_folder_json(
+    folder: &Path,
+    output: &Path,
+    profile: &AthleteProfile,
+) -> Result<()> {
+    write_folder_summaries(
+        folder,
+        output,
+        &[OutputFormat::Json],
+        profile,
+        DecimalSeparator::default(),
+        Units::default(),
+    )
+    .await
+}
+
+/// Writes the stats of every activity in `folder` to `output` as a versioned
+/// [report::Report], for storing computed metrics long-term.
+#[cfg(feature = "async")]
+pub async fn write_folder_report(
+    folder: &Path,
+    output: &Path,
+    profile: &AthleteProfile,
+) -> Result<()> {
+    report::write_report(output, folder_stats(folder, profile).await?)
+}
+
+/// Writes the stats of every activity in `folder` to `output` as CSV, one row per activity.
+#[cfg(feature = "async")]
+pub async fn write_folder_csv(
+    folder: &Path,
+    output: &Path,
+    profile: &AthleteProfile,
+    decimal_separator: DecimalSeparator,
+) -> Result<()> {
+    write_folder_summaries(
+        folder,
+        output,
+        &[OutputFormat::Csv],
+        profile,
+        decimal_separator,
+        Units::default(),
+    )
+    .await
+}
+
+/// Reads the stats written by [write_folder_csv] back from `path`, e.g. to chart an
+/// archived summary without the original TCX files. See [ActivityStats::read_csv] for
+/// which stats are kept.
+pub fn read_folder_csv(path: &Path) -> Result<Vec<ActivityStats>> {
+    let file = File::open(path).map_err(|e| anyhow!("Unable to read {}: {}", path.display(), e))?;
+    ActivityStats::read_csv(BufReader::new(file))
+}
+
+/// Writes one CSV row per lap of every activity in `folder` to `output`.
+#[cfg(feature = "async")]
+pub async fn write_folder_laps_csv(folder: &Path, output: &Path) -> Result<()> {
+    let mut parsed_results = successfully_parsed(parse_folder(folder, None).await?);
+    let activities = sorted_activities(&mut parsed_results, Some(ElevationOptions::default()));
 
-    let mut activity_stats = vec![];
     let mut output_file = File::create(output)?;
+    LapStats::write_csv_header(&mut output_file)?;
     for activity in activities {
-        let activity_stat = ActivityStats::from(activity);
-        activity_stat.write_to(&mut output_file)?;
-        activity_stats.push(activity_stat);
+        for lap_stats in LapStats::for_activity(activity) {
+            lap_stats.write_csv_row(&mut output_file)?;
+        }
     }
+    Ok(())
+}
 
-    info!("Processed {} activities", activity_stats.len());
-    chart(chart_filename, activity_stats)?;
-
+/// Writes the GPS tracks of every activity in `folder` to `output` as a single GPX file.
+#[cfg(feature = "async")]
+pub async fn write_folder_gpx(folder: &Path, output: &Path) -> Result<()> {
+    let mut parsed_results = successfully_parsed(parse_folder(folder, None).await?);
+    let activities = sorted_activities(&mut parsed_results, Some(ElevationOptions::default()));
+    fs::write(output, gpx_document(&activities))?;
     Ok(())
 }
 
-fn chart(chart_filename: String, activity_stats: Vec<ActivityStats>) -> Result<()> {
-    let x_range = 0usize..activity_stats.len();
+/// Only renders the pace vs. heart rate chart for the activities in `folder`.
+/// No chart is written, only a warning logged, when none of them have pace or HR to plot.
+#[cfg(all(feature = "charts", feature = "async"))]
+pub async fn chart_folder(
+    folder: &Path,
+    chart_filename: String,
+    options: ChartOptions,
+) -> Result<()> {
+    let activity_stats = folder_stats(folder, &AthleteProfile::default()).await?;
+    render_chart(&activity_stats, &chart_filename, options)
+}
 
-    let pace = activity_stats
-        .iter()
-        .enumerate()
-        .map(|(i, stats)| (i, stats.average_pace_seconds.as_secs()))
-        .collect::<Vec<(usize, u64)>>();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{one_lap_tcx, temp_path};
+
+    /// A one-lap activity of `sport` starting at `start`, climbing 10 m over `meters`.
+    fn activity(sport: &str, start: &str, meters: f64) -> String {
+        let track = format!(
+            "<Track>\
+             <Trackpoint><Time>{start}</Time><DistanceMeters>0</DistanceMeters><AltitudeMeters>100</AltitudeMeters>\
+             <HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>\
+             <Trackpoint><Time>{start}</Time><DistanceMeters>{meters}</DistanceMeters><AltitudeMeters>110</AltitudeMeters>\
+             <HeartRateBpm><Value>160</Value></HeartRateBpm></Trackpoint>\
+             </Track>"
+        );
+        one_lap_tcx(sport, start, meters, &track)
+    }
 
-    let hr = activity_stats
+    /// A fresh folder under the system temp dir holding `files`, by path within it.
+    fn folder(name: &str, files: &[(&str, String)]) -> PathBuf {
+        let folder = temp_path(name);
+        let _ = fs::remove_dir_all(&folder);
+        for (path, data) in files {
+            let path = folder.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+        folder
+    }
+
+    /// Files out of chronological order, a duplicate of the earliest run, another sport
+    /// in a subfolder and one that doesn't parse.
+    fn mixed_folder(name: &str) -> PathBuf {
+        folder(
+            name,
+            &[
+                ("a.tcx", activity("Running", "2024-03-02T12:00:00Z", 5000.0)),
+                ("b.tcx", activity("Running", "2024-03-01T12:00:00Z", 3000.0)),
+                ("c.tcx", activity("Running", "2024-03-01T12:00:10Z", 3000.0)),
+                (
+                    "rides/d.tcx",
+                    activity("Biking", "2024-02-28T12:00:00Z", 20000.0),
+                ),
+                ("e.tcx", String::from("<TrainingCenterDatabase>")),
+            ],
+        )
+    }
+
+    #[test]
+    fn parse_folder_sync_returns_every_file_in_path_order() {
+        let folder = mixed_folder("sync");
+        let parsed = parse_folder_sync(&folder, None).unwrap();
+        let paths: Vec<_> = parsed
+            .iter()
+            .map(|(path, parsed)| (path.strip_prefix(&folder).unwrap(), parsed.is_ok()))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                (Path::new("a.tcx"), true),
+                (Path::new("b.tcx"), true),
+                (Path::new("c.tcx"), true),
+                (Path::new("e.tcx"), false),
+                (Path::new("rides/d.tcx"), true),
+            ]
+        );
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parse_folder_parallel_matches_parse_folder_sync() {
+        let folder = mixed_folder("parallel");
+        let parallel = parse_folder_parallel(&folder).unwrap();
+        let sequential = parse_folder_sync(&folder, None).unwrap();
+        assert_eq!(parallel.len(), sequential.len());
+        for ((parallel_path, parallel), (sequential_path, sequential)) in
+            parallel.iter().zip(&sequential)
+        {
+            assert_eq!(parallel_path, sequential_path);
+            assert_eq!(parallel.as_ref().ok(), sequential.as_ref().ok());
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn streamed_folder_stats_match_parse_folder() {
+        let folder = mixed_folder("streamed");
+        let profile = AthleteProfile::default();
+        for (dedupe, lap_only) in [(false, false), (true, false), (true, true)] {
+            let options = FolderOptions {
+                dedupe,
+                lap_only,
+                ..FolderOptions::default()
+            };
+            let streamed = streamed_folder_stats(&folder, options, &profile)
+                .await
+                .unwrap();
+
+            let mut parsed = successfully_parsed(parse_folder(&folder, None).await.unwrap());
+            if dedupe {
+                parsed = dedupe_activities(parsed);
+            }
+            let elevation = (!lap_only).then_some(options.elevation);
+            let eager = activity_stats(&mut parsed, &profile, elevation);
+
+            assert_eq!(
+                serde_json::to_string(&streamed).unwrap(),
+                serde_json::to_string(&eager).unwrap()
+            );
+            assert_eq!(summary_by_sport(&streamed), summary_by_sport(&eager));
+            assert_eq!(streamed.len(), if dedupe { 3 } else { 4 });
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn streamed_folder_stats_are_in_activity_order() {
+        let folder = mixed_folder("streamed-order");
+        let options = FolderOptions {
+            dedupe: true,
+            ..FolderOptions::default()
+        };
+        let streamed = streamed_folder_stats(&folder, options, &AthleteProfile::default())
+            .await
+            .unwrap();
+        let starts: Vec<String> = streamed
+            .iter()
+            .map(|stats| stats.start_time.unwrap().to_rfc3339())
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                "2024-02-28T12:00:00+00:00",
+                "2024-03-01T12:00:00+00:00",
+                "2024-03-02T12:00:00+00:00"
+            ]
+        );
+        assert_eq!(streamed[0].elevation_gain, 33);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn streamed_folder_stats_fail_without_any_files() {
+        let folder = folder("streamed-empty", &[("notes.txt", String::new())]);
+        let result = streamed_folder_stats(
+            &folder,
+            FolderOptions::default(),
+            &AthleteProfile::default(),
+        )
+        .await;
+        assert!(result.is_err());
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn folder_stats_sync_skips_malformed_files() {
+        let folder = mixed_folder("malformed");
+        let parsed = parse_folder_sync(&folder, None).unwrap();
+        assert_eq!(parsed.len(), 5);
+        assert_eq!(successfully_parsed(parsed).len(), 4);
+
+        let stats = folder_stats_sync(&folder, &AthleteProfile::default()).unwrap();
+        assert_eq!(stats.len(), 4);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn parse_folder_sync_fails_for_a_missing_folder() {
+        let folder = temp_path("missing");
+        assert!(parse_folder_sync(&folder, None).is_err());
+    }
+
+    #[cfg(feature = "archives")]
+    fn gzip(data: &str) -> Vec<u8> {
+        use flate2::{write::GzEncoder, Compression};
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "archives")]
+    #[test]
+    fn gzipped_files_are_detected_by_magic_bytes() {
+        let run = activity("Running", "2024-03-01T12:00:00Z", 5000.0);
+        let folder = temp_path("gzip");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        // Gzipped without being renamed, and gzipped as usual.
+        fs::write(folder.join("a.tcx"), gzip(&run)).unwrap();
+        fs::write(folder.join("b.tcx.gz"), gzip(&run)).unwrap();
+
+        let parsed = successfully_parsed(parse_folder_sync(&folder, None).unwrap());
+        assert_eq!(parsed.len(), 2);
+        assert!(parsed.iter().all(|tcb| *tcb == parse_str(&run).unwrap()));
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn decode_tcx_leaves_plain_text_alone() {
+        let run = activity("Running", "2024-03-01T12:00:00Z", 5000.0);
+        let decoded = decode_tcx(Path::new("run.tcx"), run.clone().into_bytes()).unwrap();
+        assert_eq!(decoded, run);
+    }
+
+    #[cfg(not(feature = "archives"))]
+    #[test]
+    fn gzipped_files_need_the_archives_feature() {
+        let error = decode_tcx(Path::new("run.tcx"), vec![0x1f, 0x8b, 0x08, 0x00]).unwrap_err();
+        assert!(error.to_string().contains("archives"), "{}", error);
+    }
+
+    #[test]
+    fn every_activity_of_a_file_is_processed() {
+        let brick = r#"<TrainingCenterDatabase><Activities>
+<Activity Sport="Biking"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3600</TotalTimeSeconds><DistanceMeters>30000</DistanceMeters></Lap></Activity>
+<Activity Sport="Running"><Id> </Id>
+<Lap StartTime="2024-03-01T13:05:00Z"><TotalTimeSeconds>1200</TotalTimeSeconds><DistanceMeters>4000</DistanceMeters></Lap>
+<Lap StartTime="2024-03-01T13:02:00Z"><TotalTimeSeconds>180</TotalTimeSeconds><DistanceMeters>500</DistanceMeters></Lap></Activity>
+</Activities></TrainingCenterDatabase>"#;
+        let tcb = parse_str(brick).unwrap();
+        let ids: Vec<&str> = tcb.activities_iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["2024-03-01T12:00:00Z", "2024-03-01T13:02:00Z"]);
+
+        let folder = folder("brick", &[("brick.tcx", brick.to_string())]);
+        let stats = folder_stats_sync(&folder, &AthleteProfile::default()).unwrap();
+        let sports: Vec<Sport> = stats.iter().map(|s| s.sport()).collect();
+        assert_eq!(sports, [Sport::Biking, Sport::Running]);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn dedupe_activities_matches_start_and_distance() {
+        let parsed =
+            |start: &str, meters: f64| parse_str(&activity("Running", start, meters)).unwrap();
+        let kept = dedupe_activities(vec![
+            parsed("2024-03-01T12:00:00Z", 5000.0),
+            // 30s later and 0.5% longer: the same run from another device.
+            parsed("2024-03-01T12:00:30Z", 5025.0),
+            // Started at the same time but 10% longer.
+            parsed("2024-03-01T12:00:00Z", 5500.0),
+            // Same distance, two minutes later.
+            parsed("2024-03-01T12:02:00Z", 5000.0),
+        ]);
+        let kept: Vec<(&str, f32)> = kept
+            .iter()
+            .map(|tcb| {
+                let activity = tcb.get_activity(0).unwrap();
+                (activity.id.as_str(), activity.total_distance_meters())
+            })
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("2024-03-01T12:00:00Z", 5000.0),
+                ("2024-03-01T12:00:00Z", 5500.0),
+                ("2024-03-01T12:02:00Z", 5000.0)
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn read_folder_csv_reads_back_write_folder_csv() {
+        let folder = mixed_folder("csv");
+        let csv = folder.join("stats.csv");
+        let profile = AthleteProfile::default();
+        write_folder_csv(&folder, &csv, &profile, DecimalSeparator::Period)
+            .await
+            .unwrap();
+
+        let lines = |stats: Vec<ActivityStats>| -> Vec<String> {
+            stats.iter().map(ActivityStats::summary_line).collect()
+        };
+        let written = folder_stats(&folder, &profile).await.unwrap();
+        assert_eq!(written.len(), 4);
+        assert_eq!(lines(read_folder_csv(&csv).unwrap()), lines(written));
+    }
+
+    #[test]
+    fn activities_are_sorted_by_start_time_whatever_the_id_format() {
+        let mut parsed: Vec<TrainingCenterDatabase> = [
+            "2024-03-01T12:00:00-01:00",
+            "2024-03-01T12:30:00Z",
+            "2024-03-01T14:00:00+02:00",
+        ]
         .iter()
-        .enumerate()
-        .map(|(i, stats)| (i, stats.average_hr))
-        .collect::<Vec<(usize, usize)>>();
-
-    let root = BitMapBackend::new(chart_filename.as_str(), (1024, 768)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let mut chart = ChartBuilder::on(&root)
-        .x_label_area_size(35)
-        .y_label_area_size(40)
-        .right_y_label_area_size(40)
-        .margin(5)
-        .caption(
-            "Avg pace vs. Avg heart rate",
-            ("sans-serif", 50.0).into_font(),
+        .map(|start| parse_str(&activity("Running", start, 1000.0)).unwrap())
+        .collect();
+        // Named rather than timestamped, so dated by its lap at 11:00.
+        let named = activity("Running", "2024-03-01T11:00:00Z", 1000.0)
+            .replace("<Id>2024-03-01T11:00:00Z</Id>", "<Id>Morning Run</Id>");
+        parsed.push(parse_str(&named).unwrap());
+
+        let ids: Vec<&str> = sorted_activities(&mut parsed, None)
+            .iter()
+            .map(|activity| activity.id.as_str())
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "Morning Run",
+                "2024-03-01T14:00:00+02:00",
+                "2024-03-01T12:30:00Z",
+                "2024-03-01T12:00:00-01:00"
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn sport_filter_keeps_only_that_sport() {
+        let folder = mixed_folder("sport-filter");
+        let profile = AthleteProfile::default();
+        let sports =
+            |stats: Vec<ActivityStats>| -> Vec<Sport> { stats.iter().map(|s| s.sport()).collect() };
+        let options = |sport| FolderOptions {
+            sport,
+            ..FolderOptions::default()
+        };
+        let all = streamed_folder_stats(&folder, options(None), &profile)
+            .await
+            .unwrap();
+        assert_eq!(
+            sports(all),
+            [
+                Sport::Biking,
+                Sport::Running,
+                Sport::Running,
+                Sport::Running
+            ]
+        );
+        let rides = streamed_folder_stats(&folder, options(Some(Sport::Biking)), &profile)
+            .await
+            .unwrap();
+        assert_eq!(sports(rides), [Sport::Biking]);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn sport_chart_filename_adds_the_sport_before_the_extension() {
+        assert_eq!(
+            sport_chart_filename("charts/output.png", Sport::Running),
+            Path::new("charts/output-running.png").to_string_lossy()
+        );
+        assert_eq!(
+            sport_chart_filename("output", Sport::Biking),
+            "output-biking"
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn write_folder_summaries_writes_each_format() {
+        let folder = mixed_folder("summaries");
+        let output = temp_path("summaries.out");
+        let formats = [OutputFormat::Text, OutputFormat::Json, OutputFormat::Csv];
+        write_folder_summaries(
+            &folder,
+            &output,
+            &formats,
+            &AthleteProfile::default(),
+            DecimalSeparator::default(),
+            Units::default(),
         )
-        .build_cartesian_2d(x_range.clone(), 420u64..550u64)?
-        .set_secondary_coord(x_range, 115usize..180usize);
-
-    chart
-        .configure_mesh()
-        .disable_x_mesh()
-        .disable_y_mesh()
-        .x_desc("Activity number")
-        .y_desc("Pace (seconds per mile)")
-        .draw()?;
-
-    chart
-        .configure_secondary_axes()
-        .y_desc("Heart rate")
-        .draw()?;
-
-    chart
-        .draw_series(LineSeries::new(pace, &BLUE))?
-        .label("Seconds per mile")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
-
-    chart
-        .draw_secondary_series(LineSeries::new(hr, &RED))?
-        .label("Heart rate")
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
-
-    chart
-        .configure_series_labels()
-        .position(SeriesLabelPosition::UpperRight)
-        .background_style(RGBColor(128, 128, 128))
-        .draw()?;
-
-    root.present().expect("Unable to write result to file");
-    info!("Chart has been saved to {}", chart_filename);
+        .await
+        .unwrap();
 
-    Ok(())
+        let text = fs::read_to_string(output.with_extension("txt")).unwrap();
+        assert_eq!(text.matches("=== 2024-").count(), 4);
+        assert!(text.contains("Running totals"), "{text}");
+        assert!(text.contains("Biking totals"), "{text}");
+        let json = fs::read_to_string(output.with_extension("json")).unwrap();
+        let json: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(json.len(), 4);
+        let csv = fs::read(output.with_extension("csv")).unwrap();
+        assert_eq!(ActivityStats::read_csv(csv.as_slice()).unwrap().len(), 4);
+        assert!(!output.exists());
+
+        #[cfg(feature = "charts")]
+        {
+            let folder_output = temp_path("summaries-folder.txt");
+            let chart = temp_path("summaries-chart.svg");
+            display_folder_stats(
+                &folder,
+                &folder_output,
+                chart.to_string_lossy().into_owned(),
+                FolderOptions::default(),
+                &AthleteProfile::default(),
+                DecimalSeparator::default(),
+                Units::default(),
+            )
+            .await
+            .unwrap();
+            assert_eq!(fs::read_to_string(&folder_output).unwrap(), text);
+            fs::remove_file(folder_output).unwrap();
+            for sport in [Sport::Biking, Sport::Running] {
+                let chart = sport_chart_filename(&chart.to_string_lossy(), sport);
+                fs::remove_file(chart).unwrap();
+            }
+        }
+        for format in formats {
+            fs::remove_file(output.with_extension(format.extension())).unwrap();
+        }
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    /// A run, a brick file repeating it before a ride, and the ride on its own.
+    fn brick_files() -> Vec<(&'static str, String)> {
+        let legs = |start: &str, meters: f64, sport: &str| {
+            let tcx = activity(sport, start, meters);
+            let from = tcx.find("<Activity ").unwrap();
+            let to = tcx.find("</Activities>").unwrap();
+            tcx[from..to].to_string()
+        };
+        let brick = format!(
+            "<TrainingCenterDatabase><Activities>{}{}</Activities></TrainingCenterDatabase>",
+            legs("2024-03-01T12:00:00Z", 5000.0, "Running"),
+            legs("2024-03-01T13:00:00Z", 20000.0, "Biking")
+        );
+        vec![
+            ("a.tcx", activity("Running", "2024-03-01T12:00:00Z", 5000.0)),
+            ("b.tcx", brick),
+            ("c.tcx", activity("Biking", "2024-03-01T13:00:10Z", 20000.0)),
+        ]
+    }
+
+    #[test]
+    fn dedupe_activities_checks_each_activity_of_a_file() {
+        let parsed = brick_files()
+            .iter()
+            .map(|(_, tcx)| parse_str(tcx).unwrap())
+            .collect();
+        let kept = dedupe_activities(parsed);
+        let ids: Vec<Vec<(&str, Sport)>> = kept
+            .iter()
+            .map(|tcb| {
+                tcb.activities_iter()
+                    .map(|a| (a.id.as_str(), a.sport))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                vec![("2024-03-01T12:00:00Z", Sport::Running)],
+                vec![("2024-03-01T13:00:00Z", Sport::Biking)]
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn streamed_folder_stats_dedupe_each_activity() {
+        let files = brick_files();
+        let folder = folder("dedupe-brick", &files);
+        let options = FolderOptions {
+            dedupe: true,
+            ..FolderOptions::default()
+        };
+        let stats = streamed_folder_stats(&folder, options, &AthleteProfile::default())
+            .await
+            .unwrap();
+        let sports: Vec<Sport> = stats.iter().map(|s| s.sport()).collect();
+        assert_eq!(sports, [Sport::Running, Sport::Biking]);
+        fs::remove_dir_all(folder).unwrap();
+    }
 }
@@ -0,0 +1,104 @@
+use chrono::{Days, NaiveDate};
+use serde::Serialize;
+
+use crate::stats::ActivityStats;
+
+/// Time constants (in days) of the fitness and fatigue exponentially weighted averages.
+static CTL_DAYS: f64 = 42.0;
+static ATL_DAYS: f64 = 7.0;
+
+/// One day of the Performance Management Chart model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DailyLoad {
+    pub date: NaiveDate,
+    /// Total training stress of the activities on this day.
+    pub stress: f64,
+    /// Chronic training load ("fitness"), the 42 day average of stress.
+    pub ctl: f64,
+    /// Acute training load ("fatigue"), the 7 day average of stress.
+    pub atl: f64,
+    /// Training stress balance ("form"), `ctl - atl`.
+    pub tsb: f64,
+}
+
+/// Fitness/fatigue for every day from the first to the last dated activity, using each
+/// activity's relative effort as its stress score. Rest days contribute zero stress, and
+/// activities whose date can't be determined are ignored.
+pub fn training_load_series(stats: &[ActivityStats]) -> Vec<DailyLoad> {
+    let dated: Vec<(NaiveDate, f64)> = stats
+        .iter()
+        .filter_map(|s| Some((s.start_date()?, s.relative_effort)))
+        .collect();
+    let (Some(first), Some(last)) = (
+        dated.iter().map(|(date, _)| *date).min(),
+        dated.iter().map(|(date, _)| *date).max(),
+    ) else {
+        return vec![];
+    };
+
+    let mut series = vec![];
+    let (mut ctl, mut atl) = (0.0, 0.0);
+    let mut date = first;
+    while date <= last {
+        let stress: f64 = dated
+            .iter()
+            .filter(|(d, _)| *d == date)
+            .map(|(_, stress)| stress)
+            .sum();
+        ctl += (stress - ctl) / CTL_DAYS;
+        atl += (stress - atl) / ATL_DAYS;
+        series.push(DailyLoad {
+            date,
+            stress,
+            ctl,
+            atl,
+            tsb: ctl - atl,
+        });
+        date = date + Days::new(1);
+    }
+    series
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::run_stats;
+
+    fn effort(start: &str, relative_effort: f64) -> ActivityStats {
+        let mut stats = run_stats(start);
+        stats.relative_effort = relative_effort;
+        stats
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn training_load_series_averages_daily_stress() {
+        let series = training_load_series(&[
+            effort("2024-03-03T08:00:00Z", 84.0),
+            effort("2024-03-01T08:00:00Z", 21.0),
+            effort("2024-03-01T18:00:00Z", 21.0),
+        ]);
+        let stress: Vec<f64> = series.iter().map(|day| day.stress).collect();
+        assert_eq!(stress, [42.0, 0.0, 84.0]);
+        assert_eq!(series[0].date, NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+
+        // Day 1: 42/42 and 42/7; day 2 decays both; day 3 adds 84.
+        assert_close(series[0].ctl, 1.0);
+        assert_close(series[0].atl, 6.0);
+        assert_close(series[1].ctl, 41.0 / 42.0);
+        assert_close(series[1].atl, 36.0 / 7.0);
+        let ctl = 41.0 / 42.0 + (84.0 - 41.0 / 42.0) / 42.0;
+        let atl = 36.0 / 7.0 + (84.0 - 36.0 / 7.0) / 7.0;
+        assert_close(series[2].ctl, ctl);
+        assert_close(series[2].atl, atl);
+        assert_close(series[2].tsb, ctl - atl);
+    }
+
+    #[test]
+    fn training_load_series_is_empty_without_activities() {
+        assert!(training_load_series(&[]).is_empty());
+    }
+}
@@ -1,40 +1,403 @@
-use std::{path::Path, time::SystemTime};
-use tracing::info;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tracing::{debug, info, Level};
 
-use clap::Parser;
-use tcxrs::display_folder_stats;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use tcxrs::{
+    chart::{ChartOptions, ChartXAxis},
+    chart_folder, display_folder_stats, folder_stats, parse_str,
+    profile::AthleteProfile,
+    stats::{ActivityStats, DecimalSeparator},
+    units::Units,
+    write_folder_csv, write_folder_gpx, write_folder_json, write_folder_laps_csv,
+    write_folder_report, write_folder_summaries, ElevationOptions, FolderOptions, OutputFormat,
+    Sport,
+};
 
+/// Running with only a directory (and no subcommand) writes the text summary and chart
+/// together, as earlier versions did.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true
+)]
 struct Args {
-    /// Name of the directory to parse tcx files within
-    directory: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Name of the directory (or zip archive, e.g. a Strava export) to parse tcx files
+    /// within, or `-` to read a single TCX document from stdin and print its summary
+    directory: Option<PathBuf>,
+
+    /// Read a single TCX document from stdin and print its summary
+    #[arg(long)]
+    stdin: bool,
 
     /// Name of the file to print output data into.
     #[arg(short, long, default_value = "output.txt")]
-    output_file: String,
+    output_file: PathBuf,
 
-    /// Name of the file to write the chart to
+    /// Name of the file to write the chart to, an SVG when it ends in `.svg`
     #[arg(short, long, default_value = "output-bitmap.png")]
     chart: String,
+
+    /// Skip activities duplicated across files, e.g. saved by both the watch and a sync
+    #[arg(long)]
+    dedupe: bool,
+
+    /// Only process the first N files found, in path order
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Smallest altitude change in meters counted towards elevation gain and loss. Raise it
+    /// for noisy GPS altitude that reports gain on flat routes
+    #[arg(long, default_value_t = ElevationOptions::default().threshold_meters)]
+    elevation_threshold: f64,
+
+    /// Number of altitude samples averaged together before measuring elevation gain and
+    /// loss. Larger windows smooth out jitter and report less gain; 1 disables smoothing
+    #[arg(long, default_value_t = ElevationOptions::default().smoothing_window)]
+    elevation_smoothing: usize,
+
+    /// Compute stats from lap totals only, without elevation, best efforts or relative
+    /// effort. Skips most of the stats work, though parsing still takes most of the time
+    #[arg(long)]
+    lap_only: bool,
+
+    /// Format to write the stats in: text (followed by totals for each sport), JSON, or
+    /// CSV with one row per activity
+    #[arg(short, long, value_enum, default_value = "text")]
+    format: SummaryFormat,
+
+    /// Only include activities of this sport, e.g. Running. Without it, each sport in the
+    /// folder gets its own chart
+    #[arg(long, value_parser = parse_sport)]
+    sport: Option<Sport>,
+
+    /// TOML or JSON file with the athlete's max HR, resting HR, weight, HR zones and FTP
+    #[arg(long, global = true)]
+    profile: Option<PathBuf>,
+
+    /// Write decimals in text and CSV output with a comma, e.g. 6,21mi
+    #[arg(long, global = true)]
+    decimal_comma: bool,
+
+    /// Write distances, paces and elevations in text output in kilometers and meters
+    #[arg(long, global = true)]
+    metric: bool,
+
+    /// Log debugging details, such as which data each activity recorded
+    #[arg(short, long, global = true)]
+    verbose: bool,
+}
+
+impl Args {
+    /// How elevation is measured, from `--elevation-threshold` and `--elevation-smoothing`,
+    /// for folders and stdin alike.
+    fn elevation_options(&self) -> ElevationOptions {
+        ElevationOptions {
+            threshold_meters: self.elevation_threshold,
+            smoothing_window: self.elevation_smoothing,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Write the stats of every activity as text or JSON
+    Summary {
+        /// Name of the directory (or zip archive, e.g. a Strava export) to parse tcx files within
+        directory: PathBuf,
+
+        /// Name of the file to print output data into.
+        #[arg(short, long, default_value = "output.txt")]
+        output_file: PathBuf,
+
+        /// Formats of the summary, comma separated. With more than one, each is written
+        /// to the output file name with the format's extension, e.g. output.txt and output.json
+        #[arg(short, long, value_enum, value_delimiter = ',', default_value = "text")]
+        format: Vec<SummaryFormat>,
+
+        /// Print one line per activity to stdout instead of writing the output file
+        #[arg(long)]
+        oneline: bool,
+    },
+    /// Only render the pace vs. heart rate chart
+    Chart {
+        /// Name of the directory (or zip archive, e.g. a Strava export) to parse tcx files within
+        directory: PathBuf,
+
+        /// Name of the file to write the chart to, an SVG when it ends in `.svg`
+        #[arg(short, long, default_value = "output-bitmap.png")]
+        chart: String,
+
+        /// Space activities by date and label the X axis with dates
+        #[arg(long)]
+        dates: bool,
+
+        /// Add a footer with the number of activities, total distance and date range
+        #[arg(long)]
+        totals: bool,
+    },
+    /// Export activities in a machine-readable format
+    Export {
+        /// Name of the directory (or zip archive, e.g. a Strava export) to parse tcx files within
+        directory: PathBuf,
+
+        /// Name of the file to export into
+        #[arg(short, long)]
+        output_file: PathBuf,
+
+        /// Format to export: stats as CSV or JSON, per-lap stats as CSV, GPS tracks as GPX, or
+        /// stats as a JSON report stamped with its format version
+        #[arg(short, long, value_enum)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum SummaryFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+impl From<SummaryFormat> for OutputFormat {
+    fn from(format: SummaryFormat) -> OutputFormat {
+        match format {
+            SummaryFormat::Text => OutputFormat::Text,
+            SummaryFormat::Json => OutputFormat::Json,
+            SummaryFormat::Csv => OutputFormat::Csv,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ExportFormat {
+    Csv,
+    LapCsv,
+    Gpx,
+    Json,
+    Report,
+}
+
+/// A sport named on the command line, in any case, e.g. `running` for [Sport::Running].
+fn parse_sport(name: &str) -> Result<Sport, String> {
+    [
+        Sport::Running,
+        Sport::Biking,
+        Sport::Swimming,
+        Sport::Walking,
+        Sport::Other,
+    ]
+    .into_iter()
+    .find(|sport| sport.to_string().eq_ignore_ascii_case(name))
+    .ok_or_else(|| {
+        format!(
+            "unknown sport {:?}, expected Running, Biking, Swimming, Walking or Other",
+            name
+        )
+    })
+}
+
+async fn run(args: Args) -> Result<()> {
+    let profile = match &args.profile {
+        Some(path) => AthleteProfile::load(path)?,
+        None => AthleteProfile::default(),
+    };
+    let decimal_separator = if args.decimal_comma {
+        DecimalSeparator::Comma
+    } else {
+        DecimalSeparator::Period
+    };
+    let units = if args.metric {
+        Units::Metric
+    } else {
+        Units::Imperial
+    };
+    let elevation = args.elevation_options();
+    match args.command {
+        Some(Command::Summary {
+            directory,
+            oneline: true,
+            ..
+        }) => {
+            for stats in folder_stats(&directory, &profile).await? {
+                println!(
+                    "{}",
+                    stats
+                        .with_decimal_separator(decimal_separator)
+                        .with_units(units)
+                        .summary_line()
+                );
+            }
+            Ok(())
+        }
+        Some(Command::Summary {
+            directory,
+            output_file,
+            format,
+            ..
+        }) => {
+            let formats: Vec<OutputFormat> = format.into_iter().map(OutputFormat::from).collect();
+            write_folder_summaries(
+                &directory,
+                &output_file,
+                &formats,
+                &profile,
+                decimal_separator,
+                units,
+            )
+            .await
+        }
+        Some(Command::Chart {
+            directory,
+            chart,
+            dates,
+            totals,
+        }) => {
+            let x_axis = if dates {
+                ChartXAxis::Date
+            } else {
+                ChartXAxis::Index
+            };
+            let options = ChartOptions {
+                x_axis,
+                totals_footer: totals,
+            };
+            chart_folder(&directory, chart, options).await
+        }
+        Some(Command::Export {
+            directory,
+            output_file,
+            format,
+        }) => match format {
+            ExportFormat::Csv => {
+                write_folder_csv(&directory, &output_file, &profile, decimal_separator).await
+            }
+            ExportFormat::LapCsv => write_folder_laps_csv(&directory, &output_file).await,
+            ExportFormat::Gpx => write_folder_gpx(&directory, &output_file).await,
+            ExportFormat::Json => write_folder_json(&directory, &output_file, &profile).await,
+            ExportFormat::Report => write_folder_report(&directory, &output_file, &profile).await,
+        },
+        None if args.stdin || args.directory.as_deref() == Some(Path::new("-")) => {
+            let mut tcb = parse_str(&io::read_to_string(io::stdin())?)?;
+            for activity in tcb.activities_iter_mut() {
+                activity.calc_lap_elevations_with(elevation);
+                debug!(
+                    "Data coverage of {}: {}",
+                    activity.id,
+                    activity.data_coverage()
+                );
+                ActivityStats::with_profile(activity, &profile)
+                    .with_decimal_separator(decimal_separator)
+                    .with_units(units)
+                    .display();
+            }
+            Ok(())
+        }
+        None => {
+            let directory = args
+                .directory
+                .ok_or_else(|| anyhow!("A directory or subcommand is required"))?;
+            display_folder_stats(
+                &directory,
+                &args.output_file,
+                args.chart,
+                FolderOptions {
+                    dedupe: args.dedupe,
+                    limit: args.limit,
+                    elevation,
+                    lap_only: args.lap_only,
+                    format: args.format.into(),
+                    sport: args.sport,
+                },
+                &profile,
+                decimal_separator,
+                units,
+            )
+            .await
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
     let args = Args::parse();
+    let level = if args.verbose {
+        Level::DEBUG
+    } else {
+        Level::INFO
+    };
+    tracing_subscriber::fmt().with_max_level(level).init();
 
     let start = SystemTime::now();
-    if let Err(e) = display_folder_stats(
-        Path::new(&args.directory),
-        Path::new(&args.output_file),
-        args.chart,
-    )
-    .await
-    {
+    if let Err(e) = run(args).await {
         eprintln!("{}", e);
     }
     let end = SystemTime::now();
     let duration = end.duration_since(start).unwrap();
     info!("Total time: {:?}", duration)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One lap climbing 2m with each trackpoint.
+    static CLIMB: &str = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running">
+<Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3</TotalTimeSeconds><DistanceMeters>9</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><AltitudeMeters>100</AltitudeMeters><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><AltitudeMeters>102</AltitudeMeters><DistanceMeters>3</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:02Z</Time><AltitudeMeters>104</AltitudeMeters><DistanceMeters>6</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:03Z</Time><AltitudeMeters>106</AltitudeMeters><DistanceMeters>9</DistanceMeters></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#;
+
+    fn elevation_gain(args: &[&str]) -> usize {
+        let args = Args::parse_from([&["tcxrs", "--stdin"], args].concat());
+        let mut tcb = parse_str(CLIMB).unwrap();
+        let activity = tcb.get_activity_mut(0).unwrap();
+        activity.calc_lap_elevations_with(args.elevation_options());
+        activity.total_elevation_gain()
+    }
+
+    #[test]
+    fn elevation_threshold_flag_changes_reported_gain() {
+        // 6m of climbing in 2m steps, of which a 3m threshold only counts the first 4m.
+        assert_eq!(elevation_gain(&[]), 20);
+        assert_eq!(elevation_gain(&["--elevation-threshold", "3"]), 13);
+    }
+
+    #[test]
+    fn subcommands_take_their_own_flags() {
+        let args = Args::parse_from(["tcxrs", "export", "runs", "-o", "out.gpx", "-f", "gpx"]);
+        assert!(matches!(
+            args.command,
+            Some(Command::Export {
+                format: ExportFormat::Gpx,
+                ..
+            })
+        ));
+        let args = Args::parse_from(["tcxrs", "summary", "runs", "-f", "text,json"]);
+        let Some(Command::Summary { format, .. }) = args.command else {
+            panic!("expected the summary subcommand");
+        };
+        assert_eq!(format.len(), 2);
+        assert!(Args::try_parse_from(["tcxrs", "chart", "runs", "-f", "json"]).is_err());
+    }
+
+    #[test]
+    fn a_bare_directory_runs_without_a_subcommand() {
+        let args = Args::parse_from(["tcxrs", "runs"]);
+        assert!(args.command.is_none());
+        assert_eq!(args.directory, Some(PathBuf::from("runs")));
+    }
+}
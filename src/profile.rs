@@ -0,0 +1,158 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::zones::{HrZones, PowerZones, DEFAULT_HR_ZONE_PERCENTAGES, DEFAULT_MAX_HR};
+
+/// Athlete-specific inputs for HR and power zones, relative effort and power-to-weight, so
+/// they don't need to be passed every run. Every field is optional in the file; anything
+/// left out falls back to the same defaults used without a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AthleteProfile {
+    pub max_hr: usize,
+    pub resting_hr: Option<usize>,
+    pub weight_kg: Option<f32>,
+    /// Ascending bpm zone boundaries, as passed to [crate::Activity::hr_zones]. When left
+    /// out they're derived from `max_hr` (and `resting_hr`, if given).
+    pub hr_zones: Option<Vec<usize>>,
+    /// Functional threshold power in watts, for [crate::Activity::power_zones].
+    pub ftp: Option<usize>,
+}
+
+impl Default for AthleteProfile {
+    fn default() -> Self {
+        AthleteProfile {
+            max_hr: DEFAULT_MAX_HR,
+            resting_hr: None,
+            weight_kg: None,
+            hr_zones: None,
+            ftp: None,
+        }
+    }
+}
+
+impl AthleteProfile {
+    /// Reads a profile from a `.json` file, or from TOML for any other extension.
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Unable to read profile {}: {}", path.display(), e))?;
+        if path.extension().is_some_and(|e| e == "json") {
+            Ok(serde_json::from_str(&data)?)
+        } else {
+            Ok(toml::from_str(&data)?)
+        }
+    }
+
+    /// The athlete's HR zone boundaries: the ones given in the profile, or the default 5
+    /// zones as a percentage of heart rate reserve when the resting HR is known, and of
+    /// max HR otherwise.
+    pub fn hr_zone_boundaries(&self) -> Vec<usize> {
+        match (&self.hr_zones, self.resting_hr) {
+            (Some(zones), _) => zones.clone(),
+            (None, Some(resting_hr)) => {
+                HrZones::from_reserve(self.max_hr, resting_hr, &DEFAULT_HR_ZONE_PERCENTAGES)
+            }
+            (None, None) => HrZones::default_from_max(self.max_hr),
+        }
+    }
+
+    /// Watt boundaries of the athlete's Coggan power zones, if their FTP is known.
+    pub fn power_zone_boundaries(&self) -> Option<Vec<usize>> {
+        self.ftp.map(PowerZones::from_ftp)
+    }
+
+    /// `watts` relative to the athlete's weight, if it's known.
+    pub fn watts_per_kg(&self, watts: usize) -> Option<f32> {
+        self.weight_kg
+            .filter(|weight| *weight > 0.0)
+            .map(|weight| watts as f32 / weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_path;
+
+    /// A profile file named `name` in the system temp dir holding `data`.
+    fn profile_file(name: &str, data: &str) -> std::path::PathBuf {
+        let path = temp_path(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_reads_toml_and_json() {
+        let toml = profile_file(
+            "profile.toml",
+            "max_hr = 185\nresting_hr = 50\nweight_kg = 70.0\n",
+        );
+        let json = profile_file(
+            "profile.json",
+            r#"{"max_hr": 185, "resting_hr": 50, "weight_kg": 70.0}"#,
+        );
+        let expected = AthleteProfile {
+            max_hr: 185,
+            resting_hr: Some(50),
+            weight_kg: Some(70.0),
+            ..AthleteProfile::default()
+        };
+        assert_eq!(AthleteProfile::load(&toml).unwrap(), expected);
+        assert_eq!(AthleteProfile::load(&json).unwrap(), expected);
+        fs::remove_file(toml).unwrap();
+        fs::remove_file(json).unwrap();
+    }
+
+    #[test]
+    fn load_falls_back_to_defaults_and_rejects_bad_files() {
+        let empty = profile_file("empty.toml", "");
+        assert_eq!(
+            AthleteProfile::load(&empty).unwrap(),
+            AthleteProfile::default()
+        );
+        let bad = profile_file("bad.json", r#"{"max_hr": "fast"}"#);
+        assert!(AthleteProfile::load(&bad).is_err());
+        fs::remove_file(empty).unwrap();
+        fs::remove_file(bad).unwrap();
+        assert!(AthleteProfile::load(Path::new("/nonexistent/profile.toml")).is_err());
+    }
+
+    #[test]
+    fn hr_zone_boundaries_prefer_the_profile_zones() {
+        let mut profile = AthleteProfile {
+            max_hr: 190,
+            ..AthleteProfile::default()
+        };
+        assert_eq!(profile.hr_zone_boundaries(), HrZones::default_from_max(190));
+        profile.resting_hr = Some(50);
+        assert_eq!(
+            profile.hr_zone_boundaries(),
+            HrZones::from_reserve(190, 50, &DEFAULT_HR_ZONE_PERCENTAGES)
+        );
+        profile.hr_zones = Some(vec![120, 140, 160]);
+        assert_eq!(profile.hr_zone_boundaries(), [120, 140, 160]);
+    }
+
+    #[test]
+    fn watts_per_kg_needs_a_weight() {
+        let mut profile = AthleteProfile::default();
+        assert_eq!(profile.watts_per_kg(200), None);
+        profile.weight_kg = Some(80.0);
+        assert_eq!(profile.watts_per_kg(200), Some(2.5));
+        profile.weight_kg = Some(0.0);
+        assert_eq!(profile.watts_per_kg(200), None);
+    }
+
+    #[test]
+    fn power_zone_boundaries_need_an_ftp() {
+        let mut profile = AthleteProfile::default();
+        assert_eq!(profile.power_zone_boundaries(), None);
+        profile.ftp = Some(200);
+        assert_eq!(
+            profile.power_zone_boundaries(),
+            Some(PowerZones::from_ftp(200))
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::stats::ActivityStats;
+
+/// Version of the [Report] format written by this version of tcxrs. Bumped whenever a
+/// change to [ActivityStats] or the report would stop older readers understanding it.
+pub static REPORT_SCHEMA_VERSION: u32 = 1;
+
+/// Computed stats of a set of activities, stamped with the format version and when they
+/// were computed, for storing analyses long-term. Written as JSON by [write_report].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub schema_version: u32,
+    pub generated_at: DateTime<Utc>,
+    pub activities: Vec<ActivityStats>,
+}
+
+impl Report {
+    /// A report of `activities` at the current [REPORT_SCHEMA_VERSION], generated now.
+    pub fn new(activities: Vec<ActivityStats>) -> Self {
+        Report {
+            schema_version: REPORT_SCHEMA_VERSION,
+            generated_at: Utc::now(),
+            activities,
+        }
+    }
+
+    /// Reads a report written by [write_report]. Fails for reports from a newer version of
+    /// tcxrs than this one, whose format it can't know.
+    pub fn read(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).map_err(|e| anyhow!("Unable to read {}: {}", path.display(), e))?;
+        let report: Report = serde_json::from_reader(BufReader::new(file))?;
+        if report.schema_version > REPORT_SCHEMA_VERSION {
+            return Err(anyhow!(
+                "{} is a version {} report, but only up to version {} can be read",
+                path.display(),
+                report.schema_version,
+                REPORT_SCHEMA_VERSION
+            ));
+        }
+        Ok(report)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut output = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut output, self)?;
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes a [Report] of `activities` to `path` as JSON.
+pub fn write_report(path: &Path, activities: Vec<ActivityStats>) -> Result<()> {
+    Report::new(activities).write(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{run_stats, temp_path};
+
+    #[test]
+    fn reports_read_back_what_was_written() {
+        let path = temp_path("report.json");
+        let stats = run_stats("2024-03-01T12:00:00Z");
+        write_report(&path, vec![run_stats("2024-03-01T12:00:00Z")]).unwrap();
+        let report = Report::read(&path).unwrap();
+        assert_eq!(report.schema_version, REPORT_SCHEMA_VERSION);
+        assert_eq!(report.activities.len(), 1);
+        assert_eq!(report.activities[0].summary_line(), stats.summary_line());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_from_a_newer_version_are_rejected() {
+        let path = temp_path("newer-report.json");
+        let mut report = Report::new(vec![run_stats("2024-03-01T12:00:00Z")]);
+        report.schema_version = REPORT_SCHEMA_VERSION + 1;
+        report.write(&path).unwrap();
+        let error = Report::read(&path).unwrap_err().to_string();
+        assert!(error.contains("only up to version"), "{error}");
+        std::fs::remove_file(path).unwrap();
+    }
+}
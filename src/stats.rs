@@ -1,77 +1,1521 @@
-use anyhow::Result;
-use std::{fs::File, io::Write, time::Duration};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, IsoWeek, NaiveDate, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+    time::Duration,
+};
 
-use crate::Activity;
+use tracing::warn;
 
+use crate::{
+    profile::AthleteProfile,
+    units::{Units, FEET_PER_METER, METERS_PER_MILE, MILES_PER_METER},
+    Activity, Lap, Sport, BEST_EFFORT_DISTANCES,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ActivityStats {
+    /// The activity's id, usually its start time as written in the file.
     date: String,
+    /// See [Activity::start_time].
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    /// Start time in the activity's local timezone, e.g. `2024-03-01 07:00 EST`, from
+    /// [Activity::local_start_time]. Only filled in with the `timezone` feature.
+    local_start: Option<String>,
+    sport: Sport,
+    /// See [Activity::is_indoor].
+    indoor: bool,
     laps: usize,
-    distance_mi: f32,
+    pub(crate) distance_mi: f32,
     distance_km: f32,
     pub average_hr: usize,
     average_pace: String,
     pub average_pace_seconds: Duration,
+    /// Minutes per mile as a decimal, e.g. `7.75`, for spreadsheets that can't parse MM:SS.
+    average_pace_decimal: f64,
+    average_pace_decimal_km: f64,
+    /// Total time of every lap, as reported by the device.
+    pub total_duration: Duration,
+    pub moving_time: Duration,
+    /// Seconds per mile over the moving time, from [Activity::moving_pace].
+    #[serde(default)]
+    moving_pace_seconds: Option<Duration>,
+    /// Average pace adjusted for hills, e.g. `07:30 / mi`, from
+    /// [Activity::grade_adjusted_pace_seconds]. Empty without any altitude to adjust by.
+    #[serde(default)]
+    grade_adjusted_pace: String,
+    #[serde(default)]
+    grade_adjusted_pace_seconds: Option<Duration>,
     average_watts: usize,
+    /// Average power relative to the athlete's weight, when their profile gives it.
+    watts_per_kg: Option<f32>,
     average_cadence: usize,
-    elevation_gain: usize,
+    /// Peaks of the activity, `None` when it didn't record the value.
+    #[serde(default)]
+    max_hr: Option<usize>,
+    #[serde(default)]
+    max_watts: Option<usize>,
+    #[serde(default)]
+    max_cadence: Option<usize>,
+    /// See [Activity::max_speed_mph].
+    #[serde(default)]
+    max_speed_mph: Option<f32>,
+    pub(crate) elevation_gain: usize,
     elevation_loss: usize,
+    total_work_kj: Option<f64>,
+    /// Calories from power when the activity has it, otherwise as reported by the device.
+    calories: Option<usize>,
+    /// Fastest time for each of [crate::BEST_EFFORT_DISTANCES] the activity was long enough for.
+    #[serde(deserialize_with = "best_effort_names")]
+    best_efforts: Vec<(&'static str, Duration)>,
+    /// Training stress score used by [crate::load::training_load_series].
+    pub relative_effort: f64,
+    /// Time spent in each of the athlete's HR zones, from [Activity::hr_zone_durations].
+    #[serde(default)]
+    hr_zone_durations: Vec<Duration>,
+    #[serde(skip)]
+    decimal_separator: DecimalSeparator,
+    #[serde(skip)]
+    units: Units,
+}
+
+/// Character written between the whole and fractional part of numbers in text and CSV
+/// output. JSON always uses a period.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecimalSeparator {
+    #[default]
+    Period,
+    Comma,
+}
+
+impl DecimalSeparator {
+    /// `value` with `precision` decimals, e.g. `6.21` or `6,21`.
+    pub fn format(&self, value: f64, precision: usize) -> String {
+        let formatted = format!("{:.*}", precision, value);
+        match self {
+            DecimalSeparator::Period => formatted,
+            DecimalSeparator::Comma => formatted.replace('.', ","),
+        }
+    }
+
+    /// [DecimalSeparator::format] for a CSV cell, quoted when the separator is a comma so
+    /// it isn't read as the end of the cell.
+    fn format_csv(&self, value: f64, precision: usize) -> String {
+        match self {
+            DecimalSeparator::Period => self.format(value, precision),
+            DecimalSeparator::Comma => format!("\"{}\"", self.format(value, precision)),
+        }
+    }
 }
 
 impl ActivityStats {
     pub fn new(activity: &Activity) -> Self {
+        ActivityStats::with_profile(activity, &AthleteProfile::default())
+    }
+
+    /// Stats using the athlete's max HR, zones and weight for relative effort and W/kg.
+    pub fn with_profile(activity: &Activity, profile: &AthleteProfile) -> Self {
+        let distance_meters = activity.effective_distance_meters();
+        let grade_adjusted_pace = (distance_meters > 0.0 && activity.average_grade().is_some())
+            .then(|| activity.grade_adjusted_pace_seconds());
         ActivityStats {
-            date: activity.id.clone(),
-            laps: activity.lap_count(),
-            distance_mi: activity.total_distance_miles(),
-            distance_km: activity.total_distance_meters() / 1000.0,
             average_hr: activity.average_hr(),
+            moving_time: activity.moving_time(),
+            moving_pace_seconds: activity.moving_pace(),
+            grade_adjusted_pace: grade_adjusted_pace
+                .map(|pace| format_pace(pace.as_secs(), "mi"))
+                .unwrap_or_default(),
+            grade_adjusted_pace_seconds: grade_adjusted_pace,
+            max_hr: activity.max_hr(),
+            max_watts: activity.max_watts(),
+            max_cadence: activity.max_cadence(),
+            max_speed_mph: activity.max_speed_mph(),
+            total_work_kj: activity.total_work_kj(),
+            calories: activity
+                .calories_from_power()
+                .or_else(|| activity.total_calories()),
+            best_efforts: BEST_EFFORT_DISTANCES
+                .iter()
+                .zip(activity.best_efforts())
+                .filter_map(|((name, _), (_, time))| Some((*name, time?)))
+                .collect(),
+            relative_effort: activity.relative_effort_with_zones(&profile.hr_zone_boundaries()),
+            hr_zone_durations: activity.hr_zone_durations(&profile.hr_zone_boundaries()),
+            ..ActivityStats::from_laps(activity, profile)
+        }
+    }
+
+    /// Stats from lap totals alone, e.g. for quick folder-wide charts: trackpoints are only
+    /// walked for the GPS distance of laps without any (see
+    /// [Activity::effective_distance_meters]), so this is much cheaper than
+    /// [ActivityStats::with_profile] for long activities. HR is [Activity::average_lap_hr]
+    /// and moving time is the total duration. Elevation (unless already calculated into the
+    /// laps), total work and relative effort are 0 or left out, there are no best efforts
+    /// or max speed, and peaks and calories are the laps' own.
+    pub fn from_laps(activity: &Activity, profile: &AthleteProfile) -> Self {
+        let average_watts = activity.average_watts();
+        let distance_meters = activity.effective_distance_meters();
+        ActivityStats {
+            start_time: activity.start_time(),
+            #[cfg(feature = "timezone")]
+            local_start: activity
+                .local_start_time()
+                .map(|start| start.format("%Y-%m-%d %H:%M %Z").to_string()),
+            indoor: activity.is_indoor(),
+            laps: activity.lap_count(),
+            distance_mi: distance_meters * MILES_PER_METER,
+            distance_km: distance_meters / 1000.0,
+            average_hr: activity.average_lap_hr(),
             average_pace: activity.average_pace(),
             average_pace_seconds: activity.average_pace_seconds(),
-            average_watts: activity.average_watts(),
+            average_pace_decimal: activity.average_pace_decimal(),
+            average_pace_decimal_km: activity.average_pace_decimal_km(),
+            total_duration: activity.duration(),
+            moving_time: activity.duration(),
+            average_watts,
+            watts_per_kg: profile
+                .watts_per_kg(average_watts)
+                .filter(|_| average_watts > 0),
             average_cadence: activity.average_cadence(),
+            max_hr: activity.laps.iter().filter_map(Lap::max_hr).max(),
+            max_watts: activity.laps.iter().filter_map(Lap::max_watts).max(),
+            max_cadence: activity
+                .laps
+                .iter()
+                .filter_map(|lap| lap.max_cadence(activity.sport))
+                .max(),
             elevation_gain: activity.total_elevation_gain(),
             elevation_loss: activity.total_elevation_loss(),
+            calories: activity.total_calories(),
+            ..ActivityStats::empty(activity.id.clone(), activity.sport)
+        }
+    }
+
+    /// Stats of an activity with nothing recorded, for the other constructors to fill in.
+    fn empty(date: String, sport: Sport) -> Self {
+        ActivityStats {
+            date,
+            start_time: None,
+            local_start: None,
+            sport,
+            indoor: false,
+            laps: 0,
+            distance_mi: 0.0,
+            distance_km: 0.0,
+            average_hr: 0,
+            average_pace: String::new(),
+            average_pace_seconds: Duration::ZERO,
+            average_pace_decimal: 0.0,
+            average_pace_decimal_km: 0.0,
+            total_duration: Duration::ZERO,
+            moving_time: Duration::ZERO,
+            moving_pace_seconds: None,
+            grade_adjusted_pace: String::new(),
+            grade_adjusted_pace_seconds: None,
+            average_watts: 0,
+            watts_per_kg: None,
+            average_cadence: 0,
+            max_hr: None,
+            max_watts: None,
+            max_cadence: None,
+            max_speed_mph: None,
+            elevation_gain: 0,
+            elevation_loss: 0,
+            total_work_kj: None,
+            calories: None,
+            best_efforts: vec![],
+            relative_effort: 0.0,
+            hr_zone_durations: vec![],
+            decimal_separator: DecimalSeparator::default(),
+            units: Units::default(),
         }
     }
+
+    /// Stats treating every activity of `stats` as one, e.g. for a training block: totals
+    /// are summed, averages weighted by duration, and best efforts are the fastest of any
+    /// activity. It takes the date and local start of the first activity, and is only
+    /// indoor if they all were. Mixing sports logs a warning and gives [Sport::Other].
+    /// `None` without any activities.
+    pub fn combined(stats: &[ActivityStats]) -> Option<ActivityStats> {
+        let first = stats.first()?;
+        let sport = if stats.iter().all(|s| s.sport == first.sport) {
+            first.sport
+        } else {
+            warn!("Combining activities of different sports");
+            Sport::Other
+        };
+        let sum = |value: fn(&ActivityStats) -> f64| stats.iter().map(value).sum::<f64>();
+        let total_duration: Duration = stats.iter().map(|s| s.total_duration).sum();
+        // Mean of `value` over the activities where it's recorded, weighted by duration.
+        let weighted = |value: fn(&ActivityStats) -> Option<f64>| {
+            let (total, seconds) = stats
+                .iter()
+                .filter_map(|s| Some((value(s)?, s.total_duration.as_secs_f64())))
+                .fold((0.0, 0.0), |(t, d), (v, secs)| (t + v * secs, d + secs));
+            (seconds > 0.0).then(|| total / seconds)
+        };
+
+        let distance_mi = sum(|s| s.distance_mi as f64);
+        let moving_time: Duration = stats.iter().map(|s| s.moving_time).sum();
+        let distance_km = sum(|s| s.distance_km as f64);
+        let pace_seconds = if distance_mi > 0.0 {
+            total_duration.as_secs_f64() / distance_mi
+        } else {
+            0.0
+        };
+        let best_efforts = BEST_EFFORT_DISTANCES
+            .iter()
+            .filter_map(|(name, _)| {
+                let fastest = stats
+                    .iter()
+                    .flat_map(|s| s.best_efforts.iter())
+                    .filter(|(effort, _)| effort == name)
+                    .map(|(_, time)| *time)
+                    .min()?;
+                Some((*name, fastest))
+            })
+            .collect();
+        let mut hr_zone_durations: Vec<Duration> = vec![];
+        for s in stats {
+            if hr_zone_durations.len() < s.hr_zone_durations.len() {
+                hr_zone_durations.resize(s.hr_zone_durations.len(), Duration::ZERO);
+            }
+            for (total, time) in hr_zone_durations.iter_mut().zip(&s.hr_zone_durations) {
+                *total += *time;
+            }
+        }
+        // Flat-equivalent miles of each activity, as covered at its grade adjusted pace.
+        let grade_adjusted_pace = stats
+            .iter()
+            .any(|s| s.grade_adjusted_pace_seconds.is_some())
+            .then(|| {
+                let flat_miles = sum(|s| match s.grade_adjusted_pace_seconds {
+                    Some(pace) if !pace.is_zero() => {
+                        s.total_duration.as_secs_f64() / pace.as_secs_f64()
+                    }
+                    _ => s.distance_mi as f64,
+                });
+                total_duration.as_secs_f64() / flat_miles
+            })
+            .filter(|seconds| seconds.is_finite())
+            .map(|seconds| Duration::from_secs(seconds.round() as u64));
+        let some_if_any = |value: fn(&ActivityStats) -> Option<f64>| {
+            stats
+                .iter()
+                .any(|s| value(s).is_some())
+                .then(|| stats.iter().filter_map(value).sum::<f64>())
+        };
+
+        Some(ActivityStats {
+            date: first.date.clone(),
+            start_time: first.start_time,
+            local_start: first.local_start.clone(),
+            sport,
+            indoor: stats.iter().all(|s| s.indoor),
+            laps: stats.iter().map(|s| s.laps).sum(),
+            distance_mi: distance_mi as f32,
+            distance_km: distance_km as f32,
+            average_hr: weighted(|s| (s.average_hr > 0).then_some(s.average_hr as f64))
+                .map_or(0, |hr| hr.round() as usize),
+            average_pace: format!(
+                "{:02}:{:02} / mi",
+                pace_seconds.round() as u64 / 60,
+                pace_seconds.round() as u64 % 60
+            ),
+            average_pace_seconds: Duration::from_secs(pace_seconds.round() as u64),
+            average_pace_decimal: pace_seconds / 60.0,
+            average_pace_decimal_km: pace_seconds / (METERS_PER_MILE as f64 / 1000.0) / 60.0,
+            total_duration,
+            moving_time,
+            moving_pace_seconds: (distance_mi > 0.0 && !moving_time.is_zero()).then(|| {
+                Duration::from_secs((moving_time.as_secs_f64() / distance_mi).round() as u64)
+            }),
+            grade_adjusted_pace: grade_adjusted_pace
+                .map(|pace| format_pace(pace.as_secs(), "mi"))
+                .unwrap_or_default(),
+            grade_adjusted_pace_seconds: grade_adjusted_pace,
+            average_watts: weighted(|s| (s.average_watts > 0).then_some(s.average_watts as f64))
+                .map_or(0, |watts| watts.round() as usize),
+            watts_per_kg: weighted(|s| s.watts_per_kg.map(|w| w as f64)).map(|w| w as f32),
+            average_cadence: weighted(|s| {
+                (s.average_cadence > 0).then_some(s.average_cadence as f64)
+            })
+            .map_or(0, |cadence| cadence.round() as usize),
+            max_hr: stats.iter().filter_map(|s| s.max_hr).max(),
+            max_watts: stats.iter().filter_map(|s| s.max_watts).max(),
+            max_cadence: stats.iter().filter_map(|s| s.max_cadence).max(),
+            max_speed_mph: stats
+                .iter()
+                .filter_map(|s| s.max_speed_mph)
+                .reduce(f32::max),
+            elevation_gain: stats.iter().map(|s| s.elevation_gain).sum(),
+            elevation_loss: stats.iter().map(|s| s.elevation_loss).sum(),
+            total_work_kj: some_if_any(|s| s.total_work_kj),
+            calories: some_if_any(|s| s.calories.map(|c| c as f64)).map(|c| c as usize),
+            best_efforts,
+            relative_effort: sum(|s| s.relative_effort),
+            hr_zone_durations,
+            decimal_separator: first.decimal_separator,
+            units: first.units,
+        })
+    }
+
+    /// These stats, with decimals in the text and CSV output written using `separator`.
+    pub fn with_decimal_separator(mut self, separator: DecimalSeparator) -> Self {
+        self.decimal_separator = separator;
+        self
+    }
+
+    /// These stats, with distance, pace and elevation in the text output written in
+    /// `units`.
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+}
+
+/// A single value of [ActivityStats::as_map], left unformatted for the caller to render.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum StatValue {
+    Float(f64),
+    Int(i64),
+    Duration(Duration),
+    Str(String),
 }
 
 impl From<&Activity> for ActivityStats {
     fn from(activity: &Activity) -> ActivityStats {
-        ActivityStats::new(&activity)
+        ActivityStats::new(activity)
     }
 }
 
 impl ActivityStats {
     pub fn stats(&self) -> Vec<String> {
         let mut stats = vec![];
-        stats.push(format!("=== {} ===", self.date));
+        if self.indoor {
+            stats.push(format!("=== {} (indoor) ===", self.date));
+        } else {
+            stats.push(format!("=== {} ===", self.date));
+        }
+        if let Some(local_start) = &self.local_start {
+            stats.push(format!("  Local Start: {}", local_start));
+        }
         stats.push(format!("  Total laps: {}", self.laps));
+        // Activities like rowing or the elliptical may not record any distance.
+        if self.has_distance() {
+            stats.push(format!(
+                "  Distance: {}",
+                distance_text(
+                    self.units,
+                    self.decimal_separator,
+                    self.distance_mi,
+                    self.distance_km
+                )
+            ));
+        }
+        stats.push(format!("  Average HR: {}", self.average_hr));
+        if let Some(max_hr) = self.max_hr {
+            stats.push(format!("  Max HR: {}", max_hr));
+        }
+        if self.has_distance() {
+            let pace = match self.units {
+                Units::Imperial => self.average_pace.clone(),
+                Units::Metric => format_pace(self.pace_seconds_per_km(), "km"),
+            };
+            stats.push(format!("  Average Pace: {}", pace));
+        }
+        if let Some(moving_pace) = self.moving_pace_seconds.filter(|_| self.has_distance()) {
+            let pace = match self.units {
+                Units::Imperial => format_pace(moving_pace.as_secs(), "mi"),
+                Units::Metric => format_pace(
+                    (moving_pace.as_secs_f64() * 1000.0 / METERS_PER_MILE as f64).round() as u64,
+                    "km",
+                ),
+            };
+            stats.push(format!("  Moving Pace: {}", pace));
+        }
+        if let Some(grade_adjusted_pace) = self.grade_adjusted_pace_seconds {
+            let pace = match self.units {
+                Units::Imperial => self.grade_adjusted_pace.clone(),
+                Units::Metric => format_pace(
+                    (grade_adjusted_pace.as_secs_f64() * 1000.0 / METERS_PER_MILE as f64).round()
+                        as u64,
+                    "km",
+                ),
+            };
+            stats.push(format!("  Grade Adjusted Pace: {}", pace));
+        }
         stats.push(format!(
-            "  Distance: {:.2}mi / {:.2}km",
-            self.distance_mi, self.distance_km
+            "  Duration: {}",
+            format_duration(self.total_duration)
+        ));
+        stats.push(format!(
+            "  Moving Time: {}",
+            format_duration(self.moving_time)
         ));
-        stats.push(format!("  Average HR: {}", self.average_hr));
-        stats.push(format!("  Average Pace: {}", self.average_pace));
 
+        if let Some(max_speed) = self.max_speed_mph {
+            let speed = match self.units {
+                Units::Imperial => {
+                    format!("{}mph", self.decimal_separator.format(max_speed as f64, 1))
+                }
+                Units::Metric => format!(
+                    "{}km/h",
+                    self.decimal_separator
+                        .format(max_speed as f64 * METERS_PER_MILE as f64 / 1000.0, 1)
+                ),
+            };
+            stats.push(format!("  Max Speed: {}", speed));
+        }
         stats.push(format!("  Average Power: {}W", self.average_watts));
+        if let Some(max_watts) = self.max_watts {
+            stats.push(format!("  Max Power: {}W", max_watts));
+        }
+        if let Some(watts_per_kg) = self.watts_per_kg {
+            stats.push(format!(
+                "  Power to Weight: {}W/kg",
+                self.decimal_separator.format(watts_per_kg as f64, 2)
+            ));
+        }
         stats.push(format!(
-            "  Average Cadence: {} steps/min",
-            self.average_cadence
+            "  Average Cadence: {}",
+            self.cadence_text(self.average_cadence)
         ));
+        if let Some(max_cadence) = self.max_cadence {
+            stats.push(format!("  Max Cadence: {}", self.cadence_text(max_cadence)));
+        }
 
-        stats.push(format!("  Elevation Gain: {}", self.elevation_gain));
-        stats.push(format!("  Elevation Loss: {}", self.elevation_loss));
+        stats.push(format!(
+            "  Elevation Gain: {}",
+            elevation_text(self.units, self.elevation_gain)
+        ));
+        stats.push(format!(
+            "  Elevation Loss: {}",
+            elevation_text(self.units, self.elevation_loss)
+        ));
+        stats.push(format!("  Relative Effort: {:.0}", self.relative_effort));
+        // Left out when the activity has no HR to put in a zone.
+        if self.hr_zone_durations.iter().any(|time| !time.is_zero()) {
+            let zones: Vec<String> = self
+                .hr_zone_durations
+                .iter()
+                .enumerate()
+                .map(|(i, time)| format!("Z{} {}", i + 1, format_minutes(*time)))
+                .collect();
+            stats.push(format!("  Time in Zones: {}", zones.join(", ")));
+        }
+        if let Some(work) = self.total_work_kj {
+            stats.push(format!("  Total Work: {:.0}kJ", work));
+        }
+        if let Some(calories) = self.calories {
+            stats.push(format!("  Calories: {}", calories));
+        }
+        if !self.best_efforts.is_empty() {
+            let efforts: Vec<String> = self
+                .best_efforts
+                .iter()
+                .map(|(name, time)| format!("{} {}", name, format_minutes(*time)))
+                .collect();
+            stats.push(format!("  Best Efforts: {}", efforts.join(", ")));
+        }
         stats.push(String::from("================================\n\n"));
         stats
     }
+    /// A compact single line, e.g. `2024-03-01 Running 6.21mi 7:45/mi 142bpm` (or
+    /// `10.00km 4:49/km` in metric units), for grepping through many activities. HR is left
+    /// off when the activity has none, and pace when it recorded no distance.
+    pub fn summary_line(&self) -> String {
+        let day = self.date.split('T').next().unwrap_or(&self.date);
+        let (distance, pace, unit) = match self.units {
+            Units::Imperial => (self.distance_mi, self.average_pace_seconds.as_secs(), "mi"),
+            Units::Metric => (self.distance_km, self.pace_seconds_per_km(), "km"),
+        };
+        let mut line = format!(
+            "{} {} {}{}",
+            day,
+            self.sport,
+            self.decimal_separator.format(distance as f64, 2),
+            unit
+        );
+        if self.has_distance() {
+            line.push_str(&format!(" {}:{:02}/{}", pace / 60, pace % 60, unit));
+        }
+        if self.average_hr > 0 {
+            line.push_str(&format!(" {}bpm", self.average_hr));
+        }
+        line
+    }
+
     pub fn display(&self) {
         for val in self.stats() {
             println!("{}", val);
         }
     }
 
-    pub fn write_to(&self, output_file: &mut File) -> Result<()> {
-        output_file.write_all(self.stats().join("\n").as_bytes())?;
+    /// `cadence` with the unit for the sport, e.g. `170 steps/min` or `90 rpm`.
+    fn cadence_text(&self, cadence: usize) -> String {
+        match self.sport {
+            Sport::Running | Sport::Walking => format!("{} steps/min", cadence),
+            Sport::Biking => format!("{} rpm", cadence),
+            _ => cadence.to_string(),
+        }
+    }
+
+    pub(crate) fn has_distance(&self) -> bool {
+        self.distance_mi > 0.0
+    }
+
+    fn pace_seconds_per_km(&self) -> u64 {
+        (self.average_pace_decimal_km * 60.0).round() as u64
+    }
+
+    pub fn sport(&self) -> Sport {
+        self.sport
+    }
+
+    /// Sort key putting stats in the order of their activities, as `activity_order` does:
+    /// chronologically, with those without a start time last, by id.
+    #[cfg(feature = "async")]
+    #[cfg_attr(not(feature = "charts"), allow(dead_code))]
+    pub(crate) fn order_key(&self) -> (bool, Option<DateTime<Utc>>, &str) {
+        (self.start_time.is_none(), self.start_time, &self.date)
+    }
+
+    /// The UTC calendar date the activity started on, from its start time.
+    pub fn start_date(&self) -> Option<NaiveDate> {
+        self.start_time.map(|start| start.date_naive())
+    }
+
+    /// The calendar date the activity started on in its local timezone, where that's known
+    /// (see [Activity::local_start_time]), otherwise [ActivityStats::start_date].
+    pub fn local_start_date(&self) -> Option<NaiveDate> {
+        self.local_start
+            .as_deref()
+            .and_then(|local_start| local_start.get(..10))
+            .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+            .or_else(|| self.start_date())
+    }
+
+    /// Every stat keyed by its field name. Stats the activity doesn't have (e.g. total work
+    /// without power) are left out, and each best effort is keyed `best_effort_<name>` and
+    /// the time in each HR zone `hr_zone_<n>`.
+    pub fn as_map(&self) -> BTreeMap<String, StatValue> {
+        let mut map = BTreeMap::new();
+        let mut insert = |key: &str, value: StatValue| {
+            map.insert(key.to_string(), value);
+        };
+        insert("date", StatValue::Str(self.date.clone()));
+        if let Some(local_start) = &self.local_start {
+            insert("local_start", StatValue::Str(local_start.clone()));
+        }
+        insert("sport", StatValue::Str(self.sport.to_string()));
+        insert("laps", StatValue::Int(self.laps as i64));
+        insert("distance_mi", StatValue::Float(self.distance_mi as f64));
+        insert("distance_km", StatValue::Float(self.distance_km as f64));
+        insert("average_hr", StatValue::Int(self.average_hr as i64));
+        insert("average_pace", StatValue::Str(self.average_pace.clone()));
+        insert(
+            "average_pace_seconds",
+            StatValue::Duration(self.average_pace_seconds),
+        );
+        insert(
+            "average_pace_decimal",
+            StatValue::Float(self.average_pace_decimal),
+        );
+        insert(
+            "average_pace_decimal_km",
+            StatValue::Float(self.average_pace_decimal_km),
+        );
+        insert("total_duration", StatValue::Duration(self.total_duration));
+        insert("moving_time", StatValue::Duration(self.moving_time));
+        if let Some(moving_pace) = self.moving_pace_seconds {
+            insert("moving_pace_seconds", StatValue::Duration(moving_pace));
+        }
+        if let Some(grade_adjusted_pace) = self.grade_adjusted_pace_seconds {
+            insert(
+                "grade_adjusted_pace",
+                StatValue::Str(self.grade_adjusted_pace.clone()),
+            );
+            insert(
+                "grade_adjusted_pace_seconds",
+                StatValue::Duration(grade_adjusted_pace),
+            );
+        }
+        insert("average_watts", StatValue::Int(self.average_watts as i64));
+        if let Some(watts_per_kg) = self.watts_per_kg {
+            insert("watts_per_kg", StatValue::Float(watts_per_kg as f64));
+        }
+        insert(
+            "average_cadence",
+            StatValue::Int(self.average_cadence as i64),
+        );
+        if let Some(max_hr) = self.max_hr {
+            insert("max_hr", StatValue::Int(max_hr as i64));
+        }
+        if let Some(max_watts) = self.max_watts {
+            insert("max_watts", StatValue::Int(max_watts as i64));
+        }
+        if let Some(max_cadence) = self.max_cadence {
+            insert("max_cadence", StatValue::Int(max_cadence as i64));
+        }
+        if let Some(max_speed) = self.max_speed_mph {
+            insert("max_speed_mph", StatValue::Float(max_speed as f64));
+        }
+        insert("elevation_gain", StatValue::Int(self.elevation_gain as i64));
+        insert("elevation_loss", StatValue::Int(self.elevation_loss as i64));
+        if let Some(work) = self.total_work_kj {
+            insert("total_work_kj", StatValue::Float(work));
+        }
+        if let Some(calories) = self.calories {
+            insert("calories", StatValue::Int(calories as i64));
+        }
+        for (name, time) in self.best_efforts.iter() {
+            insert(&format!("best_effort_{}", name), StatValue::Duration(*time));
+        }
+        insert("relative_effort", StatValue::Float(self.relative_effort));
+        for (i, time) in self.hr_zone_durations.iter().enumerate() {
+            insert(&format!("hr_zone_{}", i + 1), StatValue::Duration(*time));
+        }
+        map
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Column names matching the fields written by [ActivityStats::write_csv_row].
+    pub fn write_csv_header<W: Write>(output: &mut W) -> Result<()> {
+        writeln!(
+            output,
+            "date,laps,distance_mi,distance_km,average_hr,average_pace,average_pace_seconds,\
+             average_watts,average_cadence,elevation_gain,elevation_loss,total_work_kj,relative_effort,\
+             watts_per_kg,sport,calories"
+        )?;
+        Ok(())
+    }
+
+    pub fn write_csv_row<W: Write>(&self, output: &mut W) -> Result<()> {
+        writeln!(
+            output,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{:.0},{},{},{}",
+            self.date,
+            self.laps,
+            self.decimal_separator
+                .format_csv(self.distance_mi as f64, 2),
+            self.decimal_separator
+                .format_csv(self.distance_km as f64, 2),
+            self.average_hr,
+            self.average_pace,
+            self.average_pace_seconds.as_secs(),
+            self.average_watts,
+            self.average_cadence,
+            self.elevation_gain,
+            self.elevation_loss,
+            self.total_work_kj
+                .map(|w| format!("{:.0}", w))
+                .unwrap_or_default(),
+            self.relative_effort,
+            self.watts_per_kg
+                .map(|w| self.decimal_separator.format_csv(w as f64, 2))
+                .unwrap_or_default(),
+            self.sport,
+            optional_cell(self.calories),
+        )?;
         Ok(())
     }
+
+    /// Reads back the rows written by [ActivityStats::write_csv_row], with either decimal
+    /// separator. Columns are matched by their header name. Stats the CSV doesn't carry
+    /// (durations, peaks, best efforts and local start) are left empty. Files written by older
+    /// versions may lack the sport, which is then [Sport::Other], and the calories.
+    pub fn read_csv<R: BufRead>(input: R) -> Result<Vec<Self>> {
+        let mut lines = input.lines();
+        let header = match lines.next() {
+            Some(header) => split_csv_row(&header?),
+            None => return Ok(vec![]),
+        };
+
+        let mut stats = vec![];
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let row = index + 2;
+            let cells = split_csv_row(&line);
+            let cell = |name: &str| {
+                header
+                    .iter()
+                    .position(|column| column == name)
+                    .and_then(|column| cells.get(column))
+                    .map_or("", |cell| cell.trim())
+            };
+            let number =
+                |name: &str| -> Result<Option<f64>> {
+                    let value = cell(name);
+                    if value.is_empty() {
+                        return Ok(None);
+                    }
+                    value.replace(',', ".").parse().map(Some).map_err(|e| {
+                        anyhow!("Invalid {} on row {} ({:?}): {}", name, row, value, e)
+                    })
+                };
+            let required = |name: &str| {
+                number(name)?.ok_or_else(|| anyhow!("Missing {} on row {}", name, row))
+            };
+
+            let average_pace_seconds = required("average_pace_seconds")?;
+            let average_watts = required("average_watts")? as usize;
+            stats.push(ActivityStats {
+                start_time: DateTime::parse_from_rfc3339(cell("date"))
+                    .ok()
+                    .map(|start| start.with_timezone(&Utc)),
+                laps: required("laps")? as usize,
+                distance_mi: required("distance_mi")? as f32,
+                distance_km: required("distance_km")? as f32,
+                average_hr: required("average_hr")? as usize,
+                average_pace: cell("average_pace").to_string(),
+                average_pace_seconds: Duration::from_secs(average_pace_seconds as u64),
+                average_pace_decimal: average_pace_seconds / 60.0,
+                average_pace_decimal_km: average_pace_seconds
+                    / (METERS_PER_MILE as f64 / 1000.0)
+                    / 60.0,
+                average_watts,
+                watts_per_kg: number("watts_per_kg")?.map(|w| w as f32),
+                average_cadence: required("average_cadence")? as usize,
+                elevation_gain: required("elevation_gain")? as usize,
+                elevation_loss: required("elevation_loss")? as usize,
+                total_work_kj: number("total_work_kj")?,
+                calories: number("calories")?.map(|c| c as usize),
+                relative_effort: required("relative_effort")?,
+                ..ActivityStats::empty(
+                    cell("date").to_string(),
+                    Sport::from(cell("sport").to_string()),
+                )
+            });
+        }
+        Ok(stats)
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
+        output.write_all(self.stats().join("\n").as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Splits a CSV row on commas outside of double quotes, dropping the quotes.
+fn split_csv_row(line: &str) -> Vec<String> {
+    let mut cells = vec![String::new()];
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells
+}
+
+/// Reads serialized best efforts back, matching each name to its entry in
+/// [BEST_EFFORT_DISTANCES]. Efforts under names this version doesn't know are dropped.
+fn best_effort_names<'de, D>(deserializer: D) -> Result<Vec<(&'static str, Duration)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let efforts: Vec<(String, Duration)> = Vec::deserialize(deserializer)?;
+    Ok(efforts
+        .into_iter()
+        .filter_map(|(name, time)| {
+            let (name, _) = BEST_EFFORT_DISTANCES.iter().find(|(n, _)| *n == name)?;
+            Some((*name, time))
+        })
+        .collect())
+}
+
+/// Formats `duration` as `H:MM:SS`. Hours keep counting past 24, so a multi-day activity
+/// reads e.g. `26:15:03`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64().round() as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Formats a pace of `seconds` per `unit` as e.g. `07:45 / mi`.
+fn format_pace(seconds: u64, unit: &str) -> String {
+    format!("{:02}:{:02} / {}", seconds / 60, seconds % 60, unit)
+}
+
+/// Formats a best effort or lap time as `M:SS`, or as `H:MM:SS` once it takes an hour or
+/// more.
+fn format_minutes(time: Duration) -> String {
+    let seconds = time.as_secs_f64().round() as u64;
+    if seconds >= 3600 {
+        format_duration(time)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Stats for a single lap of an activity, for interval analysis.
+#[derive(Debug, Serialize)]
+pub struct LapStats {
+    date: String,
+    lap: usize,
+    distance_mi: f32,
+    seconds: f32,
+    pace_seconds: Option<Duration>,
+    average_hr: Option<usize>,
+    average_watts: Option<usize>,
+    average_cadence: Option<usize>,
+    calories: Option<usize>,
+}
+
+impl LapStats {
+    /// Stats for the lap at `index` (starting at 1) of `activity`.
+    pub fn new(activity: &Activity, index: usize, lap: &Lap) -> Self {
+        LapStats {
+            date: activity.id.clone(),
+            lap: index,
+            distance_mi: lap.distance * MILES_PER_METER,
+            seconds: lap.seconds,
+            pace_seconds: lap.pace_seconds(),
+            average_hr: lap.average_hr(),
+            average_watts: lap.average_watts(),
+            average_cadence: lap.average_cadence(activity.sport),
+            calories: lap.calories,
+        }
+    }
+
+    /// Stats for every lap of `activity`, in order.
+    pub fn for_activity(activity: &Activity) -> Vec<Self> {
+        activity
+            .laps
+            .iter()
+            .enumerate()
+            .map(|(i, lap)| LapStats::new(activity, i + 1, lap))
+            .collect()
+    }
+
+    pub fn write_csv_header<W: Write>(output: &mut W) -> Result<()> {
+        writeln!(
+            output,
+            "date,lap,distance_mi,seconds,pace_seconds,average_hr,average_watts,average_cadence,calories"
+        )?;
+        Ok(())
+    }
+
+    /// Writes this lap as a CSV row, leaving cells empty for fields the lap didn't record.
+    pub fn write_csv_row<W: Write>(&self, output: &mut W) -> Result<()> {
+        writeln!(
+            output,
+            "{},{},{:.2},{:.0},{},{},{},{},{}",
+            self.date,
+            self.lap,
+            self.distance_mi,
+            self.seconds,
+            optional_cell(self.pace_seconds.map(|p| p.as_secs())),
+            optional_cell(self.average_hr),
+            optional_cell(self.average_watts),
+            optional_cell(self.average_cadence),
+            optional_cell(self.calories),
+        )?;
+        Ok(())
+    }
+}
+
+/// The table of [Activity::lap_comparison_table].
+pub(crate) fn lap_comparison_table(activity: &Activity) -> String {
+    let fastest = activity.fastest_lap();
+    let mut table = vec![format!(
+        "  {:>3} {:>9} {:>8} {:>9} {:>6} {:>6}",
+        "Lap", "Distance", "Time", "Pace", "HR", "Power"
+    )];
+    for (i, lap) in activity.laps.iter().enumerate() {
+        let stats = LapStats::new(activity, i + 1, lap);
+        let marker = if fastest.is_some_and(|f| std::ptr::eq(f, lap)) {
+            '*'
+        } else {
+            ' '
+        };
+        table.push(format!(
+            "{} {:>3} {:>7.2}mi {:>8} {:>9} {:>6} {:>6}",
+            marker,
+            stats.lap,
+            stats.distance_mi,
+            format_minutes(Duration::from_secs_f32(stats.seconds.max(0.0))),
+            stats.pace_seconds.map_or(String::from("-"), |pace| format!(
+                "{}/mi",
+                format_minutes(pace)
+            )),
+            stats
+                .average_hr
+                .map_or(String::from("-"), |hr| hr.to_string()),
+            stats
+                .average_watts
+                .map_or(String::from("-"), |watts| format!("{}W", watts)),
+        ));
+    }
+    table.join("\n")
+}
+
+/// A distance in `units`: both miles and kilometers for imperial units, as it's always
+/// been written, or only kilometers for metric.
+fn distance_text(
+    units: Units,
+    separator: DecimalSeparator,
+    distance_mi: f32,
+    distance_km: f32,
+) -> String {
+    match units {
+        Units::Imperial => format!(
+            "{}mi / {}km",
+            separator.format(distance_mi as f64, 2),
+            separator.format(distance_km as f64, 2)
+        ),
+        Units::Metric => format!("{}km", separator.format(distance_km as f64, 2)),
+    }
+}
+
+/// `feet` of elevation in `units`: a bare number of feet, as it's always been written, or
+/// meters with an `m` suffix.
+fn elevation_text(units: Units, feet: usize) -> String {
+    match units {
+        Units::Imperial => feet.to_string(),
+        Units::Metric => format!("{:.0}m", feet as f64 / FEET_PER_METER),
+    }
+}
+
+fn optional_cell<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Totals across a group of activities, e.g. every activity of one sport.
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct FolderTotals {
+    pub activities: usize,
+    pub distance_mi: f32,
+    pub distance_km: f32,
+    /// Mean of the average HR of activities that recorded HR.
+    pub average_hr: usize,
+    pub elevation_gain: usize,
+    pub elevation_loss: usize,
+    /// Total of the activities' calories, see [ActivityStats] for where they come from.
+    pub calories: usize,
+    /// Taken from the activities' stats, see [ActivityStats::with_decimal_separator].
+    #[serde(skip)]
+    decimal_separator: DecimalSeparator,
+    /// Taken from the activities' stats, see [ActivityStats::with_units].
+    #[serde(skip)]
+    units: Units,
+}
+
+impl FolderTotals {
+    pub fn new<'a>(stats: impl IntoIterator<Item = &'a ActivityStats>) -> Self {
+        let mut totals = FolderTotals::default();
+        let (mut hr_total, mut hr_count) = (0, 0);
+        for activity_stats in stats {
+            totals.activities += 1;
+            totals.decimal_separator = activity_stats.decimal_separator;
+            totals.units = activity_stats.units;
+            totals.distance_mi += activity_stats.distance_mi;
+            totals.distance_km += activity_stats.distance_km;
+            totals.elevation_gain += activity_stats.elevation_gain;
+            totals.elevation_loss += activity_stats.elevation_loss;
+            totals.calories += activity_stats.calories.unwrap_or(0);
+            if activity_stats.average_hr > 0 {
+                hr_total += activity_stats.average_hr;
+                hr_count += 1;
+            }
+        }
+        totals.average_hr = hr_total.checked_div(hr_count).unwrap_or(0);
+        totals
+    }
+
+    pub fn stats(&self, title: &str) -> Vec<String> {
+        vec![
+            format!("=== {} ===", title),
+            format!("  Activities: {}", self.activities),
+            format!("  Distance: {}", self.distance_text()),
+            format!("  Average HR: {}", self.average_hr),
+            format!(
+                "  Elevation Gain: {}",
+                elevation_text(self.units, self.elevation_gain)
+            ),
+            format!(
+                "  Elevation Loss: {}",
+                elevation_text(self.units, self.elevation_loss)
+            ),
+            format!("  Calories: {}", self.calories),
+            String::from("================================\n\n"),
+        ]
+    }
+
+    fn distance_text(&self) -> String {
+        distance_text(
+            self.units,
+            self.decimal_separator,
+            self.distance_mi,
+            self.distance_km,
+        )
+    }
+
+    pub fn write_to<W: Write>(&self, title: &str, output: &mut W) -> Result<()> {
+        output.write_all(self.stats(title).join("\n").as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Totals for each sport present in `stats`.
+pub fn summary_by_sport(stats: &[ActivityStats]) -> BTreeMap<Sport, FolderTotals> {
+    let mut by_sport: BTreeMap<Sport, Vec<&ActivityStats>> = BTreeMap::new();
+    for activity_stats in stats {
+        by_sport
+            .entry(activity_stats.sport)
+            .or_default()
+            .push(activity_stats);
+    }
+    by_sport
+        .into_iter()
+        .map(|(sport, stats)| (sport, FolderTotals::new(stats)))
+        .collect()
+}
+
+/// Grand totals of a set of activities, e.g. a whole folder, along with the totals of each
+/// ISO week (Monday to Sunday) they started in. Weeks go by the local date an activity
+/// started on where its timezone is known, and by the UTC date otherwise.
+#[derive(Debug, Default, PartialEq)]
+pub struct SummaryStats {
+    pub totals: FolderTotals,
+    /// Totals of each week with an activity, earliest first. Activities without a start time
+    /// only count towards [SummaryStats::totals].
+    pub weeks: Vec<(IsoWeek, FolderTotals)>,
+}
+
+impl SummaryStats {
+    pub fn new(stats: &[ActivityStats]) -> Self {
+        let mut by_week: BTreeMap<IsoWeek, Vec<&ActivityStats>> = BTreeMap::new();
+        for activity_stats in stats {
+            if let Some(date) = activity_stats.local_start_date() {
+                by_week
+                    .entry(date.iso_week())
+                    .or_default()
+                    .push(activity_stats);
+            }
+        }
+        SummaryStats {
+            totals: FolderTotals::new(stats),
+            weeks: by_week
+                .into_iter()
+                .map(|(week, stats)| (week, FolderTotals::new(stats)))
+                .collect(),
+        }
+    }
+
+    /// The grand totals, followed by a line for each week, e.g.
+    /// `2024-W09: 3 activities, 12.40mi / 19.96km, elevation gain 420, 1500 calories, avg HR 152`.
+    pub fn stats(&self) -> Vec<String> {
+        let mut stats = self.totals.stats("Summary");
+        let footer = stats.pop();
+        if !self.weeks.is_empty() {
+            stats.push(String::from("  Weekly:"));
+        }
+        for (week, totals) in &self.weeks {
+            stats.push(format!(
+                "    {}-W{:02}: {} activities, {}, elevation gain {}, {} calories, avg HR {}",
+                week.year(),
+                week.week(),
+                totals.activities,
+                totals.distance_text(),
+                elevation_text(totals.units, totals.elevation_gain),
+                totals.calories,
+                totals.average_hr
+            ));
+        }
+        stats.extend(footer);
+        stats
+    }
+
+    pub fn display(&self) {
+        for val in self.stats() {
+            println!("{}", val);
+        }
+    }
+
+    pub fn write_to<W: Write>(&self, output: &mut W) -> Result<()> {
+        output.write_all(self.stats().join("\n").as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+    use crate::test_support::{one_lap_tcx, stats_of};
+
+    /// Stats of a run starting at `start` covering `meters`, with one HR sample of `hr`.
+    fn run(start: &str, meters: f64, hr: u8) -> ActivityStats {
+        stats_of(&run_tcx(start, meters, hr))
+    }
+
+    fn run_tcx(start: &str, meters: f64, hr: u8) -> String {
+        let lap = format!(
+            "<AverageHeartRateBpm><Value>{hr}</Value></AverageHeartRateBpm><Track><Trackpoint>\
+             <Time>{start}</Time><DistanceMeters>0</DistanceMeters>\
+             <HeartRateBpm><Value>{hr}</Value></HeartRateBpm></Trackpoint></Track>"
+        );
+        one_lap_tcx("Running", start, meters, &lap)
+    }
+
+    #[test]
+    fn from_laps_shares_the_lap_totals_of_with_profile() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        let tcx = run_tcx("2024-02-27T12:00:00Z", 5000.0, 150);
+        let activity = parse_str(&tcx).unwrap();
+        let laps = ActivityStats::from_laps(
+            activity.get_activity(0).unwrap(),
+            &AthleteProfile::default(),
+        );
+        assert_eq!(laps.date, stats.date);
+        assert_eq!(laps.start_time, stats.start_time);
+        assert_eq!(laps.distance_km, stats.distance_km);
+        assert_eq!(laps.average_pace, stats.average_pace);
+        assert_eq!(laps.total_duration, stats.total_duration);
+        assert!(laps.best_efforts.is_empty());
+        assert_eq!(laps.relative_effort, 0.0);
+    }
+
+    #[test]
+    fn read_csv_reads_back_write_csv_row() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        let mut csv = vec![];
+        ActivityStats::write_csv_header(&mut csv).unwrap();
+        stats.write_csv_row(&mut csv).unwrap();
+        let read = ActivityStats::read_csv(csv.as_slice()).unwrap();
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].date, stats.date);
+        assert_eq!(read[0].start_time, stats.start_time);
+        assert_eq!(read[0].sport, Sport::Running);
+        assert_eq!(read[0].average_hr, 150);
+        assert_eq!(read[0].average_pace_seconds, stats.average_pace_seconds);
+        assert_eq!(read[0].total_duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn summary_stats_buckets_by_iso_week() {
+        let stats = vec![
+            run("2024-02-27T12:00:00Z", 5000.0, 150),
+            run("2024-03-03T12:00:00Z", 3000.0, 140),
+            run("2024-03-04T12:00:00Z", 2000.0, 160),
+        ];
+        let summary = SummaryStats::new(&stats);
+
+        assert_eq!(summary.totals.activities, 3);
+        assert!((summary.totals.distance_km - 10.0).abs() < 1e-3);
+        let weeks: Vec<(u32, usize)> = summary
+            .weeks
+            .iter()
+            .map(|(week, totals)| (week.week(), totals.activities))
+            .collect();
+        assert_eq!(weeks, vec![(9, 2), (10, 1)]);
+        assert!((summary.weeks[0].1.distance_km - 8.0).abs() < 1e-3);
+        assert_eq!(summary.weeks[0].1.average_hr, 145);
+        assert!(summary.stats().contains(&String::from(
+            "    2024-W10: 1 activities, 1.24mi / 2.00km, elevation gain 0, 0 calories, avg HR 160"
+        )));
+    }
+
+    #[test]
+    fn summary_stats_buckets_by_local_date() {
+        // Monday in UTC, but still Sunday evening in New York.
+        let mut stats = run("2024-03-04T02:00:00Z", 1000.0, 150);
+        stats.local_start = None;
+        assert_eq!(
+            stats.local_start_date(),
+            NaiveDate::from_ymd_opt(2024, 3, 4)
+        );
+        stats.local_start = Some(String::from("2024-03-03 21:00 EST"));
+        assert_eq!(
+            stats.local_start_date(),
+            NaiveDate::from_ymd_opt(2024, 3, 3)
+        );
+        let summary = SummaryStats::new(&[stats]);
+        assert_eq!(summary.weeks[0].0.week(), 9);
+    }
+
+    #[test]
+    fn calories_prefer_power_over_the_device() {
+        let tcx = r#"<TrainingCenterDatabase xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2"><Activities><Activity Sport="Biking"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>1000</TotalTimeSeconds><DistanceMeters>8000</DistanceMeters><Calories>900</Calories><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Extensions><ns3:TPX><ns3:Watts>200</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:16:40Z</Time><Extensions><ns3:TPX><ns3:Watts>200</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#;
+        let activity = parse_str(tcx).unwrap().get_activity(0).unwrap().clone();
+        assert_eq!(ActivityStats::new(&activity).calories, Some(200));
+        let laps = ActivityStats::from_laps(&activity, &AthleteProfile::default());
+        assert_eq!(laps.calories, Some(900));
+    }
+
+    #[test]
+    fn grade_adjusted_pace_needs_altitude() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        assert_eq!(stats.grade_adjusted_pace_seconds, None);
+        assert!(stats.grade_adjusted_pace.is_empty());
+        assert!(!stats.as_map().contains_key("grade_adjusted_pace"));
+    }
+
+    #[test]
+    fn write_to_writes_the_stats_lines() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        let mut output = vec![];
+        stats.write_to(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output, stats.stats().join("\n"));
+        assert!(output.contains("\n  Average HR: 150\n"), "{}", output);
+    }
+
+    #[test]
+    fn summary_line_fits_one_activity_on_a_line() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        assert_eq!(
+            stats.summary_line(),
+            "2024-02-27 Running 3.11mi 3:13/mi 150bpm"
+        );
+        let stats = stats.with_units(Units::Metric);
+        assert_eq!(
+            stats.summary_line(),
+            "2024-02-27 Running 5.00km 2:00/km 150bpm"
+        );
+    }
+
+    #[test]
+    fn summary_line_leaves_out_missing_pace_and_hr() {
+        let stats = run("2024-02-27T12:00:00Z", 0.0, 0);
+        assert_eq!(stats.summary_line(), "2024-02-27 Running 0.00mi");
+    }
+
+    #[test]
+    fn summary_by_sport_totals_each_sport_apart() {
+        let mut ride = run("2024-02-28T12:00:00Z", 20000.0, 0);
+        ride.sport = Sport::Biking;
+        let stats = [
+            run("2024-02-27T12:00:00Z", 5000.0, 140),
+            ride,
+            run("2024-02-29T12:00:00Z", 3000.0, 160),
+        ];
+        let by_sport = summary_by_sport(&stats);
+
+        assert_eq!(
+            by_sport.keys().copied().collect::<Vec<_>>(),
+            [Sport::Running, Sport::Biking]
+        );
+        let running = &by_sport[&Sport::Running];
+        assert_eq!(running.activities, 2);
+        assert!((running.distance_km - 8.0).abs() < 1e-4);
+        assert_eq!(running.average_hr, 150);
+        let biking = &by_sport[&Sport::Biking];
+        assert_eq!(biking.activities, 1);
+        assert!((biking.distance_km - 20.0).abs() < 1e-4);
+        assert_eq!(biking.average_hr, 0);
+    }
+
+    #[test]
+    fn as_map_keys_every_stat_the_activity_has() {
+        let map = run("2024-02-27T12:00:00Z", 5000.0, 150).as_map();
+        assert_eq!(
+            map["date"],
+            StatValue::Str(String::from("2024-02-27T12:00:00Z"))
+        );
+        assert_eq!(map["sport"], StatValue::Str(String::from("Running")));
+        assert_eq!(map["laps"], StatValue::Int(1));
+        assert_eq!(map["average_hr"], StatValue::Int(150));
+        assert_eq!(
+            map["total_duration"],
+            StatValue::Duration(Duration::from_secs(600))
+        );
+        assert!(map.contains_key("hr_zone_1"));
+        // Without power, work and watts per kg are left out.
+        assert!(!map.contains_key("total_work_kj"));
+        assert!(!map.contains_key("watts_per_kg"));
+        assert!(!map.keys().any(|key| key.starts_with("best_effort_")));
+    }
+
+    #[test]
+    fn decimal_comma_applies_to_text_and_csv_only() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150)
+            .with_decimal_separator(DecimalSeparator::Comma);
+        assert!(stats.summary_line().contains(" 3,11mi "));
+        assert!(stats.stats().iter().any(|line| line.contains("3,11")));
+
+        let mut csv = vec![];
+        stats.write_csv_row(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("2024-02-27T12:00:00Z,1,\"3,11\",\"5,00\","));
+
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        assert!((json["distance_km"].as_f64().unwrap() - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn combined_treats_the_activities_as_one() {
+        let stats = [
+            run("2024-02-27T12:00:00Z", 5000.0, 140),
+            run("2024-02-29T12:00:00Z", 3000.0, 170),
+        ];
+        let combined = ActivityStats::combined(&stats).unwrap();
+        assert_eq!(combined.date, "2024-02-27T12:00:00Z");
+        assert_eq!(combined.sport, Sport::Running);
+        assert_eq!(combined.laps, 2);
+        assert!((combined.distance_km - 8.0).abs() < 1e-4);
+        assert_eq!(combined.total_duration, Duration::from_secs(1200));
+        assert_eq!(combined.average_hr, 155);
+        let pace: f64 = 1200.0 / (8.0 / 1.609344);
+        assert_eq!(combined.average_pace_seconds.as_secs(), pace.round() as u64);
+    }
+
+    #[test]
+    fn combining_sports_gives_other() {
+        let mut ride = run("2024-02-28T12:00:00Z", 20000.0, 0);
+        ride.sport = Sport::Biking;
+        let combined = ActivityStats::combined(&[run("2024-02-27T12:00:00Z", 5000.0, 150), ride]);
+        let combined = combined.unwrap();
+        assert_eq!(combined.sport, Sport::Other);
+        // Only the run recorded HR.
+        assert_eq!(combined.average_hr, 150);
+        assert!(ActivityStats::combined(&[]).is_none());
+    }
+
+    #[test]
+    fn multi_day_activities_keep_their_start_date_and_hours() {
+        // 26:15:03 from late on the 29th, with trackpoints into March 1st.
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-02-29T22:00:00Z</Id>
+<Lap StartTime="2024-02-29T22:00:00Z"><TotalTimeSeconds>94503</TotalTimeSeconds><DistanceMeters>160000</DistanceMeters><Track>
+<Trackpoint><Time>2024-02-29T22:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-02T00:15:03Z</Time><DistanceMeters>160000</DistanceMeters></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#;
+        let stats = stats_of(tcx);
+        assert_eq!(stats.start_date(), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert!(stats.stats().iter().any(|line| line.contains("26:15:03")));
+        assert_eq!(
+            SummaryStats::new(&[stats]).weeks[0].0,
+            NaiveDate::from_ymd_opt(2024, 2, 29).unwrap().iso_week()
+        );
+    }
+
+    #[test]
+    fn best_efforts_of_an_hour_or_more_show_hours() {
+        assert_eq!(format_minutes(Duration::from_secs(59 * 60 + 59)), "59:59");
+        assert_eq!(format_minutes(Duration::from_secs(3600)), "1:00:00");
+        assert_eq!(format_duration(Duration::from_secs(94503)), "26:15:03");
+    }
+
+    #[test]
+    fn lap_comparison_table_marks_the_fastest_lap() {
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>420</TotalTimeSeconds><DistanceMeters>1609.344</DistanceMeters><AverageHeartRateBpm><Value>150</Value></AverageHeartRateBpm></Lap>
+<Lap StartTime="2024-03-01T12:07:00Z"><TotalTimeSeconds>240</TotalTimeSeconds><DistanceMeters>800</DistanceMeters></Lap>
+<Lap StartTime="2024-03-01T12:11:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>0</DistanceMeters></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#;
+        let parsed = parse_str(tcx).unwrap();
+        let table = lap_comparison_table(parsed.get_activity(0).unwrap());
+        assert_eq!(
+            table.lines().collect::<Vec<_>>(),
+            [
+                "  Lap  Distance     Time      Pace     HR  Power",
+                "*   1    1.00mi     7:00   7:00/mi    150      -",
+                "    2    0.50mi     4:00   8:03/mi      -      -",
+                "    3    0.00mi     1:00         -      -      -",
+            ]
+        );
+    }
+
+    #[test]
+    fn units_change_only_the_text_output() {
+        let imperial = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        let metric = run("2024-02-27T12:00:00Z", 5000.0, 150).with_units(Units::Metric);
+        let lines = |stats: &ActivityStats| stats.stats().join("\n");
+        assert!(lines(&imperial).contains("  Distance: 3.11mi / 5.00km\n"));
+        assert!(lines(&imperial).contains("  Average Pace: 03:13 / mi\n"));
+        assert!(lines(&metric).contains("  Distance: 5.00km\n"));
+        assert!(lines(&metric).contains("  Average Pace: 02:00 / km\n"));
+        assert_eq!(imperial.to_json().unwrap(), metric.to_json().unwrap());
+
+        let totals = FolderTotals::new([&metric]).stats("Total").join("\n");
+        assert!(totals.contains("5.00km"));
+        assert!(!totals.contains("mi"));
+    }
+
+    #[test]
+    fn time_in_zones_uses_the_profile_boundaries() {
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>180</TotalTimeSeconds><DistanceMeters>600</DistanceMeters>
+<Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><HeartRateBpm><Value>130</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:01:00Z</Time><HeartRateBpm><Value>150</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:02:00Z</Time></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:03:00Z</Time><HeartRateBpm><Value>150</Value></HeartRateBpm></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#;
+        let database = parse_str(tcx).unwrap();
+        let activity = database.get_activity(0).unwrap();
+        let profile = AthleteProfile {
+            hr_zones: Some(vec![140]),
+            ..AthleteProfile::default()
+        };
+        let stats = ActivityStats::with_profile(activity, &profile);
+        // The minute starting without HR is in neither zone.
+        assert!(stats
+            .stats()
+            .contains(&"  Time in Zones: Z1 1:00, Z2 1:00".to_string()));
+
+        // The other run has no time in any of its default zones.
+        let combined = ActivityStats::combined(&[stats, run("2024-03-02T12:00:00Z", 1000.0, 150)]);
+        let zones = combined.unwrap().hr_zone_durations;
+        assert_eq!(zones.len(), 6);
+        assert_eq!(
+            zones[..2],
+            [Duration::from_secs(60), Duration::from_secs(60)]
+        );
+        assert!(zones[2..].iter().all(Duration::is_zero));
+        assert!(!run("2024-03-02T12:00:00Z", 1000.0, 150)
+            .stats()
+            .iter()
+            .any(|line| line.contains("Time in Zones")));
+    }
+
+    #[test]
+    fn csv_rows_line_up_with_the_header() {
+        let stats = run("2024-02-27T12:00:00Z", 5000.0, 150);
+        let json: serde_json::Value = serde_json::from_str(&stats.to_json().unwrap()).unwrap();
+        assert_eq!(json["average_hr"], 150);
+
+        let mut csv = vec![];
+        ActivityStats::write_csv_header(&mut csv).unwrap();
+        stats.write_csv_row(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let header = split_csv_row(lines[0]);
+        assert_eq!(split_csv_row(lines[1]).len(), header.len());
+        assert!(header.iter().any(|column| column == "average_hr"));
+    }
+
+    #[test]
+    fn calories_add_up_across_laps() {
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters><Calories>150</Calories></Lap>
+<Lap StartTime="2024-03-01T12:10:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters><Calories>250</Calories></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#;
+        let database = parse_str(tcx).unwrap();
+        let stats = ActivityStats::new(database.get_activity(0).unwrap());
+        assert_eq!(stats.calories, Some(400));
+        assert!(stats.stats().contains(&"  Calories: 400".to_string()));
+    }
 }
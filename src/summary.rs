@@ -0,0 +1,245 @@
+use std::{io::Read, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::Sport;
+
+/// Lap-level totals of an activity, read without building any trackpoints. See
+/// [crate::parse_file_summary].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivitySummary {
+    pub id: String,
+    pub sport: Sport,
+    pub laps: Vec<LapSummary>,
+}
+
+/// The aggregates a device records for each lap.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LapSummary {
+    pub start_time: Option<DateTime<Utc>>,
+    pub seconds: f32,
+    /// Distance of this lap in meters.
+    pub distance: f32,
+    pub calories: Option<usize>,
+    pub average_hr: Option<usize>,
+    pub maximum_hr: Option<usize>,
+}
+
+impl ActivitySummary {
+    pub fn total_distance_meters(&self) -> f32 {
+        self.laps.iter().map(|l| l.distance).sum()
+    }
+
+    /// Total time of every lap.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.laps.iter().map(|l| l.seconds.max(0.0)).sum())
+    }
+
+    /// Average of the laps' average HR, weighted by lap time. `None` if no lap recorded HR.
+    pub fn average_hr(&self) -> Option<usize> {
+        let (weighted, seconds) = self
+            .laps
+            .iter()
+            .filter_map(|l| Some((l.average_hr? as f32, l.seconds)))
+            .fold((0.0, 0.0), |(weighted, total), (hr, seconds)| {
+                (weighted + hr * seconds, total + seconds)
+            });
+        (seconds > 0.0).then(|| (weighted / seconds).round() as usize)
+    }
+
+    /// Reads the first activity of a TCX document. Plain `<Track>` elements are cut out of
+    /// the text before it's parsed, so their trackpoints aren't even tokenized.
+    pub fn from_tcx(data: &str) -> Result<Self> {
+        ActivitySummary::from_reader(strip_tracks(data).as_bytes())
+    }
+
+    /// Reads the first activity of a TCX document, skipping over the contents of every
+    /// `Track` so no trackpoints are kept.
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut summary: Option<ActivitySummary> = None;
+        // Names of the currently open elements, and text of the innermost one.
+        let mut path: Vec<String> = vec![];
+        let mut text = String::new();
+        // How many elements deep within a Track we are; everything in there is ignored.
+        let mut track_depth = 0;
+
+        for event in EventReader::new(reader) {
+            match event? {
+                XmlEvent::StartElement { .. } if track_depth > 0 => track_depth += 1,
+                XmlEvent::EndElement { .. } if track_depth > 0 => track_depth -= 1,
+                XmlEvent::StartElement { name, .. } if name.local_name == "Track" => {
+                    track_depth = 1;
+                }
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    let attribute = |key: &str| {
+                        attributes
+                            .iter()
+                            .find(|a| a.name.local_name == key)
+                            .map(|a| a.value.clone())
+                    };
+                    let parent = path.last().map(String::as_str);
+                    match (name.local_name.as_str(), parent, &mut summary) {
+                        ("Activity", _, None) => {
+                            summary = Some(ActivitySummary {
+                                id: String::new(),
+                                sport: Sport::from(attribute("Sport").unwrap_or_default()),
+                                laps: vec![],
+                            });
+                        }
+                        ("Lap", Some("Activity"), Some(summary)) => {
+                            summary.laps.push(LapSummary {
+                                start_time: attribute("StartTime")
+                                    .and_then(|t| DateTime::parse_from_rfc3339(&t).ok())
+                                    .map(|t| t.with_timezone(&Utc)),
+                                ..Default::default()
+                            });
+                        }
+                        _ => {}
+                    }
+                    path.push(name.local_name);
+                    text.clear();
+                }
+                XmlEvent::Characters(chars) if track_depth == 0 => text.push_str(&chars),
+                XmlEvent::EndElement { name } => {
+                    path.pop();
+                    if let Some(summary) = summary.as_mut() {
+                        if name.local_name == "Activity" {
+                            break;
+                        }
+                        summary.set_field(&path, &name.local_name, text.trim());
+                    }
+                    text.clear();
+                }
+                _ => {}
+            }
+        }
+        summary.ok_or_else(|| anyhow!("No activity found"))
+    }
+
+    /// Stores the text of the element `name`, whose parents are `path`.
+    fn set_field(&mut self, path: &[String], name: &str, text: &str) {
+        let parent = path.last().map(String::as_str);
+        if name == "Id" && parent == Some("Activity") {
+            self.id = text.to_string();
+            return;
+        }
+        let Some(lap) = self.laps.last_mut() else {
+            return;
+        };
+        match (parent, name) {
+            (Some("Lap"), "TotalTimeSeconds") => lap.seconds = text.parse().unwrap_or_default(),
+            (Some("Lap"), "DistanceMeters") => lap.distance = text.parse().unwrap_or_default(),
+            (Some("Lap"), "Calories") => lap.calories = text.parse().ok(),
+            (Some("AverageHeartRateBpm"), "Value") => lap.average_hr = text.parse().ok(),
+            (Some("MaximumHeartRateBpm"), "Value") => lap.maximum_hr = text.parse().ok(),
+            _ => {}
+        }
+    }
+}
+
+/// `data` without the contents of any `<Track>` element. Tracks written some other way
+/// (e.g. with a namespace prefix, or self-closing) are left in place for
+/// [ActivitySummary::from_reader] to skip, as is anything inside comments.
+fn strip_tracks(data: &str) -> String {
+    let mut stripped = String::with_capacity(data.len());
+    let mut rest = data;
+    loop {
+        let comment = rest.find("<!--");
+        let Some(start) = rest.find("<Track>") else {
+            break;
+        };
+        if let Some(comment) = comment.filter(|&comment| comment < start) {
+            let end = rest[comment..]
+                .find("-->")
+                .map_or(rest.len(), |end| comment + end + "-->".len());
+            stripped.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let Some(end) = rest[start..].find("</Track>") else {
+            break;
+        };
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + end + "</Track>".len()..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An activity with one lap of 2 trackpoints, written with `track` in place of the
+    /// lap's track.
+    fn tcx(track: &str) -> String {
+        format!(
+            r#"<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:tcx="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2">
+<Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>1800</DistanceMeters><Calories>100</Calories>
+<AverageHeartRateBpm><Value>150</Value></AverageHeartRateBpm><MaximumHeartRateBpm><Value>170</Value></MaximumHeartRateBpm>
+{}
+</Lap></Activity></Activities></TrainingCenterDatabase>"#,
+            track
+        )
+    }
+
+    fn expected() -> ActivitySummary {
+        ActivitySummary {
+            id: String::from("2024-03-01T12:00:00Z"),
+            sport: Sport::Running,
+            laps: vec![LapSummary {
+                start_time: DateTime::parse_from_rfc3339("2024-03-01T12:00:00Z")
+                    .ok()
+                    .map(|t| t.with_timezone(&Utc)),
+                seconds: 600.0,
+                distance: 1800.0,
+                calories: Some(100),
+                average_hr: Some(150),
+                maximum_hr: Some(170),
+            }],
+        }
+    }
+
+    #[test]
+    fn from_tcx_skips_plain_tracks() {
+        let data = tcx("<Track><Trackpoint><Time>2024-03-01T12:00:00Z</Time>\
+             <DistanceMeters>5</DistanceMeters><HeartRateBpm><Value>90</Value></HeartRateBpm>\
+             </Trackpoint></Track>");
+        assert_eq!(ActivitySummary::from_tcx(&data).unwrap(), expected());
+    }
+
+    #[test]
+    fn from_tcx_skips_self_closing_tracks() {
+        let data = tcx("<Track/><Track />");
+        assert_eq!(ActivitySummary::from_tcx(&data).unwrap(), expected());
+    }
+
+    #[test]
+    fn from_tcx_skips_namespaced_tracks() {
+        let data = tcx(
+            "<tcx:Track><tcx:Trackpoint><tcx:Time>2024-03-01T12:00:00Z</tcx:Time>\
+             <tcx:DistanceMeters>5</tcx:DistanceMeters></tcx:Trackpoint></tcx:Track>",
+        );
+        assert_eq!(ActivitySummary::from_tcx(&data).unwrap(), expected());
+    }
+
+    #[test]
+    fn from_tcx_leaves_tracks_in_comments_alone() {
+        let data = tcx("<!-- <Track> was empty --><Track><Trackpoint>\
+             <Time>2024-03-01T12:00:00Z</Time></Trackpoint></Track>");
+        assert_eq!(ActivitySummary::from_tcx(&data).unwrap(), expected());
+    }
+
+    #[test]
+    fn strip_tracks_only_cuts_plain_tracks() {
+        assert_eq!(
+            strip_tracks("<Lap><Track><Trackpoint/></Track><Track/></Lap>"),
+            "<Lap><Track/></Lap>"
+        );
+    }
+}
@@ -1,19 +1,77 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeDelta, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    units::{FEET_PER_METER, METERS_PER_MILE, MILES_PER_METER},
+    zones::{HrZones, PowerZones},
+};
+
+mod analysis;
+mod columns;
+mod coverage;
+mod export;
+mod multisport;
+mod splits;
+mod stationary;
+#[cfg(feature = "timezone")]
+mod timezone;
+pub use analysis::AnalysisResult;
+pub use columns::TrackColumns;
+pub use coverage::DataCoverage;
+pub use export::gpx_document;
+pub use multisport::{MultisportActivity, MultisportLeg};
+pub use splits::Split;
+pub use stationary::StationaryPeriod;
 
-static FEET_PER_METER: f64 = 3.28084;
-static METERS_PER_MILE: f32 = 1609.344;
 static ALTITUDE_THRESHOLD: f64 = 1.0;
+static EARTH_RADIUS_METERS: f64 = 6_371_000.0;
+/// Number of trailing trackpoints (~seconds at 1Hz recording) smoothed pace is measured over.
+static PACE_SMOOTHING_WINDOW: usize = 30;
+/// Fewest smoothed pace samples needed before fitting a pace fade trend.
+static MIN_FADE_SAMPLES: usize = 60;
+/// Fraction by which device-reported and GPS-computed distance may differ before being flagged.
+static DISTANCE_DISCREPANCY_THRESHOLD: f32 = 0.05;
+/// How far the typical trackpoint interval may be from 1 second before being flagged.
+static SAMPLING_INTERVAL_TOLERANCE: Duration = Duration::from_millis(500);
+/// Fraction of trackpoint intervals that may be far from the typical one before being flagged.
+static IRREGULAR_SAMPLING_THRESHOLD: f32 = 0.25;
+/// Reported distances more than this many times larger or smaller than the GPS distance
+/// are most likely in the wrong unit, rather than just GPS drift.
+static PLAUSIBLE_DISTANCE_RATIO: f32 = 2.0;
+/// Lap distances adding up to less than this many meters are treated as not recorded.
+static UNRECORDED_DISTANCE_METERS: f32 = 1.0;
+/// Slowest speed, in meters/s, at which a trackpoint interval counts as moving.
+static MIN_MOVING_SPEED: f32 = 0.5;
+/// Steepest grade, in percent, still considered flat when looking for steady efforts.
+static STEADY_MAX_GRADE: f64 = 2.0;
+/// Fraction by which pace may differ from the typical flat pace while still being steady.
+static STEADY_PACE_TOLERANCE: f32 = 0.05;
+
+/// Standard race distances reported by [Activity::best_efforts], with their names.
+pub static BEST_EFFORT_DISTANCES: [(&str, f32); 5] = [
+    ("400m", 400.0),
+    ("1K", 1000.0),
+    ("1mi", METERS_PER_MILE),
+    ("5K", 5000.0),
+    ("10K", 10000.0),
+];
 
 /// Root node of the TCX document
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TrainingCenterDatabase {
-    #[serde(rename = "Activities")]
-    pub activities: Activities,
+    /// Recorded activities. Course files have none.
+    #[serde(rename = "Activities", default)]
+    pub activities: Option<Activities>,
+
+    /// Planned routes.
+    #[serde(rename = "Courses", default)]
+    pub courses: Option<Courses>,
 }
 
 /// Contains a list of activities within this file
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Activities {
     #[serde(rename = "Activity")]
     pub activities: Vec<Activity>,
@@ -21,43 +79,109 @@ pub struct Activities {
 
 /// An individual activity, containing high level information
 /// about the activity as well as all specific data points.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Activity {
     #[serde(rename = "Sport")]
-    pub sport: String,
+    pub sport: Sport,
 
-    /// The id for the activity, often the UTC timestamp of the activity start time.
-    #[serde(rename = "Id")]
+    /// The id for the activity, often the UTC timestamp of the activity start time. Files
+    /// with a blank or missing `Id` get their [Activity::effective_id] when parsed.
+    #[serde(rename = "Id", default)]
     pub id: String, // TODO: Is it guaranteed this is a timestamp? Could use DateTime<Utc> here.
 
     #[serde(rename = "Lap")]
     pub laps: Vec<Lap>,
 
-    #[serde(rename = "Creator")]
+    #[serde(rename = "Creator", default)]
     pub creator: Creator,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// Contains a list of courses within this file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Courses {
+    #[serde(rename = "Course", default)]
+    pub courses: Vec<Course>,
+}
+
+/// A planned route, rather than a recorded activity.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Course {
+    #[serde(rename = "Name")]
+    pub name: String,
+
+    #[serde(rename = "Lap", default)]
+    laps: Vec<CourseLap>,
+
+    #[serde(rename = "Track", default)]
+    tracks: Vec<Track>,
+}
+
+/// Planned time and distance for a section of a course.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct CourseLap {
+    #[serde(rename = "TotalTimeSeconds", deserialize_with = "lenient_f32")]
+    seconds: f32,
+
+    /// Distance of this section in meters.
+    #[serde(rename = "DistanceMeters", deserialize_with = "lenient_f32")]
+    distance: f32,
+}
+
+/// The kind of activity, from the `Sport` attribute. The TCX schema only defines
+/// Running, Biking and Other, but some apps write other values; anything unrecognized
+/// becomes [Sport::Other].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Sport {
+    Running,
+    Biking,
+    Swimming,
+    Walking,
+    Other,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct Creator {
     /// Device name that created this activity.
     #[serde(rename = "Name")]
     name: String,
+
+    /// Serial number of the device, 0 when not recorded.
+    #[serde(rename = "UnitId", default)]
+    unit_id: u32,
+
+    #[serde(rename = "ProductID", default)]
+    product_id: u16,
+
+    /// Firmware version of the device.
+    #[serde(rename = "Version", default)]
+    version: CreatorVersion,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+struct CreatorVersion {
+    #[serde(rename = "VersionMajor", default)]
+    major: u16,
+
+    #[serde(rename = "VersionMinor", default)]
+    minor: u16,
 }
 
 /// Specific data for each Lap of the activity
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Lap {
     #[serde(rename = "StartTime")]
     pub start_time: DateTime<Utc>,
 
-    #[serde(rename = "TotalTimeSeconds")]
+    #[serde(rename = "TotalTimeSeconds", deserialize_with = "lenient_f32")]
     pub seconds: f32,
 
-    #[serde(rename = "Calories")]
-    pub calories: usize,
+    /// Calories burned as estimated by the device. Minimal files may leave this out.
+    #[serde(rename = "Calories", default)]
+    pub calories: Option<usize>,
 
     /// Distance travelled in meters.
-    #[serde(rename = "DistanceMeters")]
+    #[serde(rename = "DistanceMeters", deserialize_with = "lenient_f32")]
     pub distance: f32,
 
     /// Average HR for this lap
@@ -68,10 +192,16 @@ pub struct Lap {
     #[serde(rename = "MaximumHeartRateBpm")]
     maximum_hr: Option<HRValue>,
 
-    #[serde(rename = "Track")]
+    /// Average cadence for this lap. Bike computers record crank revolutions per minute
+    /// here, while running cadence goes in the LX extension instead.
+    #[serde(rename = "Cadence", default)]
+    cadence: Option<usize>,
+
+    /// Laps without any recorded data (e.g. pool swims) may omit the track.
+    #[serde(rename = "Track", default)]
     track: Track,
 
-    #[serde(rename = "Extensions")]
+    #[serde(rename = "Extensions", default)]
     extensions: Vec<LapExtension>, // TODO: Other fields - Intensity, TriggerMethod, MaximumSpeed
 
     /// Fields not parsed but used to calculate altitude gain/loss across [TrackPoints]
@@ -83,23 +213,25 @@ pub struct Lap {
     alt_loss_meters: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct HRValue {
-    #[serde(rename = "$value")]
+    /// Some exporters write HR as a decimal (`150.0`), which is rounded to whole bpm.
+    #[serde(rename = "$value", deserialize_with = "lenient_usize")]
     value: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct LapExtension {
+    /// Absent when the extensions only hold vendor-specific data.
     #[serde(rename = "LX")]
-    lx: LXExtension,
+    lx: Option<LXExtension>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct LXExtension {
     // TODO: What is this unit of measurement? m/s?
     #[serde(rename = "AvgSpeed")]
-    avg_speed: f64,
+    avg_speed: Option<f64>,
 
     /// Average cadence in steps per minute for this lap. This is the steps done by one foot,
     /// so doubling the number gives a more typical cadence measurement.
@@ -120,79 +252,394 @@ struct LXExtension {
     max_watts: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 struct Track {
-    #[serde(rename = "Trackpoint")]
+    #[serde(rename = "Trackpoint", default)]
     track_points: Vec<TrackPoint>,
 }
 
 /// There is a trackpoint every second for this activity
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct TrackPoint {
-    #[serde(rename = "Time")]
+    /// A few writers leave out some trackpoints' time. Those get [missing_time] until
+    /// [Activity::interpolate_missing_times] fills it in.
+    #[serde(rename = "Time", default = "missing_time")]
     time: DateTime<Utc>,
 
     #[serde(rename = "HeartRateBpm")]
     hr: Option<HRValue>,
 
-    /// Distance (in meters) travelled
-    #[serde(rename = "DistanceMeters")]
+    /// Distance (in meters) travelled. Indoor sessions on a trainer often record none, in
+    /// which case it's 0.
+    #[serde(rename = "DistanceMeters", default, deserialize_with = "lenient_f32")]
     distance: f32,
 
     /// Current altitude (in meters)
     #[serde(rename = "AltitudeMeters")]
     altitude: Option<f64>,
 
-    /// Current Lat/Long position
-    #[serde(rename = "Position")]
+    /// Current Lat/Long position. GPS glitches reporting an impossible position
+    /// (see [Position::is_valid]) are treated as having no position.
+    #[serde(rename = "Position", default, deserialize_with = "valid_position")]
     position: Option<Position>,
 
-    #[serde(rename = "Extensions")]
+    /// Course trackpoints don't carry any extensions.
+    #[serde(rename = "Extensions", default)]
     extensions: Vec<TrackpointExtension>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
-struct Position {
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Position {
     /// Latitude: Positive number indicates north of equator, negative indicates south.
     #[serde(rename = "LatitudeDegrees")]
-    lat: f64,
+    pub lat: f64,
 
     /// Longitude: Positive number indicates east of the prime meridian, negative indicates west.
     #[serde(rename = "LongitudeDegrees")]
-    long: f64,
+    pub long: f64,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct TrackpointExtension {
-    #[serde(rename = "TPX")]
-    tpx: TPXExtension,
+    /// Empty when the extensions only hold vendor-specific data. Some writers split the
+    /// values across several TPX elements, and any other elements are ignored.
+    #[serde(rename = "TPX", default)]
+    tpx: Vec<TPXExtension>,
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 struct TPXExtension {
-    // TODO: What is this unit of measurement? m/s?
+    /// Current speed in meters/s.
     #[serde(rename = "Speed")]
     speed: Option<f64>,
 
     /// Current cadence in steps per minute. This is the steps done by one foot,
     /// so doubling the number gives a more typical cadence measurement.
     #[serde(rename = "RunCadence")]
-    cadence: usize,
+    cadence: Option<usize>,
 
     /// Current watts as estimated by the device.
     #[serde(rename = "Watts")]
     watts: Option<usize>,
 }
 
+/// How lap elevation gain and loss are measured from the altitude samples. Barometric
+/// altimeters and GPS-only devices differ in how noisy their altitude is, so what works
+/// for one over- or under-counts climbing on another.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElevationOptions {
+    /// Smallest change in meters from the last counted altitude that counts as climbing or
+    /// descending. Higher values ignore more noise, but also more real rolling terrain.
+    pub threshold_meters: f64,
+    /// Number of altitude samples averaged around each one before measuring, centered on
+    /// it. `1` leaves the altitudes as recorded; larger windows even out jitter, reporting
+    /// less gain on noisy recordings.
+    pub smoothing_window: usize,
+}
+
+impl Default for ElevationOptions {
+    fn default() -> Self {
+        ElevationOptions {
+            threshold_meters: ALTITUDE_THRESHOLD,
+            smoothing_window: 1,
+        }
+    }
+}
+
+/// A latitude/longitude rectangle, for [Activity::clip_to_bounds]. Boxes crossing the
+/// antimeridian aren't supported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lat: f64,
+    pub min_long: f64,
+    pub max_lat: f64,
+    pub max_long: f64,
+}
+
+impl BoundingBox {
+    /// Whether `position` is inside the box, or on its edge.
+    pub fn contains(&self, position: &Position) -> bool {
+        (self.min_lat..=self.max_lat).contains(&position.lat)
+            && (self.min_long..=self.max_long).contains(&position.long)
+    }
+}
+
+/// Bounds on plausible instantaneous pace, in seconds per mile. Pace samples outside of
+/// them are treated as GPS glitches and left out of pace series.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaceClamp {
+    pub fastest: f32,
+    pub slowest: f32,
+}
+
+impl Default for PaceClamp {
+    /// 2:00/mi is well beyond a world-record sprint, and 60:00/mi is effectively standing still.
+    fn default() -> Self {
+        PaceClamp {
+            fastest: 120.0,
+            slowest: 3600.0,
+        }
+    }
+}
+
+impl PaceClamp {
+    pub fn contains(&self, seconds_per_mile: f32) -> bool {
+        (self.fastest..=self.slowest).contains(&seconds_per_mile)
+    }
+}
+
+impl From<String> for Sport {
+    fn from(sport: String) -> Self {
+        match sport.as_str() {
+            "Running" => Sport::Running,
+            "Biking" => Sport::Biking,
+            "Swimming" => Sport::Swimming,
+            "Walking" => Sport::Walking,
+            _ => Sport::Other,
+        }
+    }
+}
+
+impl From<Sport> for String {
+    fn from(sport: Sport) -> Self {
+        sport.to_string()
+    }
+}
+
+impl fmt::Display for Sport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Sport::Running => "Running",
+            Sport::Biking => "Biking",
+            Sport::Swimming => "Swimming",
+            Sport::Walking => "Walking",
+            Sport::Other => "Other",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A data-quality problem found by [Activity::validate].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationWarning {
+    /// Device-reported distance divided by GPS-computed distance is this far from 1.0.
+    DistanceDiscrepancy(f32),
+    /// This many trackpoints have a lower cumulative distance than the one before them.
+    DecreasingDistance(usize),
+    /// Trackpoints are typically this far apart rather than ~1 second.
+    SamplingInterval(Duration),
+    /// This fraction of trackpoint intervals differ a lot from the typical one, as with
+    /// "smart recording".
+    IrregularSampling(f32),
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationWarning::DistanceDiscrepancy(ratio) => write!(
+                f,
+                "Device distance is {:.1}% of GPS distance",
+                ratio * 100.0
+            ),
+            ValidationWarning::DecreasingDistance(count) => write!(
+                f,
+                "Distance decreases at {} trackpoints; see Activity::repair_distances",
+                count
+            ),
+            ValidationWarning::SamplingInterval(interval) => write!(
+                f,
+                "Trackpoints are recorded every {:.1}s rather than every second",
+                interval.as_secs_f32()
+            ),
+            ValidationWarning::IrregularSampling(fraction) => write!(
+                f,
+                "{:.0}% of trackpoint intervals are irregular; time-weighted stats may be off",
+                fraction * 100.0
+            ),
+        }
+    }
+}
+
+/// Parses a numeric element that writers emit either as an integer (`5000`) or a
+/// decimal (`5000.0`), going through the raw text rather than the deserializer's
+/// typed number handling so both spellings land in the same `f32` field.
+fn lenient_f32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    raw.trim()
+        .parse::<f64>()
+        .map(|v| v as f32)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Like [lenient_f32], for whole numbers: decimals are rounded to the nearest integer.
+fn lenient_usize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<usize, D::Error> {
+    let raw = String::deserialize(deserializer)?;
+    let value = raw
+        .trim()
+        .parse::<f64>()
+        .map_err(serde::de::Error::custom)?;
+    if value.is_nan() || value < 0.0 {
+        return Err(serde::de::Error::custom(format!(
+            "expected a non-negative number, got {}",
+            raw
+        )));
+    }
+    Ok(value.round() as usize)
+}
+
+fn valid_position<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Position>, D::Error> {
+    let position = Option::<Position>::deserialize(deserializer)?;
+    Ok(position.filter(Position::is_valid))
+}
+
+/// Minutes spent in each HR zone, weighted by the zone's index, as used for TRIMP.
+fn weighted_zone_minutes(zones: &[Duration]) -> f64 {
+    zones
+        .iter()
+        .enumerate()
+        .map(|(weight, time)| weight as f64 * time.as_secs_f64() / 60.0)
+        .sum()
+}
+
+/// Energy cost of running in J/kg/m at `grade` (rise over run, not percent), from
+/// Minetti et al. (2002). The fit was measured between -45% and 45%, so grades are
+/// clamped to that range.
+fn running_cost(grade: f64) -> f64 {
+    let i = grade.clamp(-0.45, 0.45);
+    155.4 * i.powi(5) - 30.4 * i.powi(4) - 43.3 * i.powi(3) + 46.3 * i.powi(2) + 19.5 * i + 3.6
+}
+
+/// Replaces each recorded altitude with the mean of the `window` recorded altitudes centered
+/// on it. Missing altitudes stay missing, and a `window` of 1 or less changes nothing.
+fn smooth_altitudes(altitudes: &mut [Option<f64>], window: usize) {
+    if window <= 1 {
+        return;
+    }
+    let present: Vec<(usize, f64)> = altitudes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, alt)| alt.map(|a| (i, a)))
+        .collect();
+    let half = window / 2;
+    for (n, (idx, _)) in present.iter().enumerate() {
+        let window = &present[n.saturating_sub(half)..(n + half + 1).min(present.len())];
+        let mean = window.iter().map(|(_, a)| a).sum::<f64>() / window.len() as f64;
+        altitudes[*idx] = Some(mean);
+    }
+}
+
+/// Replaces each recorded altitude deviating more than `max_deviation` meters from the
+/// median of itself and its recorded neighbors with that median. Missing altitudes are
+/// skipped over, and the first and last recorded ones are kept.
+fn despike_altitudes(altitudes: &mut [Option<f64>], max_deviation: f64) {
+    let present: Vec<(usize, f64)> = altitudes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, alt)| alt.map(|a| (i, a)))
+        .collect();
+    for window in present.windows(3) {
+        let mut neighborhood = [window[0].1, window[1].1, window[2].1];
+        neighborhood.sort_by(|a, b| a.total_cmp(b));
+        let (idx, altitude) = window[1];
+        if (altitude - neighborhood[1]).abs() > max_deviation {
+            altitudes[idx] = Some(neighborhood[1]);
+        }
+    }
+}
+
+/// Climbing and descending over a stretch of altitude samples, see [ElevationChange::of].
+struct ElevationChange {
+    gain_meters: f64,
+    loss_meters: f64,
+    /// The last altitude counted, which later changes are measured from.
+    last_alt: f64,
+}
+
+impl ElevationChange {
+    /// Totals the climbing and descending in `altitudes`, only counting changes of at least
+    /// `threshold` meters from the last counted altitude. It's measured from the first
+    /// recorded one, so a lap starting without altitude doesn't count a climb up from 0.
+    fn of(altitudes: &[Option<f64>], threshold: f64) -> Self {
+        let mut change = ElevationChange {
+            gain_meters: 0.0,
+            loss_meters: 0.0,
+            last_alt: altitudes.iter().flatten().next().copied().unwrap_or(0.0),
+        };
+        for altitude in altitudes.iter().flatten().copied() {
+            let alt_change = (altitude - change.last_alt).abs();
+            if alt_change < threshold {
+                continue;
+            }
+
+            if altitude > change.last_alt {
+                change.gain_meters += alt_change;
+            } else {
+                change.loss_meters += alt_change;
+            }
+
+            change.last_alt = altitude;
+        }
+        change
+    }
+}
+
+/// Placeholder time of trackpoints that didn't have one.
+fn missing_time() -> DateTime<Utc> {
+    DateTime::<Utc>::MIN_UTC
+}
+
 /************* IMPLS **************/
 
 impl TrainingCenterDatabase {
     pub fn get_activity(&self, idx: usize) -> Option<&Activity> {
-        self.activities.activities.get(idx)
+        self.activities.as_ref()?.activities.get(idx)
     }
 
     pub fn get_activity_mut(&mut self, idx: usize) -> Option<&mut Activity> {
-        self.activities.activities.get_mut(idx)
+        self.activities.as_mut()?.activities.get_mut(idx)
+    }
+
+    /// Every activity in the file, in order. Most files hold one, but multisport and brick
+    /// workouts are often exported as an activity per leg.
+    pub fn activities_iter(&self) -> impl Iterator<Item = &Activity> {
+        self.activities.iter().flat_map(|a| a.activities.iter())
+    }
+
+    pub fn activities_iter_mut(&mut self) -> impl Iterator<Item = &mut Activity> {
+        self.activities
+            .iter_mut()
+            .flat_map(|a| a.activities.iter_mut())
+    }
+
+    pub fn courses(&self) -> &[Course] {
+        self.courses.as_ref().map_or(&[], |c| c.courses.as_slice())
+    }
+}
+
+impl Course {
+    /// Planned distance in meters, from the course laps when present and otherwise the
+    /// cumulative distance of the last trackpoint.
+    pub fn total_distance_meters(&self) -> f32 {
+        if !self.laps.is_empty() {
+            return self.laps.iter().map(|l| l.distance).sum();
+        }
+        self.track_points().last().map_or(0.0, |tp| tp.distance)
+    }
+
+    /// Planned duration from the course laps, which may be zero for routes without a target.
+    pub fn total_seconds(&self) -> f32 {
+        self.laps.iter().map(|l| l.seconds).sum()
+    }
+
+    /// Every (valid) position along the route, in order.
+    pub fn positions(&self) -> Vec<Position> {
+        self.track_points().filter_map(|tp| tp.position).collect()
+    }
+
+    fn track_points(&self) -> impl Iterator<Item = &TrackPoint> {
+        self.tracks.iter().flat_map(|t| t.track_points.iter())
     }
 }
 
@@ -201,10 +648,82 @@ impl Activity {
         self.creator.name.as_str()
     }
 
+    /// The UTC calendar date the activity started on, or `None` without a
+    /// [start time](Activity::start_time). Activities that run past midnight, e.g. ultras,
+    /// stay dated by their start.
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.date_in(&Utc)
+    }
+
+    /// The calendar date the activity started on in `tz`, or `None` without a
+    /// [start time](Activity::start_time).
+    pub fn date_in<Tz: TimeZone>(&self, tz: &Tz) -> Option<NaiveDate> {
+        self.start_time()
+            .map(|start| start.with_timezone(tz).date_naive())
+    }
+
+    /// Total time of every lap.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.laps.iter().map(|l| l.seconds.max(0.0)).sum())
+    }
+
+    /// Time spent moving: trackpoint intervals covered at [MIN_MOVING_SPEED] or faster, so
+    /// stops at lights or auto-paused stretches recorded as trackpoints don't count.
+    /// Activities without trackpoints or trackpoint distances (e.g. on indoor equipment)
+    /// fall back to their [duration](Activity::duration).
+    pub fn moving_time(&self) -> Duration {
+        self.moving_time_with_threshold(MIN_MOVING_SPEED)
+    }
+
+    /// [Activity::moving_time], counting intervals as stopped below `min_speed` m/s
+    /// instead, e.g. lower for hiking up steep ground.
+    pub fn moving_time_with_threshold(&self, min_speed: f32) -> Duration {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        if points.len() < 2 || points.iter().all(|tp| tp.distance <= 0.0) {
+            return self.duration();
+        }
+        points
+            .windows(2)
+            .filter_map(|pair| {
+                let interval = (pair[1].time - pair[0].time).to_std().ok()?;
+                let meters = pair[1].distance - pair[0].distance;
+                (meters / interval.as_secs_f32() >= min_speed).then_some(interval)
+            })
+            .sum()
+    }
+
+    /// The activity's id, or when it's blank, the UTC time of its first trackpoint (or lap
+    /// if it has no timed trackpoints) in the usual id format, e.g. `2024-03-01T12:00:00Z`.
+    /// Empty if the id is blank and nothing in the activity has a time.
+    pub fn effective_id(&self) -> String {
+        if !self.id.trim().is_empty() {
+            return self.id.clone();
+        }
+        self.track_points()
+            .map(|tp| tp.time)
+            .find(|time| *time != missing_time())
+            .or_else(|| self.laps.first().map(|lap| lap.start_time))
+            .map_or_else(String::new, |start| {
+                start.to_rfc3339_opts(SecondsFormat::Secs, true)
+            })
+    }
+
+    /// When the activity started: its id parsed as an RFC 3339 timestamp, or when the id is
+    /// something else (some devices write a GUID), the first lap's start time. `None` if the
+    /// id isn't a timestamp and there are no laps.
+    pub fn start_time(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.id)
+            .ok()
+            .map(|start| start.with_timezone(&Utc))
+            .or_else(|| self.laps.first().map(|lap| lap.start_time))
+    }
+
     pub fn lap_count(&self) -> usize {
         self.laps.len()
     }
 
+    /// Average of every trackpoint HR. Files without trackpoint HR fall back to the laps'
+    /// average HR, weighted by lap time. 0 if neither is recorded.
     pub fn average_hr(&self) -> usize {
         if self.lap_count() == 0 {
             return 0;
@@ -214,25 +733,204 @@ impl Activity {
         let mut total_divisor = 0;
         for lap in self.laps.iter() {
             total_hr += lap.total_hr();
-            total_divisor += lap.total_measurements();
+            total_divisor += lap.hr_measurements();
+        }
+        if total_divisor == 0 {
+            return self.average_lap_hr();
         }
         total_hr / total_divisor
     }
 
+    /// Average of the laps' recorded average HR, weighted by lap time. 0 if no lap has one.
+    pub fn average_lap_hr(&self) -> usize {
+        let (weighted, seconds) = self
+            .laps
+            .iter()
+            .filter_map(|l| Some((l.average_hr()? as f32, l.seconds)))
+            .fold((0.0, 0.0), |(weighted, total), (hr, seconds)| {
+                (weighted + hr * seconds, total + seconds)
+            });
+        if seconds > 0.0 {
+            (weighted / seconds).round() as usize
+        } else {
+            0
+        }
+    }
+
+    /// Like [Activity::average_hr], but only over trackpoints with HR of at least `min_hr`,
+    /// e.g. to leave the warmup and cooldown out of a workout's average. 0 if no trackpoint
+    /// reached `min_hr`. There is no lap fallback, as lap averages can't be split by HR.
+    pub fn average_hr_active(&self, min_hr: usize) -> usize {
+        let active: Vec<usize> = self
+            .track_points()
+            .filter_map(|tp| tp.hr.as_ref().map(|hr| hr.value))
+            .filter(|hr| *hr >= min_hr)
+            .collect();
+        if active.is_empty() {
+            return 0;
+        }
+        active.iter().sum::<usize>() / active.len()
+    }
+
+    /// Average pace in seconds per mile over the stretches with HR of at least `min_hr`,
+    /// the counterpart of [Activity::average_hr_active]. Intervals between trackpoints are
+    /// attributed to the HR at their start, as in [Activity::hr_zones]. `None` if no
+    /// distance was covered at or above `min_hr`.
+    pub fn average_pace_active_seconds(&self, min_hr: usize) -> Option<Duration> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        let (seconds, meters) = points
+            .windows(2)
+            .filter(|pair| pair[0].hr.as_ref().is_some_and(|hr| hr.value >= min_hr))
+            .fold((0.0, 0.0), |(seconds, meters), pair| {
+                let interval = (pair[1].time - pair[0].time).num_milliseconds() as f64 / 1000.0;
+                let distance = (pair[1].distance - pair[0].distance).max(0.0) as f64;
+                (seconds + interval.max(0.0), meters + distance)
+            });
+        if meters <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs(
+            (seconds / meters * METERS_PER_MILE as f64).round() as u64,
+        ))
+    }
+
+    /// Time spent in each heart rate zone, given ascending zone boundaries in bpm.
+    /// Zone `i` covers `boundaries[i - 1] <= hr < boundaries[i]`, so the result has one more
+    /// entry than `boundaries`, the last being everything at or above the top boundary.
+    /// Each interval between trackpoints is attributed to the HR at its start, and intervals
+    /// starting at a trackpoint without HR are skipped.
+    pub fn hr_zones(&self, boundaries: &[usize]) -> Vec<Duration> {
+        self.zone_durations(boundaries, |tp| tp.hr.as_ref().map(|hr| hr.value))
+    }
+
+    /// Time spent in each zone of ascending bpm `zones`, e.g. `[120, 140, 160, 180]` for 5
+    /// zones. The same as [Activity::hr_zones].
+    pub fn hr_zone_durations(&self, zones: &[usize]) -> Vec<Duration> {
+        self.hr_zones(zones)
+    }
+
+    /// Time spent in each of Coggan's 7 power zones for a functional threshold power of
+    /// `ftp` watts, from [PowerZones::from_ftp]. Intervals are attributed like
+    /// [Activity::hr_zones], using trackpoint watts; without power every zone is zero.
+    pub fn power_zones(&self, ftp: usize) -> Vec<Duration> {
+        self.zone_durations(&PowerZones::from_ftp(ftp), TrackPoint::watts)
+    }
+
+    /// Time per zone of `boundaries` for the trackpoint value given by `value`, as
+    /// described by [Activity::hr_zones].
+    fn zone_durations(
+        &self,
+        boundaries: &[usize],
+        value: impl Fn(&TrackPoint) -> Option<usize>,
+    ) -> Vec<Duration> {
+        let mut zones = vec![Duration::ZERO; boundaries.len() + 1];
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        for pair in points.windows(2) {
+            let Some(value) = value(pair[0]) else {
+                continue;
+            };
+            let Ok(interval) = (pair[1].time - pair[0].time).to_std() else {
+                continue;
+            };
+            let zone = boundaries.iter().take_while(|b| value >= **b).count();
+            zones[zone] += interval;
+        }
+        zones
+    }
+
+    /// Elapsed time since the first trackpoint paired with HR, for every trackpoint that
+    /// recorded HR.
+    pub fn hr_series(&self) -> Vec<(Duration, usize)> {
+        let Some(start) = self.track_points().next().map(|tp| tp.time) else {
+            return vec![];
+        };
+        self.track_points()
+            .filter_map(|tp| Some(((tp.time - start).to_std().ok()?, tp.hr.as_ref()?.value)))
+            .collect()
+    }
+
+    /// Cumulative distance in meters covered since the first trackpoint, paired with the
+    /// time elapsed since then, for every trackpoint.
+    pub fn distance_series(&self) -> Vec<(Duration, f32)> {
+        let Some(first) = self.track_points().next() else {
+            return vec![];
+        };
+        let (start, start_distance) = (first.time, first.distance);
+        self.track_points()
+            .filter_map(|tp| {
+                Some((
+                    (tp.time - start).to_std().ok()?,
+                    tp.distance - start_distance,
+                ))
+            })
+            .collect()
+    }
+
+    /// Time spent with HR above `bpm`, attributing intervals as [Activity::hr_zones] does.
+    pub fn time_above_hr(&self, bpm: usize) -> Duration {
+        self.hr_zones(&[bpm + 1])[1]
+    }
+
+    /// Time spent with HR below `bpm`, attributing intervals as [Activity::hr_zones] does.
+    pub fn time_below_hr(&self, bpm: usize) -> Duration {
+        self.hr_zones(&[bpm])[0]
+    }
+
+    /// Time spent in each pace zone, given ascending zone boundaries in seconds per mile.
+    /// Like [Activity::hr_zones], zone `i` covers `boundaries[i - 1] <= pace < boundaries[i]`,
+    /// so the first zone is the fastest. Pace is the smoothed pace with glitches clamped
+    /// out, and the time of a dropped interval counts towards the next one kept.
+    pub fn pace_zones(&self, boundaries_sec_per_mile: &[u64]) -> Vec<Duration> {
+        let mut zones = vec![Duration::ZERO; boundaries_sec_per_mile.len() + 1];
+        let mut previous = Duration::ZERO;
+        for (elapsed, pace) in self.clamped_pace_series(PACE_SMOOTHING_WINDOW, PaceClamp::default())
+        {
+            let zone = boundaries_sec_per_mile
+                .iter()
+                .take_while(|b| pace >= **b as f32)
+                .count();
+            zones[zone] += elapsed.saturating_sub(previous);
+            previous = elapsed;
+        }
+        zones
+    }
+
+    /// Edwards' TRIMP training stress: minutes spent in each of the 5 default HR zones
+    /// (50-60% .. 90-100% of `max_hr`) weighted 1 through 5. Time below 50% adds nothing.
+    pub fn relative_effort(&self, max_hr: usize) -> f64 {
+        self.relative_effort_with_zones(&HrZones::default_from_max(max_hr))
+    }
+
+    /// [Activity::relative_effort] for the athlete's own ascending bpm zone boundaries:
+    /// minutes in the zone above the `i`th boundary are weighted `i + 1`.
+    pub fn relative_effort_with_zones(&self, boundaries: &[usize]) -> f64 {
+        weighted_zone_minutes(&self.hr_zones(boundaries))
+    }
+
     /// Average pace in meters/s.
     fn average_pace_meters(&self) -> f32 {
         if self.lap_count() == 0 {
             return 0.0;
         }
 
-        let mut total_time = 0.0;
-        let mut total_distance = 0.0;
-        for lap in self.laps.iter() {
-            total_time += lap.seconds;
-            total_distance += lap.distance;
-        }
+        let total_time: f32 = self.laps.iter().map(|lap| lap.seconds).sum();
+        self.effective_distance_meters() / total_time
+    }
 
-        total_distance / total_time
+    /// Average pace in seconds per mile over the [moving time](Activity::moving_time)
+    /// rather than the laps' total time, so stops don't slow it down. `None` when no
+    /// distance was covered or no time was spent moving.
+    pub fn moving_pace(&self) -> Option<Duration> {
+        let (meters, seconds) = (
+            self.effective_distance_meters() as f64,
+            self.moving_time().as_secs_f64(),
+        );
+        if meters <= 0.0 || seconds <= 0.0 {
+            return None;
+        }
+        Some(Duration::from_secs(
+            (seconds / meters * METERS_PER_MILE as f64).round() as u64,
+        ))
     }
 
     // Return average pace in miles/minute, formatted as a time "MM:SS"
@@ -249,108 +947,2821 @@ impl Activity {
         )
     }
 
+    /// Average pace in minutes per mile as a decimal, e.g. `7.75` for 7:45 / mi, for tools
+    /// that can't parse "MM:SS". 0 when no distance was covered.
+    pub fn average_pace_decimal(&self) -> f64 {
+        self.average_pace_decimal_per(METERS_PER_MILE)
+    }
+
+    /// Average pace in minutes per kilometer as a decimal, e.g. `4.5` for 4:30 / km.
+    pub fn average_pace_decimal_km(&self) -> f64 {
+        self.average_pace_decimal_per(1000.0)
+    }
+
+    fn average_pace_decimal_per(&self, meters: f32) -> f64 {
+        let speed = self.average_pace_meters() as f64;
+        if speed.is_nan() || speed <= 0.0 {
+            return 0.0;
+        }
+        meters as f64 / speed / 60.0
+    }
+
     pub fn average_pace_seconds(&self) -> std::time::Duration {
         let seconds_per_mile = (METERS_PER_MILE / self.average_pace_meters()).round() as u64;
         std::time::Duration::new(seconds_per_mile, 0)
     }
 
-    pub fn total_distance_meters(&self) -> f32 {
-        self.laps.iter().map(|l| l.distance).sum()
+    /// Average pace in seconds per mile, adjusted to its flat equivalent for running the
+    /// whole activity at a uniform `grade_percent`, e.g. a treadmill's incline setting.
+    /// Treadmills don't record altitude, so the grade has to come from the caller. The
+    /// pace is scaled by the energy cost of running at that grade relative to flat ground,
+    /// so uphill grades give a faster adjusted pace and gentle downhills a slower one.
+    /// `None` without any distance or time.
+    pub fn incline_adjusted_pace_seconds(&self, grade_percent: f64) -> Option<Duration> {
+        let speed = self.average_pace_meters() as f64;
+        if !speed.is_finite() || speed <= 0.0 {
+            return None;
+        }
+        let factor = running_cost(0.0) / running_cost(grade_percent / 100.0);
+        let seconds = METERS_PER_MILE as f64 / speed * factor;
+        Some(Duration::from_secs(seconds.round() as u64))
     }
 
-    pub fn total_distance_miles(&self) -> f32 {
-        0.0006213712 * self.total_distance_meters()
+    /// Average pace in seconds per mile, adjusted to its flat equivalent using the activity's
+    /// own altitude. Each interval between trackpoints with an altitude counts as the
+    /// distance it would take the same effort to cover on flat ground, by the energy cost
+    /// of running at its grade, so hilly runs come out faster than their raw pace. Distance
+    /// without altitude counts as flat, so this is [Activity::average_pace_seconds] for
+    /// flat or indoor activities.
+    pub fn grade_adjusted_pace_seconds(&self) -> Duration {
+        let flat = running_cost(0.0);
+        let extra_meters: f64 = self
+            .grade_intervals()
+            .map(|(meters, climb)| meters * (running_cost(climb / meters) / flat - 1.0))
+            .sum();
+        let meters = self.effective_distance_meters() as f64 + extra_meters;
+        let seconds: f64 = self.laps.iter().map(|lap| lap.seconds as f64).sum();
+        if meters <= 0.0 || seconds <= 0.0 {
+            return self.average_pace_seconds();
+        }
+        Duration::from_secs((seconds / meters * METERS_PER_MILE as f64).round() as u64)
     }
 
-    /// Total elevation gain in feet.
-    pub fn total_elevation_gain(&self) -> usize {
-        let gain_meters = self
-            .laps
-            .iter()
-            .map(|l| l.alt_gain_meters)
-            .fold(0.0, |sum, v| sum + v);
-        (gain_meters * FEET_PER_METER).round() as usize
+    /// Elapsed time since the first trackpoint, paired with the instantaneous pace
+    /// (in seconds per mile) between each trackpoint and the one before it.
+    pub fn pace_series(&self) -> Vec<(Duration, f32)> {
+        self.smoothed_pace_series(1)
     }
 
-    /// Total elevation loss in feet.
-    pub fn total_elevation_loss(&self) -> usize {
-        let loss_meters = self
-            .laps
-            .iter()
-            .map(|l| l.alt_loss_meters)
-            .fold(0.0, |sum, v| sum + v);
-        (loss_meters * FEET_PER_METER).round() as usize
+    /// Like [Activity::pace_series], but each pace is measured across the trailing
+    /// `window` trackpoint intervals to smooth out GPS jitter. Glitches are rejected using
+    /// the default [PaceClamp].
+    pub fn smoothed_pace_series(&self, window: usize) -> Vec<(Duration, f32)> {
+        self.clamped_pace_series(window, PaceClamp::default())
     }
 
-    pub fn calc_lap_elevations(&mut self) {
-        self.laps.iter_mut().for_each(|l| l.calc_elevation());
+    /// Smoothed pace series where intervals between trackpoints whose instantaneous pace
+    /// falls outside `clamp` are dropped before smoothing, so a single glitch can't skew the
+    /// surrounding window. Intervals without forward movement have no defined pace and are
+    /// dropped as well.
+    pub fn clamped_pace_series(&self, window: usize, clamp: PaceClamp) -> Vec<(Duration, f32)> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        let Some(start) = points.first().map(|tp| tp.time) else {
+            return vec![];
+        };
+
+        // Elapsed time at the end of each plausible interval, with its meters and seconds.
+        let intervals: Vec<(Duration, f32, f32)> = points
+            .windows(2)
+            .filter_map(|pair| {
+                let meters = pair[1].distance - pair[0].distance;
+                let seconds = (pair[1].time - pair[0].time).num_milliseconds() as f32 / 1000.0;
+                if meters <= 0.0
+                    || seconds <= 0.0
+                    || !clamp.contains(seconds * METERS_PER_MILE / meters)
+                {
+                    return None;
+                }
+                Some(((pair[1].time - start).to_std().ok()?, meters, seconds))
+            })
+            .collect();
+
+        (0..intervals.len())
+            .map(|i| {
+                let trailing = &intervals[(i + 1).saturating_sub(window.max(1))..=i];
+                let meters: f32 = trailing.iter().map(|(_, meters, _)| meters).sum();
+                let seconds: f32 = trailing.iter().map(|(_, _, seconds)| seconds).sum();
+                (intervals[i].0, seconds * METERS_PER_MILE / meters)
+            })
+            .collect()
     }
 
-    pub fn average_cadence(&self) -> usize {
-        let total_cadence: usize = self
-            .laps
+    /// How much pace slowed from start to finish, as a percentage of the starting pace, from
+    /// a least-squares line fit through the smoothed pace series. Positive values mean the
+    /// run got slower. `None` when there are too few pace samples to fit a trend.
+    pub fn pace_fade_percent(&self) -> Option<f64> {
+        let series = self.smoothed_pace_series(PACE_SMOOTHING_WINDOW);
+        if series.len() < MIN_FADE_SAMPLES {
+            return None;
+        }
+
+        let n = series.len() as f64;
+        let points: Vec<(f64, f64)> = series
             .iter()
-            .filter_map(|l| l.extensions.get(0))
-            .filter_map(|ext| ext.lx.avg_cadence)
+            .map(|(elapsed, pace)| (elapsed.as_secs_f64(), *pace as f64))
+            .collect();
+        let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let mean_pace = points.iter().map(|(_, p)| p).sum::<f64>() / n;
+        let covariance: f64 = points
+            .iter()
+            .map(|(t, p)| (t - mean_t) * (p - mean_pace))
             .sum();
-        (total_cadence / self.lap_count()) * 2
+        let variance: f64 = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum();
+        if variance == 0.0 {
+            return None;
+        }
+
+        let slope = covariance / variance;
+        let (first_t, last_t) = (points[0].0, points[points.len() - 1].0);
+        let start_pace = mean_pace + slope * (first_t - mean_t);
+        let end_pace = mean_pace + slope * (last_t - mean_t);
+        Some((end_pace - start_pace) / start_pace * 100.0)
     }
 
-    pub fn average_watts(&self) -> usize {
-        let total_watts: usize = self
-            .laps
+    /// Heart rate drift over the steady-effort portions of the activity, as the percentage
+    /// by which average HR in the second half of those portions exceeds the first half.
+    /// Positive values mean HR crept up at the same effort.
+    ///
+    /// A trackpoint counts as steady when, over the trailing [PACE_SMOOTHING_WINDOW]
+    /// trackpoints, the grade is within [STEADY_MAX_GRADE]% (trackpoints without altitude
+    /// are taken as flat) and pace is within [STEADY_PACE_TOLERANCE] of the median pace of
+    /// all such flat stretches. `None` with fewer than [MIN_FADE_SAMPLES] steady trackpoints
+    /// that recorded HR.
+    pub fn hr_drift_steady(&self) -> Option<f64> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        // Pace and HR at the end of every flat window.
+        let mut flat: Vec<(f32, usize)> = vec![];
+        for window in points.windows(PACE_SMOOTHING_WINDOW + 1) {
+            let (first, last) = (window[0], window[window.len() - 1]);
+            let meters = last.distance - first.distance;
+            let seconds = (last.time - first.time).num_milliseconds() as f32 / 1000.0;
+            let Some(hr) = last.hr.as_ref().map(|hr| hr.value) else {
+                continue;
+            };
+            if meters <= 0.0 || seconds <= 0.0 {
+                continue;
+            }
+            let climb = match (first.altitude, last.altitude) {
+                (Some(from), Some(to)) => to - from,
+                _ => 0.0,
+            };
+            if (climb / meters as f64 * 100.0).abs() <= STEADY_MAX_GRADE {
+                flat.push((seconds * METERS_PER_MILE / meters, hr));
+            }
+        }
+
+        let mut paces: Vec<f32> = flat.iter().map(|(pace, _)| *pace).collect();
+        paces.sort_by(f32::total_cmp);
+        let median_pace = *paces.get(paces.len() / 2)?;
+        let steady: Vec<usize> = flat
             .iter()
-            .filter_map(|l| l.extensions.get(0))
-            .map(|ext| ext.lx.avg_watts.unwrap_or(0))
-            .sum();
-        total_watts / self.lap_count()
-    }
-}
+            .filter(|(pace, _)| ((pace - median_pace) / median_pace).abs() <= STEADY_PACE_TOLERANCE)
+            .map(|(_, hr)| *hr)
+            .collect();
+        if steady.len() < MIN_FADE_SAMPLES {
+            return None;
+        }
 
-impl Lap {
-    /// The total amount of Trackpoint measurements
-    /// this lap contains.
-    fn total_measurements(&self) -> usize {
-        self.track.track_points.len()
+        let (first_half, second_half) = steady.split_at(steady.len() / 2);
+        let mean = |hrs: &[usize]| hrs.iter().sum::<usize>() as f64 / hrs.len() as f64;
+        let (first_hr, second_hr) = (mean(first_half), mean(second_half));
+        Some((second_hr - first_hr) / first_hr * 100.0)
     }
 
-    /// The total of all individual HR values, used to calculate
-    /// the average HR across multiple laps.
-    fn total_hr(&self) -> usize {
-        self.track
-            .track_points
+    /// Elapsed time since the first trackpoint paired with speed in meters/s, for every
+    /// trackpoint. Uses the device-reported speed when there is one, and otherwise the
+    /// distance covered since the previous trackpoint over the time between them.
+    /// Trackpoints with neither are left out.
+    pub fn speed_series(&self) -> Vec<(Duration, f64)> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        let Some(start) = points.first().map(|tp| tp.time) else {
+            return vec![];
+        };
+        points
             .iter()
-            .filter_map(|tp| tp.hr.as_ref())
-            .map(|hr| hr.value)
-            .sum()
+            .enumerate()
+            .filter_map(|(i, tp)| {
+                let elapsed = (tp.time - start).to_std().ok()?;
+                if let Some(speed) = tp.speed_ms() {
+                    return Some((elapsed, speed));
+                }
+                let previous = points.get(i.checked_sub(1)?)?;
+                let seconds = (tp.time - previous.time).num_milliseconds() as f64 / 1000.0;
+                if seconds <= 0.0 {
+                    return None;
+                }
+                let meters = (tp.distance - previous.distance).max(0.0) as f64;
+                Some((elapsed, meters / seconds))
+            })
+            .collect()
     }
 
-    fn calc_elevation(&mut self) {
-        self.last_alt = if let Some(tp) = self.track.track_points.first() {
-            tp.altitude.unwrap_or(0.0)
-        } else {
-            0.0
+    /// Shortest time taken to cover `distance_meters` anywhere in the activity, or `None`
+    /// if it's shorter than that. The start of each effort is interpolated between
+    /// trackpoints, so the result doesn't depend on where trackpoints happen to fall.
+    pub fn fastest_split(&self, distance_meters: f32) -> Option<Duration> {
+        if distance_meters <= 0.0 {
+            return None;
+        }
+        let points: Vec<(f32, f64)> = {
+            let tps: Vec<&TrackPoint> = self.track_points().collect();
+            let start = tps.first()?.time;
+            tps.iter()
+                .map(|tp| {
+                    let elapsed = (tp.time - start).num_milliseconds() as f64 / 1000.0;
+                    (tp.distance, elapsed)
+                })
+                .collect()
         };
 
-        for tp in self.track.track_points.iter() {
-            if let Some(altitude) = tp.altitude {
-                let alt_change = (altitude - self.last_alt).abs();
-                if alt_change < ALTITUDE_THRESHOLD {
-                    continue;
-                }
+        let mut fastest: Option<f64> = None;
+        let mut i = 0;
+        for j in 1..points.len() {
+            let target = points[j].0 - distance_meters;
+            if target < points[0].0 {
+                continue;
+            }
+            // Move `i` to the last trackpoint at or before where this effort starts.
+            while points[i + 1].0 <= target {
+                i += 1;
+            }
+            let (d0, t0) = points[i];
+            let (d1, t1) = points[i + 1];
+            let start = if d1 > d0 {
+                t0 + (t1 - t0) * ((target - d0) / (d1 - d0)) as f64
+            } else {
+                t0
+            };
+            let elapsed = points[j].1 - start;
+            fastest = Some(fastest.map_or(elapsed, |f| f.min(elapsed)));
+        }
+        fastest.map(|s| Duration::from_secs_f64(s.max(0.0)))
+    }
 
-                if altitude > self.last_alt {
-                    self.alt_gain_meters += alt_change;
-                } else {
-                    self.alt_loss_meters += alt_change;
-                }
+    /// Time taken for each consecutive `split_meters` of the activity from the start, i.e.
+    /// the durations of its [Activity::splits] with the final partial split left out.
+    pub fn distance_splits(&self, split_meters: f32) -> Vec<Duration> {
+        self.splits(split_meters)
+            .into_iter()
+            .filter(|split| split.distance_meters >= split_meters)
+            .map(|split| split.duration)
+            .collect()
+    }
 
-                self.last_alt = altitude;
+    /// Fastest time taken to ride or run along `polyline`, a segment given as (lat, long)
+    /// points in the direction it's traversed, or `None` if the activity never covered it.
+    ///
+    /// An effort starts at the trackpoint closest to the segment's first point and ends at
+    /// the one closest to its last point, both within `tolerance_m`. In between, every
+    /// trackpoint must stay within `tolerance_m` of the segment and pass each of its points
+    /// in order, so merely crossing the start and finish elsewhere doesn't count.
+    pub fn match_segment(&self, polyline: &[(f64, f64)], tolerance_m: f64) -> Option<Duration> {
+        if polyline.len() < 2 {
+            return None;
+        }
+        let segment: Vec<Position> = polyline
+            .iter()
+            .map(|&(lat, long)| Position { lat, long })
+            .collect();
+        let (first, last) = (&segment[0], &segment[segment.len() - 1]);
+        let points: Vec<(DateTime<Utc>, Position)> = self
+            .track_points()
+            .filter_map(|tp| Some((tp.time, tp.position.filter(Position::is_valid)?)))
+            .collect();
+        let closest_in_run = |i: usize, target: &Position| {
+            let distance = points[i].1.distance_to(target);
+            distance <= tolerance_m
+                && !points
+                    .get(i + 1)
+                    .is_some_and(|(_, next)| next.distance_to(target) < distance)
+        };
+
+        let mut fastest: Option<Duration> = None;
+        for (i, (entry_time, _)) in points.iter().enumerate() {
+            if !closest_in_run(i, first) {
+                continue;
+            }
+            // Index of the next segment point the effort has to pass.
+            let mut next_point = 1;
+            for (j, (exit_time, position)) in points.iter().enumerate().skip(i + 1) {
+                if position.distance_to_polyline(&segment) > tolerance_m {
+                    break;
+                }
+                while next_point < segment.len() - 1
+                    && position.distance_to(&segment[next_point]) <= tolerance_m
+                {
+                    next_point += 1;
+                }
+                if next_point == segment.len() - 1 && closest_in_run(j, last) {
+                    let elapsed = (*exit_time - *entry_time).to_std().unwrap_or_default();
+                    fastest = Some(fastest.map_or(elapsed, |f| f.min(elapsed)));
+                    break;
+                }
             }
         }
+        fastest
+    }
+
+    /// [Activity::fastest_split] for each of [BEST_EFFORT_DISTANCES], paired with the
+    /// distance in meters.
+    pub fn best_efforts(&self) -> Vec<(f32, Option<Duration>)> {
+        BEST_EFFORT_DISTANCES
+            .iter()
+            .map(|(_, meters)| (*meters, self.fastest_split(*meters)))
+            .collect()
+    }
+
+    /// Elapsed-time ranges where smoothed pace stayed faster than `pace_threshold`
+    /// (seconds per mile) for at least `min_duration`. Each pace sample covers the time since
+    /// the sample before it, so a surge starts where the last slower sample (or the
+    /// activity) ended.
+    pub fn detect_surges(
+        &self,
+        pace_threshold: f32,
+        min_duration: Duration,
+    ) -> Vec<(Duration, Duration)> {
+        let mut surges = vec![];
+        let mut current: Option<(Duration, Duration)> = None;
+        let mut previous = Duration::ZERO;
+        for (elapsed, pace) in self.smoothed_pace_series(PACE_SMOOTHING_WINDOW) {
+            if pace < pace_threshold {
+                let start = current.map_or(previous, |(start, _)| start);
+                current = Some((start, elapsed));
+            } else if let Some(surge) = current.take() {
+                surges.push(surge);
+            }
+            previous = elapsed;
+        }
+        surges.extend(current);
+        surges.retain(|(start, end)| *end - *start >= min_duration);
+        surges
+    }
+
+    /// Fastest speed reached, in miles per hour. Uses the device-reported trackpoint speed
+    /// when available, otherwise the distance/time between consecutive trackpoints. Speeds
+    /// outside the default [PaceClamp] are ignored as glitches.
+    pub fn max_speed_mph(&self) -> Option<f32> {
+        let clamp = PaceClamp::default();
+        let plausible = |mps: f32| mps > 0.0 && clamp.contains(METERS_PER_MILE / mps);
+
+        let reported = self
+            .track_points()
+            .filter_map(TrackPoint::speed_ms)
+            .map(|speed| speed as f32)
+            .filter(|speed| plausible(*speed))
+            .reduce(f32::max);
+        let max_mps = reported.or_else(|| {
+            let points: Vec<&TrackPoint> = self.track_points().collect();
+            points
+                .windows(2)
+                .filter_map(|pair| {
+                    let seconds = (pair[1].time - pair[0].time).num_milliseconds() as f32 / 1000.0;
+                    (seconds > 0.0).then(|| (pair[1].distance - pair[0].distance) / seconds)
+                })
+                .filter(|speed| plausible(*speed))
+                .reduce(f32::max)
+        })?;
+
+        Some(max_mps * 3600.0 / METERS_PER_MILE)
+    }
+
+    /// The pace at [Activity::max_speed_mph], formatted as "MM:SS / mi".
+    pub fn max_pace(&self) -> Option<String> {
+        let seconds_per_mile = (3600.0 / self.max_speed_mph()?).round() as u64;
+        Some(format!(
+            "{:02}:{:02} / mi",
+            seconds_per_mile / 60,
+            seconds_per_mile % 60
+        ))
+    }
+
+    pub fn total_distance_meters(&self) -> f32 {
+        // Summing floats starts from -0.0, which would show as "-0.00" without any laps.
+        self.laps.iter().fold(0.0, |total, l| total + l.distance)
+    }
+
+    pub fn total_distance_miles(&self) -> f32 {
+        self.total_distance_meters() * MILES_PER_METER
+    }
+
+    /// The device-reported [total distance](Activity::total_distance_meters), or when the
+    /// laps recorded next to none but the track has GPS positions (as with some apps that
+    /// strip data), [Activity::distance_from_gps] instead.
+    pub fn effective_distance_meters(&self) -> f32 {
+        let reported = self.total_distance_meters();
+        if reported >= UNRECORDED_DISTANCE_METERS {
+            return reported;
+        }
+        let gps_distance = self.distance_from_gps();
+        if gps_distance > 0.0 {
+            gps_distance
+        } else {
+            reported
+        }
+    }
+
+    /// Whether this was recorded indoors, e.g. on a treadmill, trainer or in a pool: it has
+    /// trackpoints but none of them has a GPS position. TCX has no indoor sports, so the
+    /// sport can't tell. Manually entered activities without any trackpoints aren't indoor.
+    pub fn is_indoor(&self) -> bool {
+        let mut track_points = self.track_points().peekable();
+        track_points.peek().is_some() && track_points.all(|tp| tp.position.is_none())
+    }
+
+    /// Distance in meters computed from the trackpoint positions, bridging over any
+    /// trackpoints that have no position.
+    pub fn gps_distance_meters(&self) -> f32 {
+        self.distance_from_gps()
+    }
+
+    /// Sum of the haversine distances in meters between consecutive trackpoint positions,
+    /// ignoring `DistanceMeters`. Trackpoints without a position are bridged over, joining
+    /// the positions either side of them.
+    pub fn distance_from_gps(&self) -> f32 {
+        let positions: Vec<Position> = self.track_points().filter_map(|tp| tp.position).collect();
+        positions
+            .windows(2)
+            .map(|pair| pair[0].distance_to(&pair[1]))
+            .sum::<f64>() as f32
+    }
+
+    /// How winding the route is: GPS path length divided by the straight-line distance
+    /// from the first position to the last. Close to 1 for a straight route, and large for
+    /// loops and out-and-backs, which finish near their start. The straight-line distance
+    /// is taken to be at least 1m, so finishing exactly at the start gives a large finite
+    /// value. `None` without any GPS distance.
+    pub fn sinuosity(&self) -> Option<f64> {
+        let path_length = self.gps_distance_meters() as f64;
+        if path_length <= 0.0 {
+            return None;
+        }
+        let first = self.track_points().find_map(|tp| tp.position)?;
+        let last = self.track_points().filter_map(|tp| tp.position).last()?;
+        Some(path_length / first.distance_to(&last).max(1.0))
+    }
+
+    /// Ratio of the device-reported distance to the GPS-computed distance, or `None`
+    /// when there isn't any GPS distance to compare against.
+    pub fn distance_discrepancy(&self) -> Option<f32> {
+        let gps_distance = self.gps_distance_meters();
+        if gps_distance <= 0.0 {
+            return None;
+        }
+        Some(self.total_distance_meters() / gps_distance)
+    }
+
+    /// Whether the device-reported distance is roughly consistent with the GPS track.
+    /// Activities without GPS distance are assumed to be plausible.
+    pub fn plausible_distance(&self) -> bool {
+        self.distance_discrepancy().is_none_or(|ratio| {
+            (1.0 / PLAUSIBLE_DISTANCE_RATIO..=PLAUSIBLE_DISTANCE_RATIO).contains(&ratio)
+        })
+    }
+
+    /// For activities whose distance isn't [plausible](Activity::plausible_distance), the
+    /// power of ten the reported distance appears to be scaled by compared to the GPS track,
+    /// e.g. `1000.0` when an exporter wrote millimeters. Dividing reported distances by the
+    /// hint gives meters.
+    pub fn distance_scale_hint(&self) -> Option<f32> {
+        if self.plausible_distance() {
+            return None;
+        }
+        let ratio = self.distance_discrepancy()?;
+        if ratio <= 0.0 {
+            return None;
+        }
+        Some(10f32.powf(ratio.log10().round()))
+    }
+
+    /// Median time between consecutive trackpoints, or `None` with fewer than two.
+    pub fn sampling_interval_median(&self) -> Option<Duration> {
+        let mut intervals = self.sampling_intervals();
+        intervals.sort();
+        intervals.get(intervals.len() / 2).copied()
+    }
+
+    fn sampling_intervals(&self) -> Vec<Duration> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        points
+            .windows(2)
+            .filter_map(|pair| (pair[1].time - pair[0].time).to_std().ok())
+            .collect()
+    }
+
+    /// Checks the activity for data-quality problems that make computed stats unreliable.
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        let mut warnings = vec![];
+        if let Some(ratio) = self.distance_discrepancy() {
+            if (ratio - 1.0).abs() > DISTANCE_DISCREPANCY_THRESHOLD {
+                warnings.push(ValidationWarning::DistanceDiscrepancy(ratio));
+            }
+        }
+        let decreasing = self.decreasing_distance_count();
+        if decreasing > 0 {
+            warnings.push(ValidationWarning::DecreasingDistance(decreasing));
+        }
+        if let Some(median) = self.sampling_interval_median() {
+            if median.abs_diff(Duration::from_secs(1)) > SAMPLING_INTERVAL_TOLERANCE {
+                warnings.push(ValidationWarning::SamplingInterval(median));
+            }
+            let intervals = self.sampling_intervals();
+            let irregular = intervals
+                .iter()
+                .filter(|i| i.abs_diff(median) > median / 2)
+                .count() as f32
+                / intervals.len() as f32;
+            if irregular > IRREGULAR_SAMPLING_THRESHOLD {
+                warnings.push(ValidationWarning::IrregularSampling(irregular));
+            }
+        }
+        warnings
+    }
+
+    /// Cumulative distance (meters) paired with altitude (meters) for every trackpoint
+    /// that records an altitude.
+    pub fn elevation_profile(&self) -> Vec<(f32, f64)> {
+        self.track_points()
+            .filter_map(|tp| Some((tp.distance, tp.altitude?)))
+            .collect()
+    }
+
+    /// Altitude in meters `meters` into the activity, interpolated between the trackpoints
+    /// on either side of that cumulative distance. `None` beyond the last trackpoint's
+    /// distance, or when either of those trackpoints has no altitude.
+    pub fn elevation_at_distance(&self, meters: f32) -> Option<f64> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        if meters < points.first()?.distance || meters > points.last()?.distance {
+            return None;
+        }
+        // First trackpoint at or past `meters`, so the one before it is short of it.
+        let after = points.iter().position(|tp| tp.distance >= meters)?;
+        let (d1, a1) = (points[after].distance, points[after].altitude?);
+        let Some(before) = after.checked_sub(1).map(|i| points[i]) else {
+            return Some(a1);
+        };
+        let (d0, a0) = (before.distance, before.altitude?);
+        if d1 <= d0 {
+            return Some(a1);
+        }
+        Some(a0 + (a1 - a0) * ((meters - d0) / (d1 - d0)) as f64)
+    }
+
+    /// Net elevation change over the distance covered, as a percentage. Only intervals
+    /// between trackpoints that both record an altitude and moved forward are counted.
+    /// `None` without any.
+    pub fn average_grade(&self) -> Option<f64> {
+        let (meters, climb) = self
+            .grade_intervals()
+            .fold((0.0, 0.0), |(m, c), (meters, climb)| {
+                (m + meters, c + climb)
+            });
+        (meters > 0.0).then(|| climb / meters * 100.0)
+    }
+
+    /// Like [Activity::average_grade], but only over the intervals that climbed. `None`
+    /// when none did.
+    pub fn average_uphill_grade(&self) -> Option<f64> {
+        let (meters, climb) = self
+            .grade_intervals()
+            .filter(|(_, climb)| *climb > 0.0)
+            .fold((0.0, 0.0), |(m, c), (meters, climb)| {
+                (m + meters, c + climb)
+            });
+        (meters > 0.0).then(|| climb / meters * 100.0)
+    }
+
+    /// Distance and altitude change in meters between consecutive trackpoints, for every
+    /// pair that both record an altitude and moved forward.
+    fn grade_intervals(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        let mut previous: Option<&TrackPoint> = None;
+        self.track_points().filter_map(move |tp| {
+            let before = previous.replace(tp)?;
+            let meters = (tp.distance - before.distance) as f64;
+            let climb = tp.altitude? - before.altitude?;
+            (meters > 0.0).then_some((meters, climb))
+        })
+    }
+
+    /// Total elevation gain in feet.
+    pub fn total_elevation_gain(&self) -> usize {
+        let gain_meters = self
+            .laps
+            .iter()
+            .map(|l| l.alt_gain_meters)
+            .fold(0.0, |sum, v| sum + v);
+        (gain_meters * FEET_PER_METER).round() as usize
+    }
+
+    /// Total elevation loss in feet.
+    pub fn total_elevation_loss(&self) -> usize {
+        let loss_meters = self
+            .laps
+            .iter()
+            .map(|l| l.alt_loss_meters)
+            .fold(0.0, |sum, v| sum + v);
+        (loss_meters * FEET_PER_METER).round() as usize
+    }
+
+    /// All trackpoints of the activity, in order, across every lap.
+    fn track_points(&self) -> impl Iterator<Item = &TrackPoint> {
+        self.laps.iter().flat_map(|l| l.track.track_points.iter())
+    }
+
+    /// Removes every trackpoint within `radius_m` meters of the activity's first or last
+    /// recorded position, so the start/end location (often home) isn't revealed when
+    /// sharing a route. Trackpoints without a position are kept.
+    pub fn obfuscate_start(&mut self, radius_m: f64) {
+        let first = self.track_points().find_map(|tp| tp.position);
+        let last = self.track_points().filter_map(|tp| tp.position).last();
+        let (Some(first), Some(last)) = (first, last) else {
+            return;
+        };
+
+        for lap in self.laps.iter_mut() {
+            lap.track.track_points.retain(|tp| match tp.position {
+                Some(pos) => {
+                    pos.distance_to(&first) > radius_m && pos.distance_to(&last) > radius_m
+                }
+                None => true,
+            });
+        }
+    }
+
+    /// Total mechanical work in kilojoules, integrating trackpoint power over time. When
+    /// fewer than half the trackpoints carry power, falls back to each lap's average watts
+    /// over its duration. `None` without any power data.
+    pub fn total_work_kj(&self) -> Option<f64> {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        let powered = points.iter().filter(|tp| tp.watts().is_some()).count();
+        if powered > 0 && powered * 2 >= points.len() {
+            let joules: f64 = points
+                .windows(2)
+                .filter_map(|pair| {
+                    let watts = pair[0].watts()?;
+                    let seconds = (pair[1].time - pair[0].time).num_milliseconds() as f64 / 1000.0;
+                    Some(watts as f64 * seconds)
+                })
+                .sum();
+            return Some(joules / 1000.0);
+        }
+        self.lap_work_kj()
+    }
+
+    /// Total work in kilojoules from each lap's average watts over its duration.
+    fn lap_work_kj(&self) -> Option<f64> {
+        let lap_joules: Vec<f64> = self
+            .laps
+            .iter()
+            .filter_map(|l| {
+                let watts = l.lx()?.avg_watts?;
+                Some(watts as f64 * l.seconds as f64)
+            })
+            .collect();
+        if lap_joules.is_empty() {
+            return None;
+        }
+        Some(lap_joules.iter().sum::<f64>() / 1000.0)
+    }
+
+    /// Splits the activity wherever consecutive trackpoints are more than `min_pause` apart,
+    /// returning each piece as its own activity. Trackpoints keep their original lap, with
+    /// a lap that spans a pause being split between both pieces. The first piece keeps this
+    /// activity's id, later pieces use their start time as their id.
+    pub fn split_at_pauses(&self, min_pause: Duration) -> Vec<Activity> {
+        let mut segments: Vec<Vec<Lap>> = vec![vec![]];
+        let mut last_time: Option<DateTime<Utc>> = None;
+        for lap in self.laps.iter() {
+            let mut piece = vec![];
+            for tp in lap.track.track_points.iter() {
+                let paused = last_time
+                    .and_then(|last| (tp.time - last).to_std().ok())
+                    .is_some_and(|gap| gap > min_pause);
+                if paused {
+                    if !piece.is_empty() {
+                        let laps = segments.last_mut().expect("segments is never empty");
+                        laps.push(lap.with_track_points(std::mem::take(&mut piece)));
+                    }
+                    segments.push(vec![]);
+                }
+                last_time = Some(tp.time);
+                piece.push(tp.clone());
+            }
+            if !piece.is_empty() {
+                let laps = segments.last_mut().expect("segments is never empty");
+                laps.push(lap.with_track_points(piece));
+            }
+        }
+
+        segments
+            .into_iter()
+            .filter(|laps| !laps.is_empty())
+            .enumerate()
+            .map(|(i, laps)| Activity {
+                sport: self.sport,
+                id: if i == 0 {
+                    self.id.clone()
+                } else {
+                    laps[0]
+                        .start_time
+                        .to_rfc3339_opts(SecondsFormat::Secs, true)
+                },
+                laps,
+                creator: self.creator.clone(),
+            })
+            .collect()
+    }
+
+    /// A copy of the activity holding only the trackpoints positioned inside `bbox`, e.g. the
+    /// part of a run inside a park. A lap that leaves and re-enters the box is split into a
+    /// lap per visit, with its time, distance and calories recomputed for what's kept, and
+    /// trackpoint distances only count distance covered inside the box. Trackpoints
+    /// without a position are dropped. As with [Activity::split_at_pauses], lap
+    /// elevations need recalculating.
+    pub fn clip_to_bounds(&self, bbox: &BoundingBox) -> Activity {
+        let mut laps = vec![];
+        // Subtracted from the distance of trackpoints inside the box, so it only grows
+        // while inside. Kept across laps when the track stays inside between them.
+        let (mut clipped_distance, mut offset, mut inside) = (0.0, 0.0, false);
+        for lap in self.laps.iter() {
+            let mut piece: Vec<TrackPoint> = vec![];
+            for tp in lap.track.track_points.iter() {
+                let was_inside = inside;
+                inside = tp.position.is_some_and(|p| bbox.contains(&p));
+                if inside {
+                    if !was_inside {
+                        offset = tp.distance - clipped_distance;
+                    }
+                    let mut tp = tp.clone();
+                    tp.distance -= offset;
+                    clipped_distance = tp.distance;
+                    piece.push(tp);
+                } else if !piece.is_empty() {
+                    laps.push(lap.with_track_points(std::mem::take(&mut piece)));
+                }
+            }
+            if !piece.is_empty() {
+                laps.push(lap.with_track_points(piece));
+            }
+        }
+
+        Activity {
+            sport: self.sport,
+            id: self.id.clone(),
+            laps,
+            creator: self.creator.clone(),
+        }
+    }
+
+    /// Total of the device-reported lap calories, or `None` if no lap reported any.
+    pub fn total_calories(&self) -> Option<usize> {
+        let reported: Vec<usize> = self.laps.iter().filter_map(|l| l.calories).collect();
+        (!reported.is_empty()).then(|| reported.iter().sum())
+    }
+
+    /// Calories burned computed from power, or `None` without any power data. Muscles are
+    /// roughly 24% efficient and a kcal is 4.184kJ, so the energy burned in kcal comes out
+    /// almost exactly equal to the mechanical work in kJ.
+    pub fn calories_from_power(&self) -> Option<usize> {
+        self.total_work_kj().map(|kj| kj.round() as usize)
+    }
+
+    /// Calories burned: [Activity::calories_from_power] when the activity has power, then
+    /// the device-reported lap calories, otherwise an estimate from duration and a
+    /// sport-specific MET value for an athlete of `weight_kg`. Running uses the ACSM
+    /// running equation so faster running burns more.
+    pub fn estimate_calories(&self, weight_kg: f32) -> usize {
+        if let Some(calories) = self.calories_from_power().or_else(|| self.total_calories()) {
+            return calories;
+        }
+
+        let hours = self.laps.iter().map(|l| l.seconds).sum::<f32>() / 3600.0;
+        let met = match self.sport {
+            Sport::Running if self.lap_count() > 0 => {
+                // VO2 (ml/kg/min) = 0.2 * speed (m/min) + 3.5, and 1 MET = 3.5 ml/kg/min.
+                (0.2 * self.average_pace_meters() * 60.0 + 3.5) / 3.5
+            }
+            Sport::Running => 9.8,
+            Sport::Biking => 8.0,
+            Sport::Swimming => 7.0,
+            Sport::Walking => 3.5,
+            Sport::Other => 6.0,
+        };
+        (met * weight_kg * hours).round() as usize
+    }
+
+    /// Number of trackpoints whose cumulative distance is lower than the trackpoint before,
+    /// e.g. after the device corrected its GPS position.
+    pub fn decreasing_distance_count(&self) -> usize {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        points
+            .windows(2)
+            .filter(|pair| pair[1].distance < pair[0].distance)
+            .count()
+    }
+
+    /// Raises any trackpoint distance that's lower than the one before it to match, so
+    /// cumulative distance never goes backwards and no interval has a negative distance.
+    pub fn repair_distances(&mut self) {
+        let mut furthest = f32::MIN;
+        for tp in self
+            .laps
+            .iter_mut()
+            .flat_map(|l| l.track.track_points.iter_mut())
+        {
+            furthest = furthest.max(tp.distance);
+            tp.distance = furthest;
+        }
+    }
+
+    /// Drops trackpoints recorded within `from_start` of the first trackpoint or `from_end`
+    /// of the last, e.g. standing around after forgetting to stop the watch. Laps keep
+    /// their structure: a partly trimmed lap has its time, distance and calories
+    /// recomputed for the trackpoints left, and laps left without any are removed.
+    /// Elevations need recalculating afterwards.
+    pub fn trim(&mut self, from_start: Duration, from_end: Duration) {
+        let first = self.track_points().next().map(|tp| tp.time);
+        let last = self.track_points().last().map(|tp| tp.time);
+        let (Some(first), Some(last)) = (first, last) else {
+            return;
+        };
+        let (Ok(from_start), Ok(from_end)) = (
+            TimeDelta::from_std(from_start),
+            TimeDelta::from_std(from_end),
+        ) else {
+            return;
+        };
+        let (start, end) = (first + from_start, last - from_end);
+
+        self.laps = self
+            .laps
+            .iter()
+            .filter_map(|lap| {
+                let kept: Vec<TrackPoint> = lap
+                    .track
+                    .track_points
+                    .iter()
+                    .filter(|tp| tp.time >= start && tp.time <= end)
+                    .cloned()
+                    .collect();
+                if kept.len() == lap.total_measurements() {
+                    Some(lap.clone())
+                } else if kept.is_empty() {
+                    None
+                } else {
+                    Some(lap.with_track_points(kept))
+                }
+            })
+            .collect();
+    }
+
+    /// Merges every lap shorter than `min_distance` meters into the lap before it, joining
+    /// their trackpoints and adding up time, distance and calories. Other lap fields (HR,
+    /// extensions) are the preceding lap's, and elevations need recalculating afterwards.
+    /// A short first lap has nothing to merge into and is kept.
+    pub fn coalesce_short_laps(&mut self, min_distance: f32) {
+        let mut laps: Vec<Lap> = Vec::with_capacity(self.laps.len());
+        for lap in self.laps.drain(..) {
+            match laps.last_mut() {
+                Some(previous) if lap.distance < min_distance => {
+                    previous.seconds += lap.seconds;
+                    previous.distance += lap.distance;
+                    previous.calories = match (previous.calories, lap.calories) {
+                        (Some(a), Some(b)) => Some(a + b),
+                        (a, b) => a.or(b),
+                    };
+                    previous.track.track_points.extend(lap.track.track_points);
+                }
+                _ => laps.push(lap),
+            }
+        }
+        self.laps = laps;
+    }
+
+    /// Orders laps chronologically by start time. Merged files sometimes list them out of
+    /// order, which breaks anything walking the trackpoints in sequence.
+    pub fn sort_laps(&mut self) {
+        self.laps.sort_by_key(|l| l.start_time);
+    }
+
+    /// Fills in the time of trackpoints that were missing one, assuming they're evenly
+    /// spaced between the nearest timed trackpoints before and after them in their lap.
+    /// Trackpoints without a timed one on both sides can't be placed and are dropped.
+    pub fn interpolate_missing_times(&mut self) {
+        self.laps
+            .iter_mut()
+            .for_each(|l| l.interpolate_missing_times());
+    }
+
+    /// Removes trackpoints with the same timestamp as the one before them, keeping the
+    /// first. Some devices write each sample twice, which skews averages and counts.
+    /// A lap starting with a repeat of the previous lap's last trackpoint loses it too.
+    pub fn dedup_trackpoints(&mut self) {
+        let mut last_time = None;
+        for lap in self.laps.iter_mut() {
+            lap.track.track_points.retain(|tp| {
+                let repeated = last_time == Some(tp.time);
+                last_time = Some(tp.time);
+                !repeated
+            });
+        }
+    }
+
+    pub fn calc_lap_elevations(&mut self) {
+        self.calc_lap_elevations_with(ElevationOptions::default());
+    }
+
+    /// Calculates lap elevations like [Activity::calc_lap_elevations], with the altitude
+    /// threshold and smoothing in `options` tuned for the recording device. Altitudes are
+    /// smoothed across the whole activity, so the window doesn't start over at each lap.
+    pub fn calc_lap_elevations_with(&mut self, options: ElevationOptions) {
+        let altitudes: Vec<Option<f64>> = self.track_points().map(|tp| tp.altitude).collect();
+        self.accumulate_lap_elevations(altitudes, options);
+    }
+
+    /// Calculates lap elevations like [Activity::calc_lap_elevations], first replacing any
+    /// altitude sample deviating more than `max_deviation` meters from the median of itself
+    /// and its two neighbors, so single-sample spikes don't count as climbs.
+    pub fn calc_elevation_despike(&mut self, max_deviation: f64) {
+        self.calc_elevation_despike_with(max_deviation, ElevationOptions::default());
+    }
+
+    /// Despiked lap elevations like [Activity::calc_elevation_despike], measured with the
+    /// threshold and smoothing in `options` like [Activity::calc_lap_elevations_with].
+    /// Spikes are found across the whole activity, so a lap's first and last samples are
+    /// compared with their neighbors in the laps around it.
+    pub fn calc_elevation_despike_with(&mut self, max_deviation: f64, options: ElevationOptions) {
+        let mut altitudes: Vec<Option<f64>> = self.track_points().map(|tp| tp.altitude).collect();
+        despike_altitudes(&mut altitudes, max_deviation);
+        self.accumulate_lap_elevations(altitudes, options);
+    }
+
+    /// Smooths the activity's `altitudes`, one per trackpoint, by `options` and measures
+    /// each lap's share of them.
+    fn accumulate_lap_elevations(
+        &mut self,
+        mut altitudes: Vec<Option<f64>>,
+        options: ElevationOptions,
+    ) {
+        smooth_altitudes(&mut altitudes, options.smoothing_window);
+        let mut rest = altitudes.as_slice();
+        for lap in self.laps.iter_mut() {
+            let (lap_altitudes, after) = rest.split_at(lap.track.track_points.len());
+            lap.accumulate_elevation(lap_altitudes, options.threshold_meters);
+            rest = after;
+        }
+    }
+
+    /// Average cadence over every lap, as described by [Lap::average_cadence]: steps per
+    /// minute for running and other sports, crank revolutions per minute for biking. 0
+    /// without any laps.
+    pub fn average_cadence(&self) -> usize {
+        let total_cadence: usize = self
+            .laps
+            .iter()
+            .filter_map(|l| l.average_cadence(self.sport))
+            .sum();
+        total_cadence.checked_div(self.lap_count()).unwrap_or(0)
+    }
+
+    /// Average distance covered per step, in meters, from average speed and average
+    /// (two-footed) cadence. `None` without cadence data.
+    pub fn average_stride_length_m(&self) -> Option<f64> {
+        if self.lap_count() == 0 || self.average_cadence() == 0 {
+            return None;
+        }
+        let meters_per_minute = self.average_pace_meters() as f64 * 60.0;
+        Some(meters_per_minute / self.average_cadence() as f64)
+    }
+
+    /// Average of the laps' average watts, 0 without any laps.
+    pub fn average_watts(&self) -> usize {
+        let total_watts: usize = self
+            .laps
+            .iter()
+            .filter_map(|l| l.lx())
+            .map(|lx| lx.avg_watts.unwrap_or(0))
+            .sum();
+        total_watts.checked_div(self.lap_count()).unwrap_or(0)
+    }
+
+    /// Highest HR of the activity, from the laps' maximum HR and every trackpoint's HR.
+    /// `None` without any HR.
+    pub fn max_hr(&self) -> Option<usize> {
+        let laps = self.laps.iter().filter_map(Lap::max_hr);
+        let points = self
+            .track_points()
+            .filter_map(|tp| tp.hr.as_ref().map(|hr| hr.value));
+        laps.chain(points).max()
+    }
+
+    /// Highest power in watts, from the laps' max watts and every trackpoint's watts.
+    /// `None` without any power.
+    pub fn max_watts(&self) -> Option<usize> {
+        let laps = self.laps.iter().filter_map(Lap::max_watts);
+        laps.chain(self.track_points().filter_map(TrackPoint::watts))
+            .max()
+    }
+
+    /// Highest cadence, from the laps' max cadence and every trackpoint's cadence, in the
+    /// units of [Activity::average_cadence]. `None` without any cadence.
+    pub fn max_cadence(&self) -> Option<usize> {
+        let laps = self.laps.iter().filter_map(|l| l.max_cadence(self.sport));
+        let points = self
+            .track_points()
+            .flat_map(|tp| tp.tpx())
+            .filter_map(|tpx| tpx.cadence)
+            .map(|cadence| match self.sport {
+                Sport::Biking => cadence,
+                _ => cadence * 2,
+            });
+        laps.chain(points).max()
+    }
+
+    /// The lap with the fastest pace (distance over time), e.g. the best rep of an interval
+    /// session. Laps without distance or time are ignored, and `None` if that's all of them.
+    pub fn fastest_lap(&self) -> Option<&Lap> {
+        self.timed_laps()
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, lap)| lap)
+    }
+
+    /// The lap with the slowest pace, ignoring the same laps as [Activity::fastest_lap].
+    pub fn slowest_lap(&self) -> Option<&Lap> {
+        self.timed_laps()
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, lap)| lap)
+    }
+
+    /// Laps that covered distance in some time, paired with their speed in meters/s.
+    fn timed_laps(&self) -> impl Iterator<Item = (f32, &Lap)> {
+        self.laps
+            .iter()
+            .filter(|lap| lap.distance > 0.0 && lap.seconds > 0.0)
+            .map(|lap| (lap.distance / lap.seconds, lap))
+    }
+}
+
+impl Lap {
+    /// The Garmin LX extension, wherever it appears among this lap's extensions.
+    fn lx(&self) -> Option<&LXExtension> {
+        self.extensions.iter().find_map(|e| e.lx.as_ref())
+    }
+
+    /// Average heart rate for this lap as recorded by the device.
+    pub fn average_hr(&self) -> Option<usize> {
+        self.average_hr.as_ref().map(|hr| hr.value)
+    }
+
+    /// Average cadence for this lap of a `sport` activity. Biking cadence is whole crank
+    /// revolutions per minute, and is reported as recorded. For other sports it's the
+    /// (two-footed) steps per minute, doubled from the single-foot running cadence.
+    pub fn average_cadence(&self, sport: Sport) -> Option<usize> {
+        match sport {
+            Sport::Biking => self.cadence.or_else(|| self.lx()?.avg_cadence),
+            _ => self.lx()?.avg_cadence.map(|cadence| cadence * 2),
+        }
+    }
+
+    /// Average watts as estimated by the device for this lap.
+    pub fn average_watts(&self) -> Option<usize> {
+        self.lx()?.avg_watts
+    }
+
+    pub fn max_hr(&self) -> Option<usize> {
+        self.maximum_hr.as_ref().map(|hr| hr.value)
+    }
+
+    /// Max cadence for this lap, in the units of [Lap::average_cadence].
+    pub fn max_cadence(&self, sport: Sport) -> Option<usize> {
+        let cadence = self.lx()?.max_cadence?;
+        match sport {
+            Sport::Biking => Some(cadence),
+            _ => Some(cadence * 2),
+        }
+    }
+
+    /// Max watts as estimated by the device for this lap.
+    pub fn max_watts(&self) -> Option<usize> {
+        self.lx()?.max_watts
+    }
+
+    /// Pace for this lap in seconds per mile. `None` if no distance was covered.
+    pub fn pace_seconds(&self) -> Option<Duration> {
+        if self.distance <= 0.0 {
+            return None;
+        }
+        let seconds_per_mile = (self.seconds * METERS_PER_MILE / self.distance).round() as u64;
+        Some(Duration::from_secs(seconds_per_mile))
+    }
+
+    /// Average distance covered per step in this lap, in meters. `None` without cadence data.
+    pub fn average_stride_length_m(&self) -> Option<f64> {
+        let steps_per_minute = self.lx()?.avg_cadence? * 2;
+        if steps_per_minute == 0 || self.seconds <= 0.0 {
+            return None;
+        }
+        let meters_per_minute = self.distance as f64 / self.seconds as f64 * 60.0;
+        Some(meters_per_minute / steps_per_minute as f64)
+    }
+
+    /// A copy of this lap holding only `track_points` (a subset of its own), with time,
+    /// distance and calories recomputed for that subset. Calories are split by the share of
+    /// trackpoints kept, and elevation needs recalculating.
+    fn with_track_points(&self, track_points: Vec<TrackPoint>) -> Lap {
+        let (first, last) = (&track_points[0], &track_points[track_points.len() - 1]);
+        let share = track_points.len() as f32 / self.total_measurements().max(1) as f32;
+        Lap {
+            start_time: first.time,
+            seconds: (last.time - first.time).num_milliseconds() as f32 / 1000.0,
+            calories: self
+                .calories
+                .map(|calories| (calories as f32 * share).round() as usize),
+            distance: last.distance - first.distance,
+            average_hr: self.average_hr.clone(),
+            maximum_hr: self.maximum_hr.clone(),
+            cadence: self.cadence,
+            extensions: self.extensions.clone(),
+            last_alt: 0.0,
+            alt_gain_meters: 0.0,
+            alt_loss_meters: 0.0,
+            track: Track { track_points },
+        }
+    }
+
+    /// See [Activity::interpolate_missing_times].
+    fn interpolate_missing_times(&mut self) {
+        let points = &mut self.track.track_points;
+        let timed: Vec<usize> = points
+            .iter()
+            .enumerate()
+            .filter(|(_, tp)| tp.time != missing_time())
+            .map(|(i, _)| i)
+            .collect();
+        if timed.len() == points.len() {
+            return;
+        }
+
+        for pair in timed.windows(2) {
+            let (before, after) = (pair[0], pair[1]);
+            let start = points[before].time;
+            let step = (points[after].time - start) / (after - before) as i32;
+            for (offset, tp) in points[before + 1..after].iter_mut().enumerate() {
+                tp.time = start + step * (offset + 1) as i32;
+            }
+        }
+        match (timed.first(), timed.last()) {
+            (Some(&first), Some(&last)) => {
+                points.truncate(last + 1);
+                points.drain(..first);
+            }
+            _ => points.clear(),
+        }
+    }
+
+    /// The total amount of Trackpoint measurements
+    /// this lap contains.
+    fn total_measurements(&self) -> usize {
+        self.track.track_points.len()
+    }
+
+    /// The number of Trackpoint measurements in this lap that recorded HR.
+    fn hr_measurements(&self) -> usize {
+        self.track
+            .track_points
+            .iter()
+            .filter(|tp| tp.hr.is_some())
+            .count()
+    }
+
+    /// The total of all individual HR values, used to calculate
+    /// the average HR across multiple laps.
+    fn total_hr(&self) -> usize {
+        self.track
+            .track_points
+            .iter()
+            .filter_map(|tp| tp.hr.as_ref())
+            .map(|hr| hr.value)
+            .sum()
+    }
+
+    fn accumulate_elevation(&mut self, altitudes: &[Option<f64>], threshold: f64) {
+        let change = ElevationChange::of(altitudes, threshold);
+        self.last_alt = change.last_alt;
+        self.alt_gain_meters = change.gain_meters;
+        self.alt_loss_meters = change.loss_meters;
     }
 
     /*
     TODO: Average watts, average cadence
      */
 }
+
+impl TrackPoint {
+    /// Every Garmin TPX extension, wherever they appear among this trackpoint's extensions.
+    fn tpx(&self) -> impl Iterator<Item = &TPXExtension> {
+        self.extensions.iter().flat_map(|e| e.tpx.iter())
+    }
+
+    fn watts(&self) -> Option<usize> {
+        self.tpx().find_map(|tpx| tpx.watts)
+    }
+
+    /// Speed in meters/s as reported by the device. See [Activity::speed_series] for speeds
+    /// that fall back to distance over time when the device didn't report any.
+    pub fn speed_ms(&self) -> Option<f64> {
+        self.tpx().find_map(|tpx| tpx.speed)
+    }
+}
+
+impl Position {
+    /// Whether this is a plausible position: within lat/long range and not exactly 0/0
+    /// ("null island"), which devices report when they have no GPS fix.
+    pub fn is_valid(&self) -> bool {
+        let null_island = self.lat == 0.0 && self.long == 0.0;
+        !null_island && self.lat.abs() <= 90.0 && self.long.abs() <= 180.0
+    }
+
+    /// Great-circle distance in meters to `other`, using the haversine formula.
+    fn distance_to(&self, other: &Position) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_long = (other.long - self.long).to_radians();
+
+        let a =
+            (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_long / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_METERS * a.sqrt().asin()
+    }
+
+    /// Shortest distance in meters to any of the lines joining consecutive `points`. Over
+    /// segment-sized distances the earth is treated as flat around this position.
+    fn distance_to_polyline(&self, points: &[Position]) -> f64 {
+        let lat_scale = EARTH_RADIUS_METERS.to_radians();
+        let long_scale = lat_scale * self.lat.to_radians().cos();
+        let local = |p: &Position| {
+            (
+                (p.long - self.long) * long_scale,
+                (p.lat - self.lat) * lat_scale,
+            )
+        };
+        points
+            .windows(2)
+            .map(|pair| {
+                let ((x0, y0), (x1, y1)) = (local(&pair[0]), local(&pair[1]));
+                let (dx, dy) = (x1 - x0, y1 - y0);
+                let length = dx * dx + dy * dy;
+                // Fraction of the way along the line of the point nearest this position.
+                let t = if length > 0.0 {
+                    (-(x0 * dx + y0 * dy) / length).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (x0 + t * dx).hypot(y0 + t * dy)
+            })
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    /// The first activity of a TCX document holding a running activity with `laps`.
+    fn running(laps: &str) -> Activity {
+        let tcx = format!(
+            "<TrainingCenterDatabase><Activities><Activity Sport=\"Running\">\
+             <Id>2024-03-01T12:00:00Z</Id>{}</Activity></Activities></TrainingCenterDatabase>",
+            laps
+        );
+        parse_str(&tcx).unwrap().get_activity(0).unwrap().clone()
+    }
+
+    /// A one-lap run with a trackpoint every 10 seconds and 10 meters at each of
+    /// `altitudes`, e.g. `"100"`, or none for an empty string.
+    fn with_altitudes(altitudes: &[&str]) -> Activity {
+        let trackpoints: String = altitudes
+            .iter()
+            .enumerate()
+            .map(|(i, altitude)| {
+                let altitude = if altitude.is_empty() {
+                    String::new()
+                } else {
+                    format!("<AltitudeMeters>{}</AltitudeMeters>", altitude)
+                };
+                format!(
+                    "<Trackpoint><Time>{}</Time><DistanceMeters>{}</DistanceMeters>{}</Trackpoint>",
+                    Utc.timestamp_opt(1709294400 + 10 * i as i64, 0)
+                        .unwrap()
+                        .to_rfc3339(),
+                    10 * i,
+                    altitude
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>{}</TotalTimeSeconds>\
+             <DistanceMeters>{}</DistanceMeters><Track>{}</Track></Lap>",
+            10 * altitudes.len(),
+            10 * altitudes.len(),
+            trackpoints
+        ))
+    }
+
+    #[test]
+    fn detect_surges_counts_the_first_interval_of_a_surge() {
+        // One 400m minute, a 4:01/mi pace.
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>400</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:01:00Z</Time><DistanceMeters>400</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        let minute = Duration::from_secs(60);
+        assert_eq!(
+            activity.detect_surges(300.0, minute),
+            vec![(Duration::ZERO, minute)]
+        );
+        assert!(activity
+            .detect_surges(300.0, minute + Duration::from_secs(1))
+            .is_empty());
+    }
+
+    #[test]
+    fn detect_surges_starts_after_the_last_slower_sample() {
+        // Two slow minutes, then a fast one the smoothed pace crosses the threshold in.
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>180</TotalTimeSeconds><DistanceMeters>1000</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:01:00Z</Time><DistanceMeters>200</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:02:00Z</Time><DistanceMeters>400</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:03:00Z</Time><DistanceMeters>1000</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        assert_eq!(
+            activity.detect_surges(300.0, Duration::from_secs(60)),
+            vec![(Duration::from_secs(120), Duration::from_secs(180))]
+        );
+    }
+
+    #[test]
+    fn detect_surges_finds_a_two_minute_surge() {
+        // 1Hz for 10 minutes at 3m/s, with a 5m/s surge from minute 4 to 6.
+        let mut distance = 0.0;
+        let mut trackpoints = String::new();
+        for second in 0..=600 {
+            if second > 0 {
+                distance += if (241..=360).contains(&second) {
+                    5.0
+                } else {
+                    3.0
+                };
+            }
+            let time = Utc.timestamp_opt(1709294400 + second, 0).unwrap();
+            trackpoints.push_str(&format!(
+                "<Trackpoint><Time>{}</Time><DistanceMeters>{}</DistanceMeters></Trackpoint>",
+                time.to_rfc3339(),
+                distance
+            ));
+        }
+        let activity = running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>600</TotalTimeSeconds>\
+             <DistanceMeters>{}</DistanceMeters><Track>{}</Track></Lap>",
+            distance, trackpoints
+        ));
+
+        // 3m/s is 8:56/mi and 5m/s 5:22/mi; the 30s smoothing crosses 7:00/mi partway in.
+        let surges = activity.detect_surges(420.0, Duration::from_secs(90));
+        assert_eq!(surges.len(), 1);
+        let (start, end) = surges[0];
+        assert!((250..=270).contains(&start.as_secs()), "{:?}", start);
+        assert!((370..=390).contains(&end.as_secs()), "{:?}", end);
+    }
+
+    #[test]
+    fn obfuscate_start_drops_points_near_the_start_and_end() {
+        // Five points about 111m apart heading north, and one without a position.
+        let mut trackpoints = String::new();
+        for (i, lat) in ["40.000", "40.001", "40.002", "", "40.003", "40.004"]
+            .iter()
+            .enumerate()
+        {
+            let position = if lat.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    "<Position><LatitudeDegrees>{}</LatitudeDegrees>\
+                     <LongitudeDegrees>-75.0</LongitudeDegrees></Position>",
+                    lat
+                )
+            };
+            trackpoints.push_str(&format!(
+                "<Trackpoint><Time>2024-03-01T12:0{}:00Z</Time>{}</Trackpoint>",
+                i, position
+            ));
+        }
+        let mut activity = running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>300</TotalTimeSeconds>\
+             <DistanceMeters>450</DistanceMeters><Track>{}</Track></Lap>",
+            trackpoints
+        ));
+
+        activity.obfuscate_start(150.0);
+        let kept: Vec<Option<f64>> = activity
+            .track_points()
+            .map(|tp| tp.position.map(|pos| pos.lat))
+            .collect();
+        assert_eq!(kept, vec![Some(40.002), None]);
+    }
+
+    #[test]
+    fn obfuscate_start_keeps_tracks_without_gps() {
+        let mut activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>200</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:01:00Z</Time></Trackpoint>
+</Track></Lap>"#,
+        );
+        activity.obfuscate_start(1000.0);
+        assert_eq!(activity.track_points().count(), 2);
+    }
+
+    #[test]
+    fn integer_and_decimal_numbers_parse_alike() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>5000</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>12</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><DistanceMeters> 14.5 </DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        let lap = &activity.laps[0];
+        assert_eq!((lap.seconds, lap.distance), (600.0, 5000.0));
+        let distances: Vec<f32> = activity.track_points().map(|tp| tp.distance).collect();
+        assert_eq!(distances, vec![12.0, 14.5]);
+    }
+
+    #[test]
+    fn non_numeric_distances_fail_to_parse() {
+        let tcx = "<TrainingCenterDatabase><Activities><Activity Sport=\"Running\">\
+             <Id>2024-03-01T12:00:00Z</Id><Lap StartTime=\"2024-03-01T12:00:00Z\">\
+             <TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>five</DistanceMeters>\
+             </Lap></Activity></Activities></TrainingCenterDatabase>";
+        assert!(parse_str(tcx).is_err());
+    }
+
+    #[test]
+    fn calc_elevation_despike_drops_single_sample_spikes() {
+        let mut activity = with_altitudes(&["100", "100", "130", "100", "100"]);
+        activity.calc_lap_elevations();
+        assert_eq!(activity.total_elevation_gain(), 98);
+
+        activity.calc_elevation_despike(5.0);
+        assert_eq!(activity.total_elevation_gain(), 0);
+        assert_eq!(activity.total_elevation_loss(), 0);
+    }
+
+    #[test]
+    fn calc_elevation_despike_keeps_steady_climbs() {
+        let mut activity = with_altitudes(&["100", "", "104", "108", "112"]);
+        activity.calc_elevation_despike(5.0);
+        assert_eq!(activity.total_elevation_gain(), 39);
+    }
+
+    #[test]
+    fn calc_elevation_despike_looks_across_laps() {
+        let lap = |start: &str, altitudes: [u32; 3]| {
+            let trackpoints: String = altitudes
+                .iter()
+                .map(|alt| {
+                    format!(
+                        "<Trackpoint><Time>{}</Time><AltitudeMeters>{}</AltitudeMeters></Trackpoint>",
+                        start, alt
+                    )
+                })
+                .collect();
+            format!(
+                "<Lap StartTime=\"{}\"><TotalTimeSeconds>60</TotalTimeSeconds>\
+                 <DistanceMeters>200</DistanceMeters><Track>{}</Track></Lap>",
+                start, trackpoints
+            )
+        };
+        // The spike is the first sample of the second lap.
+        let mut activity = running(&format!(
+            "{}{}",
+            lap("2024-03-01T12:00:00Z", [100, 100, 100]),
+            lap("2024-03-01T12:01:00Z", [130, 100, 100])
+        ));
+        activity.calc_elevation_despike(5.0);
+        assert_eq!(activity.total_elevation_gain(), 0);
+        assert_eq!(activity.total_elevation_loss(), 0);
+
+        let mut activity = with_altitudes(&["100", "", "104", "108", "112"]);
+        let options = ElevationOptions {
+            threshold_meters: 20.0,
+            smoothing_window: 1,
+        };
+        activity.calc_elevation_despike_with(5.0, options);
+        assert_eq!(activity.total_elevation_gain(), 0);
+    }
+
+    /// A one-lap run reporting `meters`, with three trackpoints a second apart heading
+    /// north from 40°N by 0.001° (111m) each.
+    fn gps_run(meters: f32) -> Activity {
+        let trackpoints: String = ["40.000", "40.001", "40.002"]
+            .iter()
+            .enumerate()
+            .map(|(i, lat)| {
+                format!(
+                    "<Trackpoint><Time>2024-03-01T12:00:0{}Z</Time>\
+                     <Position><LatitudeDegrees>{}</LatitudeDegrees>\
+                     <LongitudeDegrees>-75.0</LongitudeDegrees></Position></Trackpoint>",
+                    i, lat
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>2</TotalTimeSeconds>\
+             <DistanceMeters>{}</DistanceMeters><Track>{}</Track></Lap>",
+            meters, trackpoints
+        ))
+    }
+
+    #[test]
+    fn gps_distance_follows_the_positions() {
+        let activity = gps_run(222.4);
+        assert!((activity.gps_distance_meters() - 222.4).abs() < 0.5);
+        assert!((activity.distance_discrepancy().unwrap() - 1.0).abs() < 0.01);
+        assert!(activity.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_warns_about_a_distance_discrepancy() {
+        let activity = gps_run(444.8);
+        let warnings = activity.validate();
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            matches!(warnings[0], ValidationWarning::DistanceDiscrepancy(ratio) if (ratio - 2.0).abs() < 0.01),
+            "{:?}",
+            warnings
+        );
+    }
+
+    #[test]
+    fn distance_discrepancy_needs_gps() {
+        let activity = with_altitudes(&["100", "100"]);
+        assert_eq!(activity.gps_distance_meters(), 0.0);
+        assert_eq!(activity.distance_discrepancy(), None);
+    }
+
+    #[test]
+    fn impossible_positions_parse_as_none() {
+        let trackpoints: String = [("0", "0"), ("91", "-75"), ("40", "-181"), ("40", "-75")]
+            .iter()
+            .enumerate()
+            .map(|(i, (lat, long))| {
+                format!(
+                    "<Trackpoint><Time>2024-03-01T12:00:0{}Z</Time>\
+                     <Position><LatitudeDegrees>{}</LatitudeDegrees>\
+                     <LongitudeDegrees>{}</LongitudeDegrees></Position></Trackpoint>",
+                    i, lat, long
+                )
+            })
+            .collect();
+        let activity = running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>3</TotalTimeSeconds>\
+             <DistanceMeters>10</DistanceMeters><Track>{}</Track></Lap>",
+            trackpoints
+        ));
+        let positions: Vec<Option<Position>> =
+            activity.track_points().map(|tp| tp.position).collect();
+        assert_eq!(
+            positions,
+            vec![
+                None,
+                None,
+                None,
+                Some(Position {
+                    lat: 40.0,
+                    long: -75.0
+                })
+            ]
+        );
+        assert_eq!(activity.gps_distance_meters(), 0.0);
+    }
+
+    #[test]
+    fn null_island_is_only_exactly_zero() {
+        assert!(!Position {
+            lat: 0.0,
+            long: 0.0
+        }
+        .is_valid());
+        assert!(Position {
+            lat: 0.0,
+            long: 0.001
+        }
+        .is_valid());
+        assert!(Position {
+            lat: -90.0,
+            long: 180.0
+        }
+        .is_valid());
+    }
+
+    #[test]
+    fn split_at_pauses_splits_a_lap_spanning_the_pause() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>610</TotalTimeSeconds><DistanceMeters>300</DistanceMeters><Calories>100</Calories><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:10Z</Time><DistanceMeters>40</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:10:00Z</Time><DistanceMeters>250</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:10:10Z</Time><DistanceMeters>300</DistanceMeters></Trackpoint>
+</Track></Lap>
+<Lap StartTime="2024-03-01T12:10:10Z"><TotalTimeSeconds>10</TotalTimeSeconds><DistanceMeters>40</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:10:20Z</Time><DistanceMeters>340</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+
+        let pieces = activity.split_at_pauses(Duration::from_secs(60));
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].id, "2024-03-01T12:00:00Z");
+        assert_eq!(pieces[1].id, "2024-03-01T12:10:00Z");
+        assert_eq!(pieces[0].laps.len(), 1);
+        assert_eq!(pieces[1].laps.len(), 2);
+        let first = &pieces[0].laps[0];
+        assert_eq!(
+            (first.seconds, first.distance, first.calories),
+            (10.0, 40.0, Some(50))
+        );
+        let resumed = &pieces[1].laps[0];
+        assert_eq!(resumed.start_time, pieces[1].start_time().unwrap());
+        assert_eq!((resumed.seconds, resumed.distance), (10.0, 50.0));
+    }
+
+    #[test]
+    fn split_at_pauses_keeps_an_unpaused_activity_whole() {
+        let activity = with_altitudes(&["100", "100", "100"]);
+        let pieces = activity.split_at_pauses(Duration::from_secs(10));
+        assert_eq!(pieces.len(), 1);
+        assert_eq!(pieces[0].id, activity.id);
+        assert_eq!(pieces[0].track_points().count(), 3);
+    }
+
+    #[test]
+    fn courses_parse_without_activities() {
+        let tcx = parse_str(
+            r#"<TrainingCenterDatabase><Courses><Course><Name>River loop</Name>
+<Lap><TotalTimeSeconds>1800</TotalTimeSeconds><DistanceMeters>5000</DistanceMeters></Lap>
+<Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Position><LatitudeDegrees>40.0</LatitudeDegrees><LongitudeDegrees>-75.0</LongitudeDegrees></Position><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><Position><LatitudeDegrees>40.001</LatitudeDegrees><LongitudeDegrees>-75.0</LongitudeDegrees></Position><DistanceMeters>111</DistanceMeters></Trackpoint>
+</Track></Course></Courses></TrainingCenterDatabase>"#,
+        )
+        .unwrap();
+        assert!(tcx.get_activity(0).is_none());
+        let course = &tcx.courses()[0];
+        assert_eq!(course.name, "River loop");
+        assert_eq!(course.total_distance_meters(), 5000.0);
+        assert_eq!(course.total_seconds(), 1800.0);
+        assert_eq!(course.positions().len(), 2);
+    }
+
+    #[test]
+    fn course_distance_falls_back_to_the_track() {
+        let tcx = parse_str(
+            r#"<TrainingCenterDatabase><Courses><Course><Name>Out and back</Name><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><DistanceMeters>2500</DistanceMeters></Trackpoint>
+</Track></Course></Courses></TrainingCenterDatabase>"#,
+        )
+        .unwrap();
+        assert_eq!(tcx.courses()[0].total_distance_meters(), 2500.0);
+        assert_eq!(tcx.courses()[0].total_seconds(), 0.0);
+    }
+
+    #[test]
+    fn vendor_extensions_and_omitted_elements_parse() {
+        let tcx = parse_str(
+            r#"<TrainingCenterDatabase xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2" xmlns:v="http://example.com/vendor">
+<Activities><Activity Sport="Biking"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>500</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Extensions><v:Temperature>21</v:Temperature></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><Extensions><v:Temperature>21</v:Temperature><ns3:TPX><ns3:Speed>8.5</ns3:Speed><v:Ignored>1</v:Ignored><ns3:Watts>210</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+</Track>
+<Extensions><v:LapNote>windy</v:LapNote><ns3:LX><ns3:AvgWatts>200</ns3:AvgWatts></ns3:LX></Extensions></Lap>
+<Lap StartTime="2024-03-01T12:01:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>0</DistanceMeters><Extensions><v:LapNote>rest</v:LapNote></Extensions></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#,
+        )
+        .unwrap();
+        let activity = tcx.get_activity(0).unwrap();
+        assert_eq!(activity.creator(), "");
+        assert!(activity.laps[1].track.track_points.is_empty());
+        assert_eq!(activity.laps[0].lx().and_then(|lx| lx.avg_watts), Some(200));
+        assert!(activity.laps[1].lx().is_none());
+        let points: Vec<&TrackPoint> = activity.track_points().collect();
+        assert_eq!((points[0].watts(), points[0].speed_ms()), (None, None));
+        assert_eq!(
+            (points[1].watts(), points[1].speed_ms()),
+            (Some(210), Some(8.5))
+        );
+    }
+
+    #[test]
+    fn clamped_pace_series_drops_implausible_intervals() {
+        // 30m every 10s (8:56/mi), except a 2km jump in one interval.
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>40</TotalTimeSeconds><DistanceMeters>2090</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:10Z</Time><DistanceMeters>30</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:20Z</Time><DistanceMeters>60</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:30Z</Time><DistanceMeters>2060</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:40Z</Time><DistanceMeters>2090</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        let pace = 10.0 * METERS_PER_MILE / 30.0;
+        let series = activity.smoothed_pace_series(2);
+        let elapsed: Vec<u64> = series.iter().map(|(time, _)| time.as_secs()).collect();
+        assert_eq!(elapsed, vec![10, 20, 40]);
+        assert!(
+            series.iter().all(|(_, p)| (p - pace).abs() < 0.1),
+            "{:?}",
+            series
+        );
+
+        let unclamped = PaceClamp {
+            fastest: 0.0,
+            slowest: f32::MAX,
+        };
+        assert_eq!(activity.clamped_pace_series(1, unclamped).len(), 4);
+    }
+
+    #[test]
+    fn pace_clamp_bounds_are_inclusive() {
+        let clamp = PaceClamp::default();
+        assert!(clamp.contains(120.0) && clamp.contains(3600.0));
+        assert!(!clamp.contains(119.9) && !clamp.contains(3600.1));
+    }
+
+    #[test]
+    fn laps_are_sorted_by_start_time() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:10:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters></Lap>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>1000</DistanceMeters></Lap>
+<Lap StartTime="2024-03-01T12:20:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>3000</DistanceMeters></Lap>"#,
+        );
+        let distances: Vec<f32> = activity.laps.iter().map(|lap| lap.distance).collect();
+        assert_eq!(distances, vec![1000.0, 2000.0, 3000.0]);
+    }
+
+    #[test]
+    fn total_calories_only_counts_laps_that_report_them() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters><Calories>150</Calories></Lap>
+<Lap StartTime="2024-03-01T12:10:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters></Lap>"#,
+        );
+        assert_eq!(activity.total_calories(), Some(150));
+        assert_eq!(activity.estimate_calories(70.0), 150);
+
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters></Lap>"#,
+        );
+        assert_eq!(activity.total_calories(), None);
+    }
+
+    #[test]
+    fn estimate_calories_without_any_reported() {
+        // An hour at 10km/h: (0.2 * 166.7m/min + 3.5) / 3.5 = 10.5 METs.
+        let run = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3600</TotalTimeSeconds><DistanceMeters>10000</DistanceMeters></Lap>"#,
+        );
+        assert_eq!(run.estimate_calories(70.0), 737);
+
+        let mut ride = run.clone();
+        ride.sport = Sport::Biking;
+        assert_eq!(ride.estimate_calories(70.0), 560);
+    }
+
+    #[test]
+    fn average_hr_skips_trackpoints_without_hr() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3</TotalTimeSeconds><DistanceMeters>10</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:02Z</Time><HeartRateBpm><Value>160</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:03Z</Time></Trackpoint>
+</Track></Lap>"#,
+        );
+        assert_eq!(activity.average_hr(), 150);
+    }
+
+    #[test]
+    fn average_hr_falls_back_to_the_laps() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters><AverageHeartRateBpm><Value>140</Value></AverageHeartRateBpm></Lap>
+<Lap StartTime="2024-03-01T12:10:00Z"><TotalTimeSeconds>1200</TotalTimeSeconds><DistanceMeters>4000</DistanceMeters><AverageHeartRateBpm><Value>170</Value></AverageHeartRateBpm></Lap>
+<Lap StartTime="2024-03-01T12:30:00Z"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>2000</DistanceMeters></Lap>"#,
+        );
+        assert_eq!(activity.average_hr(), 160);
+        assert_eq!(with_altitudes(&["100"]).average_hr(), 0);
+    }
+
+    #[test]
+    fn repair_distances_never_goes_backwards() {
+        // Distance dips in the first lap and again at the start of the second.
+        let mut activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3</TotalTimeSeconds><DistanceMeters>30</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><DistanceMeters>20</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:02Z</Time><DistanceMeters>15</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:03Z</Time><DistanceMeters>30</DistanceMeters></Trackpoint>
+</Track></Lap>
+<Lap StartTime="2024-03-01T12:00:04Z"><TotalTimeSeconds>2</TotalTimeSeconds><DistanceMeters>20</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:04Z</Time><DistanceMeters>28</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:05Z</Time><DistanceMeters>50</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        assert_eq!(activity.decreasing_distance_count(), 2);
+        assert!(matches!(
+            activity.validate().as_slice(),
+            [ValidationWarning::DecreasingDistance(2)]
+        ));
+
+        activity.repair_distances();
+        let distances: Vec<f32> = activity.track_points().map(|tp| tp.distance).collect();
+        assert_eq!(distances, vec![0.0, 20.0, 20.0, 30.0, 30.0, 50.0]);
+        assert_eq!(activity.decreasing_distance_count(), 0);
+    }
+
+    /// A ride of two trackpoints 1000s apart at `watts`, whose lap reports 900 calories.
+    fn powered_ride(watts: &str) -> Activity {
+        let tcx = format!(
+            r#"<TrainingCenterDatabase xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2"><Activities><Activity Sport="Biking"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>1000</TotalTimeSeconds><DistanceMeters>8000</DistanceMeters><Calories>900</Calories><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Extensions><ns3:TPX>{watts}</ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:16:40Z</Time><Extensions><ns3:TPX>{watts}</ns3:TPX></Extensions></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#
+        );
+        parse_str(&tcx).unwrap().get_activity(0).unwrap().clone()
+    }
+
+    #[test]
+    fn calories_from_power_are_preferred_over_the_device() {
+        let activity = powered_ride("<ns3:Watts>200</ns3:Watts>");
+        assert_eq!(activity.total_work_kj(), Some(200.0));
+        assert_eq!(activity.calories_from_power(), Some(200));
+        assert_eq!(activity.estimate_calories(70.0), 200);
+    }
+
+    #[test]
+    fn calories_from_power_need_power() {
+        let activity = powered_ride("");
+        assert_eq!(activity.calories_from_power(), None);
+        assert_eq!(activity.estimate_calories(70.0), 900);
+    }
+
+    #[test]
+    fn biking_cadence_is_reported_in_rpm() {
+        let lap = r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>500</DistanceMeters><Cadence>90</Cadence><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Extensions><ns3:TPX><ns3:RunCadence>95</ns3:RunCadence></ns3:TPX></Extensions></Trackpoint>
+</Track><Extensions><ns3:LX><ns3:AvgRunCadence>85</ns3:AvgRunCadence></ns3:LX></Extensions></Lap>"#;
+        let activity = |sport: &str| {
+            let tcx = format!(
+                "<TrainingCenterDatabase xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\">\
+                 <Activities><Activity Sport=\"{}\"><Id>2024-03-01T12:00:00Z</Id>{}</Activity>\
+                 </Activities></TrainingCenterDatabase>",
+                sport, lap
+            );
+            parse_str(&tcx).unwrap().get_activity(0).unwrap().clone()
+        };
+
+        let ride = activity("Biking");
+        assert_eq!((ride.average_cadence(), ride.max_cadence()), (90, Some(95)));
+        let run = activity("Running");
+        assert_eq!((run.average_cadence(), run.max_cadence()), (170, Some(190)));
+    }
+
+    #[test]
+    fn missing_times_are_interpolated() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>30</TotalTimeSeconds><DistanceMeters>90</DistanceMeters><Track>
+<Trackpoint><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>10</DistanceMeters></Trackpoint>
+<Trackpoint><DistanceMeters>40</DistanceMeters></Trackpoint>
+<Trackpoint><DistanceMeters>70</DistanceMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:30Z</Time><DistanceMeters>90</DistanceMeters></Trackpoint>
+<Trackpoint><DistanceMeters>95</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        let times: Vec<String> = activity
+            .track_points()
+            .map(|tp| tp.time.format("%H:%M:%S").to_string())
+            .collect();
+        assert_eq!(times, ["12:00:00", "12:00:10", "12:00:20", "12:00:30"]);
+    }
+
+    #[test]
+    fn trackpoints_without_any_times_are_dropped() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>30</TotalTimeSeconds><DistanceMeters>90</DistanceMeters><Track>
+<Trackpoint><DistanceMeters>0</DistanceMeters></Trackpoint>
+<Trackpoint><DistanceMeters>90</DistanceMeters></Trackpoint>
+</Track></Lap>"#,
+        );
+        assert_eq!(activity.track_points().count(), 0);
+        assert_eq!(activity.total_distance_meters(), 90.0);
+    }
+
+    #[test]
+    fn decimal_hr_is_rounded_to_whole_bpm() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>2</TotalTimeSeconds><DistanceMeters>10</DistanceMeters>
+<AverageHeartRateBpm><Value>150.4</Value></AverageHeartRateBpm><MaximumHeartRateBpm><Value> 171.5 </Value></MaximumHeartRateBpm><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><HeartRateBpm><Value>149.6</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><HeartRateBpm><Value>151</Value></HeartRateBpm></Trackpoint>
+</Track></Lap>"#,
+        );
+        let lap = &activity.laps[0];
+        assert_eq!((lap.average_hr(), lap.max_hr()), (Some(150), Some(172)));
+        assert_eq!(activity.average_hr(), 150);
+    }
+
+    #[test]
+    fn negative_hr_fails_to_parse() {
+        let tcx = "<TrainingCenterDatabase><Activities><Activity Sport=\"Running\">\
+             <Id>2024-03-01T12:00:00Z</Id><Lap StartTime=\"2024-03-01T12:00:00Z\">\
+             <TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>1000</DistanceMeters>\
+             <AverageHeartRateBpm><Value>-150</Value></AverageHeartRateBpm>\
+             </Lap></Activity></Activities></TrainingCenterDatabase>";
+        assert!(parse_str(tcx).is_err());
+    }
+
+    #[test]
+    fn start_time_falls_back_to_the_first_lap() {
+        let mut activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:05Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>200</DistanceMeters></Lap>"#,
+        );
+        let id_time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap();
+        assert_eq!(activity.start_time(), Some(id_time));
+
+        activity.id = String::from("2024-03-01T07:00:00-05:00");
+        assert_eq!(activity.start_time(), Some(id_time));
+
+        activity.id = String::from("c0ffee00-1234-4321-abcd-000000000000");
+        let lap_time = Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 5).unwrap();
+        assert_eq!(activity.start_time(), Some(lap_time));
+
+        activity.laps.clear();
+        assert_eq!(activity.start_time(), None);
+    }
+
+    #[test]
+    fn averages_are_zero_without_laps() {
+        let activity = Activity {
+            sport: Sport::Running,
+            id: String::from("2024-03-01T12:00:00Z"),
+            laps: vec![],
+            creator: Creator::default(),
+        };
+        assert_eq!(activity.average_cadence(), 0);
+        assert_eq!(activity.average_watts(), 0);
+        assert_eq!(activity.average_hr(), 0);
+        assert_eq!(activity.average_stride_length_m(), None);
+    }
+
+    #[test]
+    fn effective_distance_falls_back_to_gps() {
+        let stripped = gps_run(0.0);
+        assert!((stripped.effective_distance_meters() - 222.4).abs() < 0.5);
+        assert!(stripped.average_pace_seconds() > Duration::ZERO);
+
+        assert_eq!(gps_run(5000.0).effective_distance_meters(), 5000.0);
+        // Without GPS there's nothing better than what the laps report.
+        let mut treadmill = with_altitudes(&["100", "100"]);
+        treadmill.laps[0].distance = 0.0;
+        assert_eq!(treadmill.effective_distance_meters(), 0.0);
+    }
+
+    #[test]
+    fn grade_adjusted_pace_is_pace_on_the_flat() {
+        let flat = with_altitudes(&["100", "100", "100", "100", "100"]);
+        assert_eq!(flat.average_grade(), Some(0.0));
+        assert_eq!(
+            flat.grade_adjusted_pace_seconds(),
+            flat.average_pace_seconds()
+        );
+    }
+
+    #[test]
+    fn grade_adjusted_pace_is_faster_uphill_and_slower_downhill() {
+        // A 5% grade costs 4.685 J/kg/m against 3.6 on the flat, so the 40m climbed count
+        // as 52.06m: 50s over 62.06m is 21:37/mi, against 26:49/mi raw.
+        let uphill = with_altitudes(&["100", "100.5", "101", "101.5", "102"]);
+        assert_eq!(uphill.average_pace_seconds().as_secs(), 1609);
+        let gap = uphill.grade_adjusted_pace_seconds().as_secs();
+        assert!((1296..=1298).contains(&gap), "{}", gap);
+
+        let downhill = with_altitudes(&["102", "101.5", "101", "100.5", "100"]);
+        assert!(downhill.grade_adjusted_pace_seconds() > downhill.average_pace_seconds());
+    }
+
+    #[test]
+    fn elevation_is_measured_from_the_first_recorded_altitude() {
+        let mut activity = with_altitudes(&["", "", "100", "105", "103"]);
+        activity.calc_lap_elevations();
+        assert_eq!(activity.total_elevation_gain(), 16);
+        assert_eq!(activity.total_elevation_loss(), 7);
+    }
+
+    #[test]
+    fn smoothing_runs_across_lap_boundaries() {
+        let mut activity = with_altitudes(&["100", "104", "100", "104", "100", "104", "108"]);
+        let mut second = activity.laps[0].clone();
+        second.track.track_points = activity.laps[0].track.track_points.split_off(3);
+        activity.laps.push(second);
+
+        activity.calc_lap_elevations_with(ElevationOptions {
+            threshold_meters: 1.0,
+            smoothing_window: 3,
+        });
+        // The second lap's first altitude averages in the last one of the first lap,
+        // smoothing to 101.33, 102.67, 104 and 106. Smoothing each lap on its own would
+        // have started it at 102 instead, for only 4m of gain.
+        let gain = activity.laps[1].alt_gain_meters;
+        assert!((gain - 14.0 / 3.0).abs() < 1e-9, "{}", gain);
+        assert_eq!(activity.laps[0].alt_gain_meters, 0.0);
+    }
+
+    #[test]
+    fn hr_zones_attribute_each_interval_to_its_starting_hr() {
+        let trackpoints: String = ["100", "130", "", "130", "170", "180"]
+            .iter()
+            .enumerate()
+            .map(|(i, hr)| {
+                let hr = if hr.is_empty() {
+                    String::new()
+                } else {
+                    format!("<HeartRateBpm><Value>{}</Value></HeartRateBpm>", hr)
+                };
+                format!(
+                    "<Trackpoint><Time>2024-03-01T12:00:{}0Z</Time>{}</Trackpoint>",
+                    i, hr
+                )
+            })
+            .collect();
+        let activity = running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>50</TotalTimeSeconds>\
+             <DistanceMeters>150</DistanceMeters><Track>{}</Track></Lap>",
+            trackpoints
+        ));
+        let seconds: Vec<u64> = activity
+            .hr_zones(&[120, 140, 160])
+            .iter()
+            .map(Duration::as_secs)
+            .collect();
+        assert_eq!(seconds, vec![10, 20, 0, 10]);
+    }
+
+    #[test]
+    fn total_work_falls_back_to_lap_power() {
+        // Only one of three trackpoints has power, so the lap average is used instead.
+        let tcx = r#"<TrainingCenterDatabase xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2"><Activities><Activity Sport="Biking"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>1000</TotalTimeSeconds><DistanceMeters>8000</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Extensions><ns3:TPX><ns3:Watts>400</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:08:20Z</Time></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:16:40Z</Time></Trackpoint>
+</Track><Extensions><ns3:LX><ns3:AvgWatts>150</ns3:AvgWatts></ns3:LX></Extensions></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#;
+        let activity = parse_str(tcx).unwrap().get_activity(0).unwrap().clone();
+        assert_eq!(activity.total_work_kj(), Some(150.0));
+        assert_eq!(with_altitudes(&["100", "100"]).total_work_kj(), None);
+    }
+
+    #[test]
+    fn stride_length_divides_speed_by_steps() {
+        let lap = |start: &str, meters: &str, cadence: &str| {
+            format!(
+                "<Lap StartTime=\"{start}\"><TotalTimeSeconds>600</TotalTimeSeconds>\
+                 <DistanceMeters>{meters}</DistanceMeters><Track></Track><Extensions><LX>\
+                 <AvgRunCadence>{cadence}</AvgRunCadence></LX></Extensions></Lap>"
+            )
+        };
+        let activity = running(&format!(
+            "{}{}",
+            lap("2024-03-01T12:00:00Z", "1800", "90"),
+            lap("2024-03-01T12:10:00Z", "1200", "80")
+        ));
+        // 180m/min over 180 steps/min, then 120m/min over 160 steps/min.
+        assert_eq!(activity.laps[0].average_stride_length_m(), Some(1.0));
+        assert_eq!(activity.laps[1].average_stride_length_m(), Some(0.75));
+        // 150m/min over the laps' average of 170 steps/min.
+        let stride = activity.average_stride_length_m().unwrap();
+        assert!((stride - 150.0 / 170.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stride_length_needs_cadence() {
+        let activity = running(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>600</TotalTimeSeconds>\
+             <DistanceMeters>1800</DistanceMeters><Track></Track></Lap>",
+        );
+        assert_eq!(activity.laps[0].average_stride_length_m(), None);
+        assert_eq!(activity.average_stride_length_m(), None);
+    }
+
+    /// A one-lap run with a trackpoint every 10 seconds, each `meters` further along than
+    /// the one before, and `extensions` inside every trackpoint.
+    fn with_steps(meters: &[f32], extensions: &str) -> Activity {
+        let mut distance = 0.0;
+        let trackpoints: String = std::iter::once(0.0)
+            .chain(meters.iter().copied())
+            .enumerate()
+            .map(|(i, step)| {
+                distance += step;
+                format!(
+                    "<Trackpoint><Time>{}</Time><DistanceMeters>{}</DistanceMeters>{}</Trackpoint>",
+                    Utc.timestamp_opt(1709294400 + 10 * i as i64, 0)
+                        .unwrap()
+                        .to_rfc3339(),
+                    distance,
+                    extensions
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>{}</TotalTimeSeconds>\
+             <DistanceMeters>{}</DistanceMeters><Track>{}</Track></Lap>",
+            10 * meters.len(),
+            distance,
+            trackpoints
+        ))
+    }
+
+    #[test]
+    fn max_speed_falls_back_to_trackpoint_deltas() {
+        // The 300m step is a 0:54/mi glitch.
+        let activity = with_steps(&[30.0, 40.0, 300.0, 30.0], "");
+        let mph = activity.max_speed_mph().unwrap();
+        assert!((mph - 4.0 * 3600.0 / METERS_PER_MILE).abs() < 1e-4);
+        assert_eq!(activity.max_pace().unwrap(), "06:42 / mi");
+    }
+
+    #[test]
+    fn max_speed_prefers_reported_speed() {
+        let activity = with_steps(
+            &[30.0, 40.0],
+            "<Extensions><TPX><Speed>5</Speed></TPX></Extensions>",
+        );
+        let mph = activity.max_speed_mph().unwrap();
+        assert!((mph - 5.0 * 3600.0 / METERS_PER_MILE).abs() < 1e-4);
+        assert_eq!(with_steps(&[], "").max_speed_mph(), None);
+        assert_eq!(with_steps(&[], "").max_pace(), None);
+    }
+
+    #[test]
+    fn pace_fade_follows_the_trend_in_pace() {
+        let even = with_steps(&[30.0; 100], "").pace_fade_percent().unwrap();
+        assert!(even.abs() < 1e-6);
+        let slowing: Vec<f32> = (0..100).map(|i| 40.0 - i as f32 / 10.0).collect();
+        assert!(with_steps(&slowing, "").pace_fade_percent().unwrap() > 10.0);
+        let speeding: Vec<f32> = slowing.iter().rev().copied().collect();
+        assert!(with_steps(&speeding, "").pace_fade_percent().unwrap() < -10.0);
+    }
+
+    #[test]
+    fn pace_fade_needs_enough_trackpoints() {
+        assert_eq!(with_steps(&[30.0; 10], "").pace_fade_percent(), None);
+    }
+
+    #[test]
+    fn distance_scale_hint_flags_mis_scaled_distances() {
+        assert!(gps_run(222.4).plausible_distance());
+        assert_eq!(gps_run(222.4).distance_scale_hint(), None);
+        assert!(!gps_run(222_400.0).plausible_distance());
+        assert_eq!(gps_run(222_400.0).distance_scale_hint(), Some(1000.0));
+        assert_eq!(gps_run(0.2224).distance_scale_hint(), Some(0.001));
+    }
+
+    #[test]
+    fn distances_without_gps_are_plausible() {
+        let activity = with_altitudes(&["100", "101"]);
+        assert!(activity.plausible_distance());
+        assert_eq!(activity.distance_scale_hint(), None);
+    }
+
+    /// A run without any laps, identified by `id`.
+    fn lapless(id: &str) -> Activity {
+        Activity {
+            sport: Sport::Running,
+            id: String::from(id),
+            laps: vec![],
+            creator: Creator::default(),
+        }
+    }
+
+    #[test]
+    fn date_is_the_start_date_in_the_timezone() {
+        let late = lapless("2024-02-29T23:30:00Z");
+        let day = |d| NaiveDate::from_ymd_opt(2024, 2, d);
+        assert_eq!(late.date(), day(29));
+        let east = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        assert_eq!(late.date_in(&east), NaiveDate::from_ymd_opt(2024, 3, 1));
+        let west = chrono::FixedOffset::west_opt(5 * 3600).unwrap();
+        assert_eq!(late.date_in(&west), day(29));
+
+        let early = lapless("2024-03-01T02:00:00Z");
+        assert_eq!(early.date_in(&west), day(29));
+    }
+
+    #[test]
+    fn date_is_none_without_a_start_time() {
+        let activity = lapless("morning run");
+        assert_eq!(activity.date(), None);
+    }
+
+    /// 100 trackpoints 10 seconds and 30 meters apart (5:22/mi), with `hr(i)` and climbing
+    /// `climb` meters at each.
+    fn steady_run(hr: impl Fn(usize) -> usize, climb: f64) -> Activity {
+        let trackpoints: String = (0..100)
+            .map(|i| {
+                format!(
+                    "<Trackpoint><Time>{}</Time><AltitudeMeters>{}</AltitudeMeters>\
+                     <DistanceMeters>{}</DistanceMeters><HeartRateBpm><Value>{}</Value>\
+                     </HeartRateBpm></Trackpoint>",
+                    Utc.timestamp_opt(1709294400 + 10 * i as i64, 0)
+                        .unwrap()
+                        .to_rfc3339(),
+                    100.0 + climb * i as f64,
+                    30 * i,
+                    hr(i)
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>990</TotalTimeSeconds>\
+             <DistanceMeters>2970</DistanceMeters><Track>{}</Track></Lap>",
+            trackpoints
+        ))
+    }
+
+    #[test]
+    fn hr_drift_compares_the_halves_of_steady_running() {
+        assert_eq!(steady_run(|_| 150, 0.0).hr_drift_steady(), Some(0.0));
+        // The 70 steady windows end at trackpoints 30 to 99, split at 65.
+        let drifting = steady_run(|i| if i < 65 { 140 } else { 154 }, 0.0);
+        let drift = drifting.hr_drift_steady().unwrap();
+        assert!((drift - 10.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hr_drift_ignores_hills() {
+        // A 5% grade throughout leaves nothing steady.
+        assert_eq!(steady_run(|_| 150, 1.5).hr_drift_steady(), None);
+    }
+
+    #[test]
+    fn pace_zones_bucket_time_by_smoothed_pace() {
+        // 40 intervals at 5:22/mi, then 40 at 17:53/mi; the smoothed pace passes 10:00/mi
+        // once 7 of the trailing 30 intervals are slow.
+        let steps: Vec<f32> = [30.0; 40].into_iter().chain([15.0; 40]).collect();
+        let zones = with_steps(&steps, "").pace_zones(&[300, 600]);
+        assert_eq!(
+            zones,
+            [
+                Duration::ZERO,
+                Duration::from_secs(460),
+                Duration::from_secs(340)
+            ]
+        );
+    }
+
+    #[test]
+    fn pace_zones_without_boundaries_hold_all_the_time() {
+        let zones = with_steps(&[30.0; 10], "").pace_zones(&[]);
+        assert_eq!(zones, [Duration::from_secs(100)]);
+    }
+
+    #[test]
+    fn coalesce_short_laps_merges_into_the_lap_before() {
+        let lap = |i: usize, meters: u32, calories: &str| {
+            format!(
+                "<Lap StartTime=\"2024-03-01T12:0{i}:00Z\"><TotalTimeSeconds>60</TotalTimeSeconds>\
+                 <DistanceMeters>{meters}</DistanceMeters>{calories}<Track><Trackpoint>\
+                 <Time>2024-03-01T12:0{i}:00Z</Time></Trackpoint></Track></Lap>"
+            )
+        };
+        let calories = "<Calories>10</Calories>";
+        let mut activity = running(
+            &[
+                lap(0, 100, calories),
+                lap(1, 1000, calories),
+                lap(2, 50, ""),
+                lap(3, 30, calories),
+                lap(4, 1000, calories),
+            ]
+            .concat(),
+        );
+        activity.coalesce_short_laps(200.0);
+
+        let distances: Vec<f32> = activity.laps.iter().map(|l| l.distance).collect();
+        assert_eq!(distances, [100.0, 1080.0, 1000.0]);
+        let merged = &activity.laps[1];
+        assert_eq!(merged.seconds, 180.0);
+        assert_eq!(merged.calories, Some(20));
+        assert_eq!(merged.track.track_points.len(), 3);
+        assert_eq!(activity.track_points().count(), 5);
+    }
+
+    #[test]
+    fn best_efforts_find_the_fastest_stretch_of_each_distance() {
+        // 400m at 8m/s from 30s to 80s, with slower running either side.
+        let activity = with_steps(
+            &[40.0, 40.0, 40.0, 80.0, 80.0, 80.0, 80.0, 80.0, 40.0, 40.0],
+            "",
+        );
+        let efforts = activity.best_efforts();
+        assert_eq!(efforts.len(), BEST_EFFORT_DISTANCES.len());
+        assert_eq!(efforts[0], (400.0, Some(Duration::from_secs(50))));
+        assert!(efforts[1..].iter().all(|(_, best)| best.is_none()));
+    }
+
+    #[test]
+    fn fastest_split_interpolates_the_start() {
+        // The fastest 100m starts halfway through the first interval.
+        let activity = with_steps(&[80.0, 60.0], "");
+        assert_eq!(activity.fastest_split(100.0), Some(Duration::from_secs(15)));
+        assert_eq!(activity.fastest_split(0.0), None);
+    }
+
+    #[test]
+    fn speed_series_falls_back_to_distance_over_time() {
+        let activity = with_steps(&[30.0, 40.0], "");
+        assert!(activity.track_points().all(|tp| tp.speed_ms().is_none()));
+        assert_eq!(
+            activity.speed_series(),
+            [
+                (Duration::from_secs(10), 3.0),
+                (Duration::from_secs(20), 4.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn speed_series_prefers_reported_speed() {
+        let activity = with_steps(
+            &[30.0, 40.0],
+            "<Extensions><TPX><Speed>3.5</Speed></TPX></Extensions>",
+        );
+        let speeds: Vec<f64> = activity
+            .speed_series()
+            .iter()
+            .map(|(_, speed)| *speed)
+            .collect();
+        assert_eq!(speeds, [3.5, 3.5, 3.5]);
+        // A lone trackpoint without a reported speed has nothing to measure from.
+        assert!(with_steps(&[], "").speed_series().is_empty());
+    }
+
+    #[test]
+    fn indoor_rides_average_only_the_recorded_hr() {
+        let tcx = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2">
+  <Activities>
+    <Activity Sport="Biking">
+      <Id>2024-03-01T18:00:00Z</Id>
+      <Lap StartTime="2024-03-01T18:00:00Z">
+        <TotalTimeSeconds>3</TotalTimeSeconds><DistanceMeters>0</DistanceMeters><Calories>1</Calories>
+        <Intensity>Active</Intensity><TriggerMethod>Manual</TriggerMethod>
+        <Track>
+          <Trackpoint><Time>2024-03-01T18:00:00Z</Time><HeartRateBpm><Value>120</Value></HeartRateBpm><Cadence>85</Cadence></Trackpoint>
+          <Trackpoint><Time>2024-03-01T18:00:01Z</Time><Cadence>86</Cadence></Trackpoint>
+          <Trackpoint><Time>2024-03-01T18:00:02Z</Time><HeartRateBpm><Value>130</Value></HeartRateBpm><Cadence>88</Cadence></Trackpoint>
+          <Trackpoint><Time>2024-03-01T18:00:03Z</Time><HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>
+        </Track>
+      </Lap>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>"#;
+        let parsed = parse_str(tcx).unwrap();
+        let activity = parsed.get_activity(0).unwrap();
+        assert_eq!(activity.track_points().count(), 4);
+        assert_eq!(activity.average_hr(), 130);
+        assert_eq!(activity.total_distance_meters(), 0.0);
+    }
+
+    #[test]
+    fn time_above_and_below_hr_leave_out_the_threshold() {
+        // HR cycles through 150-169, one 10s interval at each.
+        let activity = steady_run(|i| 150 + i % 20, 0.0);
+        assert_eq!(activity.time_above_hr(160), Duration::from_secs(440));
+        assert_eq!(activity.time_below_hr(160), Duration::from_secs(500));
+        assert_eq!(activity.time_above_hr(170), Duration::ZERO);
+    }
+
+    #[test]
+    fn values_split_across_tpx_elements_are_all_read() {
+        let activity = with_steps(
+            &[30.0],
+            "<Extensions><TPX><Speed>3</Speed></TPX><TPX><Watts>200</Watts></TPX></Extensions>",
+        );
+        let points: Vec<&TrackPoint> = activity.track_points().collect();
+        assert_eq!(
+            (points[1].speed_ms(), points[1].watts()),
+            (Some(3.0), Some(200))
+        );
+    }
+
+    #[test]
+    fn trim_recomputes_partly_trimmed_laps() {
+        let mut activity = with_steps(&[30.0; 10], "");
+        activity.trim(Duration::from_secs(20), Duration::from_secs(30));
+        let lap = &activity.laps[0];
+        assert_eq!(lap.track.track_points.len(), 6);
+        assert_eq!(lap.seconds, 50.0);
+        assert_eq!(lap.distance, 150.0);
+    }
+
+    #[test]
+    fn trim_removes_laps_left_without_trackpoints() {
+        let lap = |start: u32| {
+            let trackpoints: String = (start..start + 3)
+                .map(|i| format!("<Trackpoint><Time>2024-03-01T12:00:{i}0Z</Time></Trackpoint>"))
+                .collect();
+            format!(
+                "<Lap StartTime=\"2024-03-01T12:00:{start}0Z\"><TotalTimeSeconds>30</TotalTimeSeconds>\
+                 <DistanceMeters>100</DistanceMeters><Track>{trackpoints}</Track></Lap>"
+            )
+        };
+        let untrimmed = running(&[lap(0), lap(3)].concat());
+        let mut activity = untrimmed.clone();
+        activity.trim(Duration::ZERO, Duration::from_secs(25));
+        assert_eq!(activity.laps, untrimmed.laps[..1]);
+        activity.trim(Duration::ZERO, Duration::ZERO);
+        assert_eq!(activity.laps, untrimmed.laps[..1]);
+    }
+
+    /// A one-lap run with a trackpoint at each of `seconds` after the start.
+    fn sampled_at(seconds: &[i64]) -> Activity {
+        let trackpoints: String = seconds
+            .iter()
+            .map(|s| {
+                format!(
+                    "<Trackpoint><Time>{}</Time></Trackpoint>",
+                    Utc.timestamp_opt(1709294400 + s, 0).unwrap().to_rfc3339()
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>60</TotalTimeSeconds>\
+             <DistanceMeters>0</DistanceMeters><Track>{}</Track></Lap>",
+            trackpoints
+        ))
+    }
+
+    #[test]
+    fn steady_one_second_sampling_is_not_flagged() {
+        let activity = sampled_at(&[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            activity.sampling_interval_median(),
+            Some(Duration::from_secs(1))
+        );
+        assert!(activity.validate().is_empty());
+        assert_eq!(sampled_at(&[0]).sampling_interval_median(), None);
+    }
+
+    #[test]
+    fn sparse_and_irregular_sampling_are_flagged() {
+        let sparse = sampled_at(&[0, 5, 10, 15]);
+        assert_eq!(
+            sparse.validate(),
+            [ValidationWarning::SamplingInterval(Duration::from_secs(5))]
+        );
+        // 3 of the 8 intervals are "smart recording" gaps.
+        let smart = sampled_at(&[0, 1, 2, 10, 11, 12, 20, 21, 30]);
+        assert_eq!(
+            smart.validate(),
+            [ValidationWarning::IrregularSampling(0.375)]
+        );
+    }
+
+    #[test]
+    fn decimal_pace_is_minutes_per_unit() {
+        let mile = running(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>465</TotalTimeSeconds>\
+             <DistanceMeters>1609.344</DistanceMeters><Track></Track></Lap>",
+        );
+        assert!((mile.average_pace_decimal() - 7.75).abs() < 1e-4);
+        let kilometer = running(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>270</TotalTimeSeconds>\
+             <DistanceMeters>1000</DistanceMeters><Track></Track></Lap>",
+        );
+        assert!((kilometer.average_pace_decimal_km() - 4.5).abs() < 1e-4);
+
+        let json = serde_json::to_value(crate::stats::ActivityStats::new(&mile)).unwrap();
+        assert!((json["average_pace_decimal"].as_f64().unwrap() - 7.75).abs() < 1e-4);
+    }
+
+    #[test]
+    fn decimal_pace_is_zero_without_distance() {
+        assert_eq!(lapless("2024-03-01T12:00:00Z").average_pace_decimal(), 0.0);
+        assert_eq!(with_altitudes(&[]).average_pace_decimal_km(), 0.0);
+    }
+
+    /// A run with a trackpoint every 10 seconds and 100 meters at each of `latitudes`, along
+    /// -75° longitude.
+    fn along_latitudes(latitudes: &[f64]) -> Activity {
+        let trackpoints: String = latitudes
+            .iter()
+            .enumerate()
+            .map(|(i, lat)| {
+                format!(
+                    "<Trackpoint><Time>{}</Time><Position><LatitudeDegrees>{}</LatitudeDegrees>\
+                     <LongitudeDegrees>-75.0</LongitudeDegrees></Position>\
+                     <DistanceMeters>{}</DistanceMeters></Trackpoint>",
+                    Utc.timestamp_opt(1709294400 + 10 * i as i64, 0)
+                        .unwrap()
+                        .to_rfc3339(),
+                    lat,
+                    100 * i
+                )
+            })
+            .collect();
+        running(&format!(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>{}</TotalTimeSeconds>\
+             <DistanceMeters>0</DistanceMeters><Track>{}</Track></Lap>",
+            10 * latitudes.len(),
+            trackpoints
+        ))
+    }
+
+    #[test]
+    fn match_segment_times_the_covered_stretch() {
+        // Trackpoints about 111m apart.
+        let activity = along_latitudes(&[40.000, 40.001, 40.002, 40.003, 40.004]);
+        let segment = [(40.001, -75.0), (40.002, -75.0), (40.003, -75.0)];
+        assert_eq!(
+            activity.match_segment(&segment, 20.0),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(activity.match_segment(&segment[..1], 20.0), None);
+    }
+
+    #[test]
+    fn match_segment_needs_the_direction_and_the_route() {
+        let activity = along_latitudes(&[40.000, 40.001, 40.002, 40.003, 40.004]);
+        let reversed = [(40.003, -75.0), (40.001, -75.0)];
+        assert_eq!(activity.match_segment(&reversed, 20.0), None);
+        let elsewhere = [(40.001, -75.01), (40.003, -75.01)];
+        assert_eq!(activity.match_segment(&elsewhere, 20.0), None);
+    }
+
+    #[test]
+    fn sinuosity_compares_the_path_to_the_straight_line() {
+        let straight = along_latitudes(&[40.000, 40.001, 40.002]);
+        assert!((straight.sinuosity().unwrap() - 1.0).abs() < 1e-6);
+        let out_and_back = along_latitudes(&[40.000, 40.001, 40.002, 40.001]);
+        assert!((out_and_back.sinuosity().unwrap() - 3.0).abs() < 1e-3);
+        assert_eq!(with_altitudes(&["100", "101"]).sinuosity(), None);
+    }
+
+    #[test]
+    fn is_indoor_needs_trackpoints_without_any_position() {
+        assert!(with_altitudes(&["100", "101"]).is_indoor());
+        assert!(!gps_run(222.4).is_indoor());
+        assert!(!lapless("2024-03-01T12:00:00Z").is_indoor());
+    }
+
+    #[test]
+    fn elevation_at_distance_interpolates_between_trackpoints() {
+        // A trackpoint every 10m.
+        let activity = with_altitudes(&["100", "110", "", "130"]);
+        assert_eq!(activity.elevation_at_distance(0.0), Some(100.0));
+        assert_eq!(activity.elevation_at_distance(2.5), Some(102.5));
+        assert_eq!(activity.elevation_at_distance(10.0), Some(110.0));
+        // 25m is just past the 20m trackpoint, which has no altitude.
+        assert_eq!(activity.elevation_at_distance(25.0), None);
+        assert_eq!(activity.elevation_at_distance(31.0), None);
+    }
+
+    #[test]
+    fn dedup_trackpoints_keeps_the_first_of_each_timestamp() {
+        let trackpoint = |second: u32, hr: u32| {
+            format!(
+                "<Trackpoint><Time>2024-03-01T12:00:0{second}Z</Time>\
+                 <HeartRateBpm><Value>{hr}</Value></HeartRateBpm></Trackpoint>"
+            )
+        };
+        let lap = |start: u32, trackpoints: &[String]| {
+            format!(
+                "<Lap StartTime=\"2024-03-01T12:00:0{start}Z\"><TotalTimeSeconds>2</TotalTimeSeconds>\
+                 <DistanceMeters>10</DistanceMeters><Track>{}</Track></Lap>",
+                trackpoints.concat()
+            )
+        };
+        let mut activity = running(
+            &[
+                lap(
+                    0,
+                    &[trackpoint(0, 100), trackpoint(1, 110), trackpoint(1, 190)],
+                ),
+                lap(1, &[trackpoint(1, 190), trackpoint(2, 120)]),
+            ]
+            .concat(),
+        );
+        activity.dedup_trackpoints();
+
+        assert_eq!(activity.laps[0].track.track_points.len(), 2);
+        assert_eq!(activity.laps[1].track.track_points.len(), 1);
+        assert_eq!(activity.average_hr(), 110);
+    }
+
+    #[test]
+    fn incline_adjusted_pace_scales_by_running_cost() {
+        let treadmill = running(
+            "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>600</TotalTimeSeconds>\
+             <DistanceMeters>1609.344</DistanceMeters><Track></Track></Lap>",
+        );
+        let raw = treadmill.average_pace_seconds();
+        assert_eq!(raw, Duration::from_secs(600));
+        assert_eq!(treadmill.incline_adjusted_pace_seconds(0.0), Some(raw));
+        let expected = (600.0 * running_cost(0.0) / running_cost(0.05)).round() as u64;
+        assert_eq!(
+            treadmill.incline_adjusted_pace_seconds(5.0),
+            Some(Duration::from_secs(expected))
+        );
+        assert!(treadmill.incline_adjusted_pace_seconds(5.0).unwrap() < raw);
+        assert!(treadmill.incline_adjusted_pace_seconds(-2.0).unwrap() > raw);
+
+        for (seconds, meters) in [(600, 0), (0, 1000), (0, 0)] {
+            let lap = format!(
+                "<Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>{}</TotalTimeSeconds>\
+                 <DistanceMeters>{}</DistanceMeters><Track></Track></Lap>",
+                seconds, meters
+            );
+            assert_eq!(running(&lap).incline_adjusted_pace_seconds(5.0), None);
+        }
+    }
+
+    #[test]
+    fn average_grade_counts_intervals_with_both_altitudes() {
+        // The 4m climbed across the missing altitude isn't counted.
+        let activity = with_altitudes(&["100", "101", "", "105", "106"]);
+        assert_eq!(activity.average_grade(), Some(10.0));
+        assert_eq!(with_altitudes(&["100", "", "105"]).average_grade(), None);
+        assert_eq!(
+            with_altitudes(&["100", "95", "90"]).average_grade(),
+            Some(-50.0)
+        );
+    }
+
+    #[test]
+    fn power_zones_bucket_time_by_watts() {
+        // 200W is 80% of a 250W FTP, in zone 3 (75-90%).
+        let zones = powered_ride("<ns3:Watts>200</ns3:Watts>").power_zones(250);
+        let mut expected = vec![Duration::ZERO; 7];
+        expected[2] = Duration::from_secs(1000);
+        assert_eq!(zones, expected);
+        assert_eq!(powered_ride("").power_zones(250), vec![Duration::ZERO; 7]);
+    }
+
+    #[test]
+    fn clip_to_bounds_keeps_a_lap_per_visit() {
+        let bbox = BoundingBox {
+            min_lat: 40.0005,
+            min_long: -75.1,
+            max_lat: 40.0025,
+            max_long: -74.9,
+        };
+        let through = along_latitudes(&[40.000, 40.001, 40.002, 40.003]).clip_to_bounds(&bbox);
+        assert_eq!(through.laps.len(), 1);
+        assert_eq!(through.laps[0].distance, 100.0);
+        assert_eq!(through.laps[0].seconds, 10.0);
+
+        // In, out to the north, and back in on the way home.
+        let out_and_back =
+            along_latitudes(&[40.000, 40.001, 40.002, 40.003, 40.002, 40.001, 40.000]);
+        let clipped = out_and_back.clip_to_bounds(&bbox);
+        assert_eq!(clipped.laps.len(), 2);
+        let distances: Vec<f32> = clipped.track_points().map(|tp| tp.distance).collect();
+        // Distance only grows inside the box, so the 200m outside it is left out.
+        assert_eq!(distances, [0.0, 100.0, 100.0, 200.0]);
+        assert_eq!(clipped.id, out_and_back.id);
+    }
+
+    #[test]
+    fn active_averages_leave_out_the_warmup() {
+        let activity = steady_run(|i| if i < 20 { 100 } else { 160 }, 0.0);
+        assert_eq!(activity.average_hr(), 148);
+        assert_eq!(activity.average_hr_active(150), 160);
+        assert_eq!(
+            activity.average_pace_active_seconds(150),
+            Some(Duration::from_secs(536))
+        );
+        assert_eq!(activity.average_hr_active(170), 0);
+        assert_eq!(activity.average_pace_active_seconds(170), None);
+    }
+
+    #[test]
+    fn fastest_and_slowest_laps_compare_pace() {
+        let lap = |minute: u32, seconds: u32, meters: u32| {
+            format!(
+                "<Lap StartTime=\"2024-03-01T12:{minute:02}:00Z\"><TotalTimeSeconds>{seconds}</TotalTimeSeconds>\
+                 <DistanceMeters>{meters}</DistanceMeters><Track></Track></Lap>"
+            )
+        };
+        let activity = running(
+            &[
+                lap(0, 300, 1000),
+                lap(5, 180, 800),
+                // A standing rest, which has no pace.
+                lap(8, 60, 0),
+                lap(9, 400, 1000),
+            ]
+            .concat(),
+        );
+        assert_eq!(activity.fastest_lap(), Some(&activity.laps[1]));
+        assert_eq!(activity.slowest_lap(), Some(&activity.laps[3]));
+        let rest = running(&lap(0, 60, 0));
+        assert_eq!(rest.fastest_lap(), None);
+        assert_eq!(rest.slowest_lap(), None);
+    }
+
+    #[test]
+    fn blank_ids_come_from_the_first_trackpoint() {
+        let tcx = |id: &str, track: &str| {
+            format!(
+                "<TrainingCenterDatabase><Activities><Activity Sport=\"Running\">{id}\
+                 <Lap StartTime=\"2024-03-01T12:00:00Z\"><TotalTimeSeconds>60</TotalTimeSeconds>\
+                 <DistanceMeters>100</DistanceMeters><Track>{track}</Track></Lap>\
+                 </Activity></Activities></TrainingCenterDatabase>"
+            )
+        };
+        let track = "<Trackpoint><Time>2024-03-01T12:00:05+00:00</Time></Trackpoint>";
+        for id in ["<Id/>", "<Id>  </Id>", ""] {
+            let parsed = parse_str(&tcx(id, track)).unwrap();
+            assert_eq!(parsed.get_activity(0).unwrap().id, "2024-03-01T12:00:05Z");
+        }
+        let parsed = parse_str(&tcx("<Id/>", "")).unwrap();
+        assert_eq!(parsed.get_activity(0).unwrap().id, "2024-03-01T12:00:00Z");
+        let parsed = parse_str(&tcx("<Id>Morning Run</Id>", track)).unwrap();
+        assert_eq!(parsed.get_activity(0).unwrap().id, "Morning Run");
+        assert_eq!(lapless("").effective_id(), "");
+    }
+
+    #[test]
+    fn moving_time_skips_intervals_below_the_threshold() {
+        // 3 m/s, standing still, shuffling at 0.2 m/s, 3 m/s.
+        let activity = with_steps(&[30.0, 0.0, 2.0, 30.0], "");
+        assert_eq!(activity.duration(), Duration::from_secs(40));
+        assert_eq!(activity.moving_time(), Duration::from_secs(20));
+        assert_eq!(
+            activity.moving_time_with_threshold(0.1),
+            Duration::from_secs(30)
+        );
+        // 20 s over 62 m.
+        assert_eq!(activity.moving_pace(), Some(Duration::from_secs(519)));
+    }
+
+    #[test]
+    fn moving_time_falls_back_to_duration_without_trackpoint_distances() {
+        let indoor = sampled_at(&[0, 10, 20]);
+        assert_eq!(indoor.moving_time(), indoor.duration());
+        assert_eq!(indoor.moving_pace(), None);
+        assert_eq!(
+            lapless("2024-03-01T12:00:00Z").moving_time(),
+            Duration::ZERO
+        );
+    }
+
+    #[test]
+    fn peaks_are_the_highest_of_the_laps_and_trackpoints() {
+        let activity = running(
+            r#"<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>200</DistanceMeters>
+<MaximumHeartRateBpm><Value>170</Value></MaximumHeartRateBpm><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><HeartRateBpm><Value>160</Value></HeartRateBpm><Extensions><TPX><RunCadence>92</RunCadence></TPX></Extensions></Trackpoint>
+</Track><Extensions><LX><MaxRunCadence>90</MaxRunCadence><MaxWatts>300</MaxWatts></LX></Extensions></Lap>
+<Lap StartTime="2024-03-01T12:01:00Z"><TotalTimeSeconds>60</TotalTimeSeconds><DistanceMeters>200</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:01:00Z</Time><HeartRateBpm><Value>185</Value></HeartRateBpm><Extensions><TPX><Watts>310</Watts></TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:02:00Z</Time></Trackpoint>
+</Track></Lap>"#,
+        );
+        assert_eq!(activity.max_hr(), Some(185));
+        assert_eq!(activity.max_watts(), Some(310));
+        // Run cadence counts both feet.
+        assert_eq!(activity.max_cadence(), Some(184));
+
+        let flat = sampled_at(&[0, 10]);
+        assert_eq!(
+            (flat.max_hr(), flat.max_watts(), flat.max_cadence()),
+            (None, None, None)
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Duration;
+
+use super::{
+    smooth_altitudes, weighted_zone_minutes, Activity, ElevationChange, ElevationOptions, Lap,
+    Position, MIN_MOVING_SPEED,
+};
+use crate::{
+    units::FEET_PER_METER,
+    zones::{HrZones, DEFAULT_MAX_HR},
+};
+
+/// The common derived metrics of an activity, computed together by [Activity::analyze].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisResult {
+    /// See [Activity::average_hr].
+    pub average_hr: usize,
+    /// See [Activity::max_hr].
+    pub max_hr: Option<usize>,
+    /// Time in each of the default 5 HR zones for [DEFAULT_MAX_HR], as [Activity::hr_zones].
+    pub hr_zones: Vec<Duration>,
+    /// See [Activity::relative_effort].
+    pub relative_effort: f64,
+    /// See [Activity::moving_time].
+    pub moving_time: Duration,
+    /// See [Activity::gps_distance_meters].
+    pub gps_distance_meters: f32,
+    /// Elevation gain in feet, as [Activity::total_elevation_gain] gives once
+    /// [Activity::calc_lap_elevations_with] has run with the same [ElevationOptions].
+    pub elevation_gain: usize,
+    /// Elevation loss in feet, like `elevation_gain`.
+    pub elevation_loss: usize,
+    /// See [Activity::total_work_kj].
+    pub total_work_kj: Option<f64>,
+}
+
+impl Activity {
+    /// Computes the metrics of [AnalysisResult] in a single walk over the trackpoints,
+    /// for tools that need many of them at once. Each field matches the value of the
+    /// method it documents, but calling those one by one walks (and for most, collects)
+    /// the trackpoints once per metric. Elevation is measured with the default
+    /// [ElevationOptions].
+    pub fn analyze(&self) -> AnalysisResult {
+        self.analyze_with(ElevationOptions::default())
+    }
+
+    /// [Activity::analyze], measuring elevation with `elevation` as
+    /// [Activity::calc_lap_elevations_with] would.
+    pub fn analyze_with(&self, elevation: ElevationOptions) -> AnalysisResult {
+        let zone_boundaries = HrZones::default_from_max(DEFAULT_MAX_HR);
+        let mut hr_zones = vec![Duration::ZERO; zone_boundaries.len() + 1];
+        let (mut total_hr, mut hr_count, mut max_hr) = (0, 0, None);
+        let (mut moving_time, mut any_distance) = (Duration::ZERO, false);
+        let mut gps_distance = 0.0;
+        let mut last_position: Option<Position> = None;
+        let mut altitudes = vec![];
+        let (mut joules, mut powered, mut point_count) = (0.0, 0, 0);
+
+        let mut previous = None;
+        for lap in self.laps.iter() {
+            for tp in lap.track.track_points.iter() {
+                point_count += 1;
+                any_distance |= tp.distance > 0.0;
+                if tp.watts().is_some() {
+                    powered += 1;
+                }
+                if let Some(hr) = tp.hr.as_ref().map(|hr| hr.value) {
+                    total_hr += hr;
+                    hr_count += 1;
+                    max_hr = max_hr.max(Some(hr));
+                }
+                if let Some(position) = tp.position {
+                    if let Some(last) = last_position {
+                        gps_distance += last.distance_to(&position);
+                    }
+                    last_position = Some(position);
+                }
+                altitudes.push(tp.altitude);
+
+                if let Some(previous) = previous.replace(tp) {
+                    let seconds = (tp.time - previous.time).num_milliseconds() as f64 / 1000.0;
+                    if let Some(watts) = previous.watts() {
+                        joules += watts as f64 * seconds;
+                    }
+                    if let Ok(interval) = (tp.time - previous.time).to_std() {
+                        if let Some(hr) = previous.hr.as_ref().map(|hr| hr.value) {
+                            let zone = zone_boundaries.iter().take_while(|b| hr >= **b).count();
+                            hr_zones[zone] += interval;
+                        }
+                        let meters = tp.distance - previous.distance;
+                        if meters / interval.as_secs_f32() >= MIN_MOVING_SPEED {
+                            moving_time += interval;
+                        }
+                    }
+                }
+            }
+        }
+
+        // Smoothed across the whole activity, then accumulated per lap, as in
+        // Activity::calc_lap_elevations_with.
+        smooth_altitudes(&mut altitudes, elevation.smoothing_window);
+        let (mut gain_meters, mut loss_meters) = (0.0, 0.0);
+        let mut rest = altitudes.as_slice();
+        for lap in self.laps.iter() {
+            let (lap_altitudes, after) = rest.split_at(lap.track.track_points.len());
+            let change = ElevationChange::of(lap_altitudes, elevation.threshold_meters);
+            gain_meters += change.gain_meters;
+            loss_meters += change.loss_meters;
+            rest = after;
+        }
+        let max_hr = self.laps.iter().filter_map(Lap::max_hr).max().max(max_hr);
+
+        if point_count < 2 || !any_distance {
+            moving_time = self.duration();
+        }
+        let average_hr = if self.lap_count() == 0 {
+            0
+        } else {
+            total_hr
+                .checked_div(hr_count)
+                .unwrap_or_else(|| self.average_lap_hr())
+        };
+        let total_work_kj = if powered > 0 && powered * 2 >= point_count {
+            Some(joules / 1000.0)
+        } else {
+            self.lap_work_kj()
+        };
+
+        AnalysisResult {
+            average_hr,
+            max_hr,
+            relative_effort: weighted_zone_minutes(&hr_zones),
+            hr_zones,
+            moving_time,
+            gps_distance_meters: gps_distance as f32,
+            elevation_gain: (gain_meters * FEET_PER_METER).round() as usize,
+            elevation_loss: (loss_meters * FEET_PER_METER).round() as usize,
+            total_work_kj,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parse_str,
+        zones::{HrZones, DEFAULT_MAX_HR},
+        ElevationOptions,
+    };
+
+    /// Two laps with HR, power, GPS and altitude, a stop, a lap max HR higher than any
+    /// trackpoint's, and a first trackpoint without altitude.
+    static ACTIVITY: &str = r#"<TrainingCenterDatabase xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2"><Activities><Activity Sport="Running">
+<Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>30</TotalTimeSeconds><DistanceMeters>60</DistanceMeters><MaximumHeartRateBpm><Value>185</Value></MaximumHeartRateBpm><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><Position><LatitudeDegrees>40.0</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><DistanceMeters>0</DistanceMeters><HeartRateBpm><Value>120</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>200</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:10Z</Time><Position><LatitudeDegrees>40.0003</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1600</AltitudeMeters><DistanceMeters>30</DistanceMeters><HeartRateBpm><Value>140</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>250</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:20Z</Time><Position><LatitudeDegrees>40.0003</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1603</AltitudeMeters><DistanceMeters>30</DistanceMeters><HeartRateBpm><Value>150</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>0</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:30Z</Time><Position><LatitudeDegrees>40.0006</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1602.5</AltitudeMeters><DistanceMeters>60</DistanceMeters><HeartRateBpm><Value>165</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>300</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+</Track></Lap>
+<Lap StartTime="2024-03-01T12:00:30Z"><TotalTimeSeconds>20</TotalTimeSeconds><DistanceMeters>60</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:40Z</Time><Position><LatitudeDegrees>40.0009</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1598</AltitudeMeters><DistanceMeters>90</DistanceMeters><HeartRateBpm><Value>175</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>320</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:50Z</Time><Position><LatitudeDegrees>40.0012</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1601</AltitudeMeters><DistanceMeters>120</DistanceMeters><HeartRateBpm><Value>178</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Watts>310</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+</Track></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#;
+
+    #[test]
+    fn analyze_matches_the_individual_methods() {
+        let tcb = parse_str(ACTIVITY).unwrap();
+        let activity = tcb.get_activity(0).unwrap();
+        let elevation = ElevationOptions {
+            threshold_meters: 1.0,
+            smoothing_window: 3,
+        };
+        let analysis = activity.analyze_with(elevation);
+
+        let mut measured = activity.clone();
+        measured.calc_lap_elevations_with(elevation);
+        assert_eq!(analysis.average_hr, activity.average_hr());
+        assert_eq!(analysis.max_hr, activity.max_hr());
+        assert_eq!(analysis.max_hr, Some(185));
+        assert_eq!(
+            analysis.hr_zones,
+            activity.hr_zones(&HrZones::default_from_max(DEFAULT_MAX_HR))
+        );
+        assert_eq!(
+            analysis.relative_effort,
+            activity.relative_effort(DEFAULT_MAX_HR)
+        );
+        assert_eq!(analysis.moving_time, activity.moving_time());
+        assert_eq!(analysis.gps_distance_meters, activity.gps_distance_meters());
+        assert_eq!(analysis.elevation_gain, measured.total_elevation_gain());
+        assert_eq!(analysis.elevation_loss, measured.total_elevation_loss());
+        assert_eq!(analysis.total_work_kj, activity.total_work_kj());
+    }
+
+    #[test]
+    fn analyze_measures_elevation_like_calc_lap_elevations() {
+        let tcb = parse_str(ACTIVITY).unwrap();
+        let mut activity = tcb.get_activity(0).unwrap().clone();
+        activity.calc_lap_elevations();
+        let analysis = activity.analyze();
+        assert_eq!(analysis.elevation_gain, activity.total_elevation_gain());
+        assert_eq!(analysis.elevation_loss, activity.total_elevation_loss());
+    }
+}
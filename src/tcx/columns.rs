@@ -0,0 +1,87 @@
+use chrono::{DateTime, Utc};
+
+use super::Activity;
+
+/// Every trackpoint of an activity as parallel columns, one entry per trackpoint in order,
+/// for loading into a DataFrame. Returned by [Activity::to_columns].
+///
+/// Values a trackpoint didn't record are `NaN`, so every column has the same length and
+/// can be passed to polars or arrow as-is; use `is_nan` to build a null mask.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackColumns {
+    pub times: Vec<DateTime<Utc>>,
+    /// Distance travelled in meters, 0 for trackpoints that recorded none.
+    pub distances: Vec<f64>,
+    /// Altitude in meters.
+    pub altitudes: Vec<f64>,
+    pub hrs: Vec<f64>,
+    /// Speed in meters/s as reported by the device. See [Activity::speed_series] for
+    /// speeds derived from distance when it isn't.
+    pub speeds: Vec<f64>,
+    /// Cadence as recorded, i.e. steps of one foot per minute when running.
+    pub cadences: Vec<f64>,
+    pub watts: Vec<f64>,
+}
+
+impl TrackColumns {
+    /// Number of trackpoints, i.e. the length of every column.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+impl Activity {
+    /// The activity's trackpoints as columns, see [TrackColumns].
+    pub fn to_columns(&self) -> TrackColumns {
+        let mut columns = TrackColumns::default();
+        for tp in self.track_points() {
+            let or_nan = |value: Option<f64>| value.unwrap_or(f64::NAN);
+            columns.times.push(tp.time);
+            columns.distances.push(tp.distance as f64);
+            columns.altitudes.push(or_nan(tp.altitude));
+            columns
+                .hrs
+                .push(or_nan(tp.hr.as_ref().map(|hr| hr.value as f64)));
+            columns.speeds.push(or_nan(tp.speed_ms()));
+            columns.cadences.push(or_nan(
+                tp.tpx().find_map(|tpx| tpx.cadence).map(|c| c as f64),
+            ));
+            columns.watts.push(or_nan(tp.watts().map(|w| w as f64)));
+        }
+        columns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_str;
+
+    #[test]
+    fn to_columns_fills_missing_values_with_nan() {
+        let tcx = parse_str(
+            r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>1</TotalTimeSeconds><DistanceMeters>3</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><AltitudeMeters>100</AltitudeMeters><DistanceMeters>0</DistanceMeters><HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><DistanceMeters>3</DistanceMeters><Extensions><TPX><Speed>3</Speed><RunCadence>85</RunCadence><Watts>250</Watts></TPX></Extensions></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#,
+        )
+        .unwrap();
+        let columns = tcx.get_activity(0).unwrap().to_columns();
+
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns.distances, [0.0, 3.0]);
+        assert_eq!(columns.altitudes[0], 100.0);
+        assert_eq!(columns.hrs[0], 140.0);
+        assert_eq!(
+            (columns.speeds[1], columns.cadences[1], columns.watts[1]),
+            (3.0, 85.0, 250.0)
+        );
+        assert!(columns.altitudes[1].is_nan() && columns.hrs[1].is_nan());
+        assert!(columns.speeds[0].is_nan() && columns.cadences[0].is_nan());
+        assert!(columns.watts[0].is_nan());
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+use super::{Activity, TrackPoint};
+
+/// Fraction (0 to 1) of an activity's trackpoints that recorded each kind of data, to tell
+/// which metrics can be trusted. Returned by [Activity::data_coverage].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DataCoverage {
+    pub hr: f64,
+    /// Trackpoints with a valid GPS position.
+    pub position: f64,
+    pub altitude: f64,
+    pub power: f64,
+    pub cadence: f64,
+}
+
+impl fmt::Display for DataCoverage {
+    /// E.g. `HR 100%, position 98%, altitude 98%, power 0%, cadence 100%`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "HR {:.0}%, position {:.0}%, altitude {:.0}%, power {:.0}%, cadence {:.0}%",
+            self.hr * 100.0,
+            self.position * 100.0,
+            self.altitude * 100.0,
+            self.power * 100.0,
+            self.cadence * 100.0
+        )
+    }
+}
+
+impl Activity {
+    /// How many of the activity's trackpoints recorded HR, position, altitude, power and
+    /// cadence. Everything is 0 for an activity without trackpoints.
+    pub fn data_coverage(&self) -> DataCoverage {
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        if points.is_empty() {
+            return DataCoverage::default();
+        }
+        let fraction = |has: fn(&TrackPoint) -> bool| {
+            points.iter().filter(|tp| has(tp)).count() as f64 / points.len() as f64
+        };
+        DataCoverage {
+            hr: fraction(|tp| tp.hr.is_some()),
+            position: fraction(|tp| tp.position.is_some()),
+            altitude: fraction(|tp| tp.altitude.is_some()),
+            power: fraction(|tp| tp.watts().is_some()),
+            cadence: fraction(|tp| tp.tpx().any(|tpx| tpx.cadence.is_some())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    #[test]
+    fn data_coverage_counts_the_trackpoints_recording_each_value() {
+        let tcx = parse_str(
+            r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>3</TotalTimeSeconds><DistanceMeters>9</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><AltitudeMeters>100</AltitudeMeters><HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:01Z</Time><AltitudeMeters>100</AltitudeMeters><HeartRateBpm><Value>141</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:02Z</Time><Position><LatitudeDegrees>40.0</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>101</AltitudeMeters></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:00:03Z</Time><AltitudeMeters>101</AltitudeMeters><Extensions><TPX><RunCadence>85</RunCadence><Watts>250</Watts></TPX></Extensions></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#,
+        )
+        .unwrap();
+        let coverage = tcx.get_activity(0).unwrap().data_coverage();
+        assert_eq!(
+            coverage,
+            DataCoverage {
+                hr: 0.5,
+                position: 0.25,
+                altitude: 1.0,
+                power: 0.25,
+                cadence: 0.25,
+            }
+        );
+        assert_eq!(
+            coverage.to_string(),
+            "HR 50%, position 25%, altitude 100%, power 25%, cadence 25%"
+        );
+    }
+}
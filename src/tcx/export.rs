@@ -0,0 +1,535 @@
+use std::{collections::HashMap, fmt::Write};
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use serde_json::{json, Value};
+
+use super::{
+    Activity, Course, Lap, LapExtension, Sport, Track, TrackPoint, TrainingCenterDatabase,
+    PACE_SMOOTHING_WINDOW,
+};
+use crate::stats::{self, ActivityStats, LapStats};
+
+/// A GPX 1.1 document containing one track per activity, with a track segment per lap.
+/// Trackpoints without a position are left out, as are [Activity::is_indoor] activities.
+pub fn gpx_document(activities: &[&Activity]) -> String {
+    let mut gpx = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <gpx version=\"1.1\" creator=\"tcxrs\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    );
+    for activity in activities.iter().filter(|a| !a.is_indoor()) {
+        activity.write_gpx_track(&mut gpx);
+    }
+    gpx.push_str("</gpx>\n");
+    gpx
+}
+
+impl TrainingCenterDatabase {
+    /// The database as a TCX document, with the standard namespaces Garmin Connect and
+    /// other importers expect, so a parsed file can be edited (e.g. fixing a wrong sport)
+    /// and uploaded again. Only what's parsed is written: elements tcxrs ignores are lost,
+    /// as are the elevation totals calculated into laps. Laps are written with the
+    /// `Active` intensity and `Manual` trigger method the schema requires, and sports the
+    /// schema doesn't have, e.g. [Sport::Swimming], as `Other`.
+    pub fn to_tcx_string(&self) -> Result<String> {
+        let mut tcx = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <TrainingCenterDatabase \
+             xmlns=\"http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2\" \
+             xmlns:ns3=\"http://www.garmin.com/xmlschemas/ActivityExtension/v2\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+        );
+        if let Some(activities) = &self.activities {
+            tcx.push_str("  <Activities>\n");
+            for activity in activities.activities.iter() {
+                activity.write_tcx_activity(&mut tcx)?;
+            }
+            tcx.push_str("  </Activities>\n");
+        }
+        if let Some(courses) = &self.courses {
+            tcx.push_str("  <Courses>\n");
+            for course in courses.courses.iter() {
+                course.write_tcx_course(&mut tcx)?;
+            }
+            tcx.push_str("  </Courses>\n");
+        }
+        tcx.push_str("</TrainingCenterDatabase>\n");
+        Ok(tcx)
+    }
+}
+
+impl Course {
+    fn write_tcx_course(&self, tcx: &mut String) -> Result<()> {
+        writeln!(
+            tcx,
+            "    <Course>\n      <Name>{}</Name>",
+            escape_xml(&self.name)
+        )?;
+        for lap in self.laps.iter() {
+            writeln!(
+                tcx,
+                "      <Lap><TotalTimeSeconds>{}</TotalTimeSeconds>\
+                 <DistanceMeters>{}</DistanceMeters></Lap>",
+                lap.seconds, lap.distance
+            )?;
+        }
+        for track in self.tracks.iter() {
+            write_tcx_track(tcx, track)?;
+        }
+        tcx.push_str("    </Course>\n");
+        Ok(())
+    }
+}
+
+impl Activity {
+    fn write_tcx_activity(&self, tcx: &mut String) -> Result<()> {
+        writeln!(
+            tcx,
+            "    <Activity Sport=\"{}\">\n      <Id>{}</Id>",
+            tcx_sport(self.sport),
+            escape_xml(&self.id)
+        )?;
+        for lap in self.laps.iter() {
+            lap.write_tcx_lap(tcx)?;
+        }
+        // The schema's Device_t needs every one of these, so unknown ones are written as 0.
+        if !self.creator.name.is_empty() {
+            writeln!(
+                tcx,
+                "      <Creator xsi:type=\"Device_t\"><Name>{}</Name><UnitId>{}</UnitId>\
+                 <ProductID>{}</ProductID><Version><VersionMajor>{}</VersionMajor>\
+                 <VersionMinor>{}</VersionMinor></Version></Creator>",
+                escape_xml(&self.creator.name),
+                self.creator.unit_id,
+                self.creator.product_id,
+                self.creator.version.major,
+                self.creator.version.minor
+            )?;
+        }
+        tcx.push_str("    </Activity>\n");
+        Ok(())
+    }
+
+    /// A text table with the number, distance, time, pace, average HR and average power of
+    /// each lap, one row per lap under a header row, for comparing the reps of an interval
+    /// session. The [Activity::fastest_lap] is marked with a `*`, and values a lap didn't
+    /// record are shown as `-`.
+    pub fn lap_comparison_table(&self) -> String {
+        stats::lap_comparison_table(self)
+    }
+
+    /// This activity's track as a standalone GPX document.
+    pub fn to_gpx(&self) -> String {
+        gpx_document(&[self])
+    }
+
+    /// A GeoJSON `Feature` with the route as a `LineString`, skipping trackpoints without a
+    /// position. Properties carry the total distance (meters) and duration (seconds), plus a
+    /// `heart_rates` array parallel to the coordinates when the activity has HR data.
+    pub fn to_geojson(&self) -> Result<String> {
+        let (coordinates, heart_rates): (Vec<Value>, Vec<Option<usize>>) = self
+            .track_points()
+            .filter_map(|tp| {
+                let position = tp.position?;
+                Some((
+                    json!([position.long, position.lat]),
+                    tp.hr.as_ref().map(|hr| hr.value),
+                ))
+            })
+            .unzip();
+
+        let mut properties = json!({
+            "id": self.id,
+            "sport": self.sport,
+            "distance_meters": self.total_distance_meters(),
+            "duration_seconds": self.laps.iter().map(|l| l.seconds).sum::<f32>(),
+        });
+        if heart_rates.iter().any(Option::is_some) {
+            properties["heart_rates"] = json!(heart_rates);
+        }
+
+        let feature = json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "LineString",
+                "coordinates": coordinates,
+            },
+            "properties": properties,
+        });
+        Ok(serde_json::to_string(&feature)?)
+    }
+
+    /// The activity's [ActivityStats] as `summary`, with the [LapStats] of every lap in
+    /// `laps`. With `include_trackpoints`, each lap also gets a `trackpoints` array of its
+    /// recorded samples; leave it off to keep the output small. Values a trackpoint didn't
+    /// record are `null`. Each trackpoint's `pace_sec_per_mile` is the smoothed pace over
+    /// the [PACE_SMOOTHING_WINDOW] intervals leading up to it, with glitches clamped as in
+    /// [Activity::smoothed_pace_series], and `null` where there's no pace, e.g. standing
+    /// still.
+    pub fn to_detailed_json(&self, include_trackpoints: bool) -> Result<String> {
+        let paces = if include_trackpoints {
+            self.smoothed_paces_by_time()
+        } else {
+            HashMap::new()
+        };
+        let laps = LapStats::for_activity(self)
+            .into_iter()
+            .zip(self.laps.iter())
+            .map(|(stats, lap)| {
+                let mut value = serde_json::to_value(stats)?;
+                if include_trackpoints {
+                    value["trackpoints"] = trackpoints_json(lap, &paces);
+                }
+                Ok(value)
+            })
+            .collect::<Result<Vec<Value>>>()?;
+
+        let detailed = json!({
+            "summary": ActivityStats::new(self),
+            "laps": laps,
+        });
+        Ok(serde_json::to_string(&detailed)?)
+    }
+
+    /// [Activity::smoothed_pace_series] keyed by the time of the trackpoint ending each
+    /// interval.
+    fn smoothed_paces_by_time(&self) -> HashMap<DateTime<Utc>, f32> {
+        let Some(start) = self.track_points().next().map(|tp| tp.time) else {
+            return HashMap::new();
+        };
+        self.smoothed_pace_series(PACE_SMOOTHING_WINDOW)
+            .into_iter()
+            .filter_map(|(elapsed, pace)| Some((start + TimeDelta::from_std(elapsed).ok()?, pace)))
+            .collect()
+    }
+
+    fn write_gpx_track(&self, gpx: &mut String) {
+        // Writing to a String can't fail.
+        let _ = writeln!(
+            gpx,
+            "  <trk>\n    <name>{}</name>\n    <type>{}</type>",
+            escape_xml(&self.id),
+            self.sport
+        );
+        for lap in self.laps.iter() {
+            gpx.push_str("    <trkseg>\n");
+            for tp in lap.track.track_points.iter() {
+                let Some(position) = tp.position else {
+                    continue;
+                };
+                let _ = write!(
+                    gpx,
+                    "      <trkpt lat=\"{}\" lon=\"{}\">",
+                    position.lat, position.long
+                );
+                if let Some(altitude) = tp.altitude {
+                    let _ = write!(gpx, "<ele>{}</ele>", altitude);
+                }
+                let _ = writeln!(
+                    gpx,
+                    "<time>{}</time></trkpt>",
+                    tp.time.to_rfc3339_opts(SecondsFormat::Secs, true)
+                );
+            }
+            gpx.push_str("    </trkseg>\n");
+        }
+        gpx.push_str("  </trk>\n");
+    }
+}
+
+impl Lap {
+    fn write_tcx_lap(&self, tcx: &mut String) -> Result<()> {
+        writeln!(
+            tcx,
+            "      <Lap StartTime=\"{}\">",
+            self.start_time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        )?;
+        write!(
+            tcx,
+            "        <TotalTimeSeconds>{}</TotalTimeSeconds><DistanceMeters>{}</DistanceMeters>",
+            self.seconds, self.distance
+        )?;
+        // Required by the schema, so laps without calories are written with 0.
+        write!(tcx, "<Calories>{}</Calories>", self.calories.unwrap_or(0))?;
+        if let Some(hr) = &self.average_hr {
+            write!(
+                tcx,
+                "<AverageHeartRateBpm><Value>{}</Value></AverageHeartRateBpm>",
+                hr.value
+            )?;
+        }
+        if let Some(hr) = &self.maximum_hr {
+            write!(
+                tcx,
+                "<MaximumHeartRateBpm><Value>{}</Value></MaximumHeartRateBpm>",
+                hr.value
+            )?;
+        }
+        tcx.push_str("<Intensity>Active</Intensity>");
+        if let Some(cadence) = self.cadence {
+            write!(tcx, "<Cadence>{}</Cadence>", cadence)?;
+        }
+        tcx.push_str("<TriggerMethod>Manual</TriggerMethod>\n");
+        // Laps without any trackpoints (e.g. pool swims) are read back with an empty track.
+        if !self.track.track_points.is_empty() {
+            write_tcx_track(tcx, &self.track)?;
+        }
+        for extension in self.extensions.iter() {
+            write_tcx_lap_extension(tcx, extension)?;
+        }
+        tcx.push_str("      </Lap>\n");
+        Ok(())
+    }
+}
+
+fn write_tcx_lap_extension(tcx: &mut String, extension: &LapExtension) -> Result<()> {
+    tcx.push_str("        <Extensions>");
+    if let Some(lx) = &extension.lx {
+        tcx.push_str("<ns3:LX>");
+        optional_element(tcx, "ns3:AvgSpeed", lx.avg_speed)?;
+        optional_element(tcx, "ns3:AvgRunCadence", lx.avg_cadence)?;
+        optional_element(tcx, "ns3:MaxRunCadence", lx.max_cadence)?;
+        optional_element(tcx, "ns3:AvgWatts", lx.avg_watts)?;
+        optional_element(tcx, "ns3:MaxWatts", lx.max_watts)?;
+        tcx.push_str("</ns3:LX>");
+    }
+    tcx.push_str("</Extensions>\n");
+    Ok(())
+}
+
+fn write_tcx_track(tcx: &mut String, track: &Track) -> Result<()> {
+    tcx.push_str("        <Track>\n");
+    for tp in track.track_points.iter() {
+        write_tcx_trackpoint(tcx, tp)?;
+    }
+    tcx.push_str("        </Track>\n");
+    Ok(())
+}
+
+fn write_tcx_trackpoint(tcx: &mut String, tp: &TrackPoint) -> Result<()> {
+    write!(
+        tcx,
+        "          <Trackpoint><Time>{}</Time>",
+        tp.time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    )?;
+    if let Some(position) = tp.position {
+        write!(
+            tcx,
+            "<Position><LatitudeDegrees>{}</LatitudeDegrees>\
+             <LongitudeDegrees>{}</LongitudeDegrees></Position>",
+            position.lat, position.long
+        )?;
+    }
+    optional_element(tcx, "AltitudeMeters", tp.altitude)?;
+    write!(tcx, "<DistanceMeters>{}</DistanceMeters>", tp.distance)?;
+    if let Some(hr) = &tp.hr {
+        write!(
+            tcx,
+            "<HeartRateBpm><Value>{}</Value></HeartRateBpm>",
+            hr.value
+        )?;
+    }
+    for extension in tp.extensions.iter() {
+        tcx.push_str("<Extensions>");
+        for tpx in extension.tpx.iter() {
+            tcx.push_str("<ns3:TPX>");
+            optional_element(tcx, "ns3:Speed", tpx.speed)?;
+            optional_element(tcx, "ns3:RunCadence", tpx.cadence)?;
+            optional_element(tcx, "ns3:Watts", tpx.watts)?;
+            tcx.push_str("</ns3:TPX>");
+        }
+        tcx.push_str("</Extensions>");
+    }
+    tcx.push_str("</Trackpoint>\n");
+    Ok(())
+}
+
+/// Writes `<name>value</name>`, or nothing when there's no value.
+fn optional_element<T: std::fmt::Display>(
+    tcx: &mut String,
+    name: &str,
+    value: Option<T>,
+) -> Result<()> {
+    if let Some(value) = value {
+        write!(tcx, "<{}>{}</{}>", name, value, name)?;
+    }
+    Ok(())
+}
+
+fn trackpoints_json(lap: &Lap, paces: &HashMap<DateTime<Utc>, f32>) -> Value {
+    lap.track
+        .track_points
+        .iter()
+        .map(|tp| {
+            json!({
+                "time": tp.time.to_rfc3339_opts(SecondsFormat::Secs, true),
+                "distance_meters": tp.distance,
+                "altitude_meters": tp.altitude,
+                "lat": tp.position.map(|p| p.lat),
+                "long": tp.position.map(|p| p.long),
+                "hr": tp.hr.as_ref().map(|hr| hr.value),
+                "speed_ms": tp.speed_ms(),
+                "cadence": tp.tpx().find_map(|tpx| tpx.cadence),
+                "watts": tp.watts(),
+                "pace_sec_per_mile": paces.get(&tp.time),
+            })
+        })
+        .collect()
+}
+
+/// The `Sport` attribute value for `sport`: the schema only allows Running, Biking and
+/// Other, so every other sport is written as Other.
+fn tcx_sport(sport: Sport) -> Sport {
+    match sport {
+        Sport::Running | Sport::Biking => sport,
+        _ => Sport::Other,
+    }
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse_str, Sport};
+
+    static ACTIVITY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2" xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+  <Activities>
+    <Activity Sport="Running">
+      <Id>2024-03-01T12:00:00Z</Id>
+      <Lap StartTime="2024-03-01T12:00:00Z">
+        <TotalTimeSeconds>2</TotalTimeSeconds><DistanceMeters>6.5</DistanceMeters><Calories>1</Calories>
+        <AverageHeartRateBpm><Value>140</Value></AverageHeartRateBpm>
+        <MaximumHeartRateBpm><Value>150</Value></MaximumHeartRateBpm>
+        <Intensity>Active</Intensity><TriggerMethod>Manual</TriggerMethod>
+        <Track>
+          <Trackpoint><Time>2024-03-01T12:00:00Z</Time><Position><LatitudeDegrees>40.0</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1600.5</AltitudeMeters><DistanceMeters>0</DistanceMeters><HeartRateBpm><Value>135</Value></HeartRateBpm><Extensions><ns3:TPX><ns3:Speed>3.25</ns3:Speed><ns3:RunCadence>85</ns3:RunCadence><ns3:Watts>250</ns3:Watts></ns3:TPX></Extensions></Trackpoint>
+          <Trackpoint><Time>2024-03-01T12:00:02Z</Time><Position><LatitudeDegrees>40.00005</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position><AltitudeMeters>1601</AltitudeMeters><DistanceMeters>6.5</DistanceMeters><HeartRateBpm><Value>145</Value></HeartRateBpm></Trackpoint>
+        </Track>
+        <Extensions><ns3:LX><ns3:AvgSpeed>3.25</ns3:AvgSpeed><ns3:AvgRunCadence>85</ns3:AvgRunCadence><ns3:AvgWatts>250</ns3:AvgWatts></ns3:LX></Extensions>
+      </Lap>
+      <Creator xsi:type="Device_t"><Name>Forerunner 245</Name><UnitId>3991234567</UnitId><ProductID>3076</ProductID><Version><VersionMajor>9</VersionMajor><VersionMinor>10</VersionMinor><BuildMajor>0</BuildMajor><BuildMinor>0</BuildMinor></Version></Creator>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>
+"#;
+
+    #[test]
+    fn to_tcx_string_round_trips() {
+        let parsed = parse_str(ACTIVITY).unwrap();
+        let written = parsed.to_tcx_string().unwrap();
+        assert_eq!(parse_str(&written).unwrap(), parsed);
+    }
+
+    #[test]
+    fn to_tcx_string_writes_schema_sports_and_calories() {
+        let swim = ACTIVITY
+            .replace("Sport=\"Running\"", "Sport=\"Swimming\"")
+            .replace("<Calories>1</Calories>", "");
+        let written = parse_str(&swim).unwrap().to_tcx_string().unwrap();
+
+        assert!(written.contains("<Activity Sport=\"Other\">"));
+        assert!(written.contains("<Calories>0</Calories>"));
+        let reparsed = parse_str(&written).unwrap();
+        assert_eq!(reparsed.get_activity(0).unwrap().sport, Sport::Other);
+    }
+
+    #[test]
+    fn to_tcx_string_writes_a_complete_device() {
+        let written = parse_str(ACTIVITY).unwrap().to_tcx_string().unwrap();
+        assert!(written.contains(
+            "<Creator xsi:type=\"Device_t\"><Name>Forerunner 245</Name><UnitId>3991234567</UnitId>\
+             <ProductID>3076</ProductID><Version><VersionMajor>9</VersionMajor>\
+             <VersionMinor>10</VersionMinor></Version></Creator>"
+        ));
+    }
+
+    #[test]
+    fn to_geojson_writes_a_line_string_feature() {
+        let parsed = parse_str(ACTIVITY).unwrap();
+        let geojson = parsed.get_activity(0).unwrap().to_geojson().unwrap();
+        let feature: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+
+        assert_eq!(feature["type"], "Feature");
+        assert_eq!(feature["geometry"]["type"], "LineString");
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            serde_json::json!([[-105.0, 40.0], [-105.0, 40.00005]])
+        );
+        assert_eq!(feature["properties"]["distance_meters"], 6.5);
+        assert_eq!(feature["properties"]["duration_seconds"], 2.0);
+        assert_eq!(
+            feature["properties"]["heart_rates"],
+            serde_json::json!([135, 145])
+        );
+    }
+
+    #[test]
+    fn to_geojson_skips_positionless_points() {
+        let indoor = ACTIVITY
+            .replace(
+                "<Position><LatitudeDegrees>40.0</LatitudeDegrees><LongitudeDegrees>-105.0</LongitudeDegrees></Position>",
+                "",
+            )
+            .replace("<HeartRateBpm><Value>145</Value></HeartRateBpm>", "");
+        let parsed = parse_str(&indoor).unwrap();
+        let geojson = parsed.get_activity(0).unwrap().to_geojson().unwrap();
+        let feature: serde_json::Value = serde_json::from_str(&geojson).unwrap();
+
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            serde_json::json!([[-105.0, 40.00005]])
+        );
+        // The only point with HR had no position.
+        assert!(feature["properties"].get("heart_rates").is_none());
+    }
+
+    #[test]
+    fn to_detailed_json_nests_laps_and_optionally_trackpoints() {
+        let parsed = parse_str(ACTIVITY).unwrap();
+        let activity = parsed.get_activity(0).unwrap();
+
+        let summary: serde_json::Value =
+            serde_json::from_str(&activity.to_detailed_json(false).unwrap()).unwrap();
+        assert_eq!(summary["summary"]["laps"], 1);
+        assert_eq!(summary["laps"][0]["lap"], 1);
+        assert_eq!(summary["laps"][0]["average_hr"], 140);
+        assert!(summary["laps"][0].get("trackpoints").is_none());
+
+        let detailed: serde_json::Value =
+            serde_json::from_str(&activity.to_detailed_json(true).unwrap()).unwrap();
+        let trackpoints = detailed["laps"][0]["trackpoints"].as_array().unwrap();
+        assert_eq!(trackpoints.len(), 2);
+        assert_eq!(trackpoints[0]["time"], "2024-03-01T12:00:00Z");
+        assert_eq!(trackpoints[0]["watts"], 250);
+        assert!(trackpoints[0]["pace_sec_per_mile"].is_null());
+        assert!(trackpoints[1]["watts"].is_null());
+        let pace = trackpoints[1]["pace_sec_per_mile"].as_f64().unwrap();
+        assert!((pace - 2.0 * 1609.344 / 6.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn detailed_json_pace_is_null_when_standing_still() {
+        let standing = ACTIVITY.replace(
+            "<DistanceMeters>6.5</DistanceMeters><HeartRateBpm>",
+            "<DistanceMeters>0</DistanceMeters><HeartRateBpm>",
+        );
+        let parsed = parse_str(&standing).unwrap();
+        let json = parsed
+            .get_activity(0)
+            .unwrap()
+            .to_detailed_json(true)
+            .unwrap();
+        let detailed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let trackpoints = detailed["laps"][0]["trackpoints"].as_array().unwrap();
+        assert!(trackpoints
+            .iter()
+            .all(|tp| tp["pace_sec_per_mile"].is_null()));
+    }
+}
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::{Activity, Sport, TrainingCenterDatabase};
+
+/// Longest gap between the end of one leg and the start of the next for them to still be
+/// part of the same multisport event.
+static MAX_TRANSITION: TimeDelta = TimeDelta::minutes(15);
+
+/// A triathlon or other multisport event recorded as consecutive activities, one per leg.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultisportActivity {
+    /// The swim/bike/run (etc.) legs, in order.
+    pub legs: Vec<MultisportLeg>,
+    /// Time spent between each leg and the next, so one fewer than there are legs.
+    pub transitions: Vec<Duration>,
+}
+
+/// Totals for one leg of a [MultisportActivity].
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultisportLeg {
+    pub sport: Sport,
+    pub start_time: DateTime<Utc>,
+    pub duration: Duration,
+    pub distance_meters: f32,
+}
+
+impl MultisportActivity {
+    /// Combined moving time of every leg, excluding transitions.
+    pub fn moving_time(&self) -> Duration {
+        self.legs.iter().map(|l| l.duration).sum()
+    }
+
+    /// Combined time of every transition.
+    pub fn transition_time(&self) -> Duration {
+        self.transitions.iter().sum()
+    }
+
+    /// Time from the start of the first leg to the end of the last, transitions included.
+    pub fn total_time(&self) -> Duration {
+        self.moving_time() + self.transition_time()
+    }
+
+    pub fn total_distance_meters(&self) -> f32 {
+        self.legs.iter().map(|l| l.distance_meters).sum()
+    }
+}
+
+impl TrainingCenterDatabase {
+    /// Groups this file's activities into a multisport event, if they look like one.
+    ///
+    /// Activities are ordered by start time and must follow each other closely: each one
+    /// must start no more than 15 minutes after the previous one ended. An activity with
+    /// [Sport::Other] between two legs is treated as a transition (as some devices record
+    /// T1/T2 that way), with its time counted towards that transition. Returns `None` if
+    /// fewer than two legs remain, or if any activity's id isn't a start timestamp.
+    pub fn as_multisport(&self) -> Option<MultisportActivity> {
+        let mut activities: Vec<(&Activity, DateTime<Utc>)> = self
+            .activities
+            .as_ref()?
+            .activities
+            .iter()
+            .map(|activity| Some((activity, activity.start_time()?)))
+            .collect::<Option<_>>()?;
+        activities.sort_by_key(|(_, start)| *start);
+
+        let mut legs: Vec<MultisportLeg> = vec![];
+        let mut transitions = vec![];
+        let mut last_end: Option<DateTime<Utc>> = None;
+        let mut last_leg_end: Option<DateTime<Utc>> = None;
+        for (i, (activity, start_time)) in activities.iter().enumerate() {
+            let duration = activity.duration();
+            if last_end.is_some_and(|end| *start_time - end > MAX_TRANSITION) {
+                return None;
+            }
+            last_end = Some(*start_time + TimeDelta::from_std(duration).ok()?);
+
+            let between_legs = !legs.is_empty() && i + 1 < activities.len();
+            if activity.sport == Sport::Other && between_legs {
+                // Counted as part of the transition to the next leg.
+                continue;
+            }
+
+            if let Some(leg_end) = last_leg_end {
+                transitions.push((*start_time - leg_end).to_std().unwrap_or_default());
+            }
+            legs.push(MultisportLeg {
+                sport: activity.sport,
+                start_time: *start_time,
+                duration,
+                distance_meters: activity.total_distance_meters(),
+            });
+            last_leg_end = last_end;
+        }
+
+        if legs.len() < 2 {
+            return None;
+        }
+        Some(MultisportActivity { legs, transitions })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    /// A file with an activity for each `(sport, start, seconds)`.
+    fn legs(legs: &[(&str, &str, u32)]) -> TrainingCenterDatabase {
+        let activities: String = legs
+            .iter()
+            .map(|(sport, start, seconds)| {
+                format!(
+                    "<Activity Sport=\"{sport}\"><Id>{start}</Id><Lap StartTime=\"{start}\">\
+                     <TotalTimeSeconds>{seconds}</TotalTimeSeconds><DistanceMeters>1000</DistanceMeters>\
+                     <Track></Track></Lap></Activity>"
+                )
+            })
+            .collect();
+        parse_str(&format!(
+            "<TrainingCenterDatabase><Activities>{activities}</Activities></TrainingCenterDatabase>"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn as_multisport_counts_transition_activities_as_transitions() {
+        let triathlon = legs(&[
+            ("Biking", "2024-06-01T07:15:00Z", 1800),
+            ("Other", "2024-06-01T07:12:00Z", 120),
+            ("Swimming", "2024-06-01T07:00:00Z", 600),
+            ("Running", "2024-06-01T07:47:00Z", 900),
+        ])
+        .as_multisport()
+        .unwrap();
+
+        let sports: Vec<Sport> = triathlon.legs.iter().map(|leg| leg.sport).collect();
+        assert_eq!(sports, [Sport::Swimming, Sport::Biking, Sport::Running]);
+        assert_eq!(
+            triathlon.transitions,
+            [Duration::from_secs(300), Duration::from_secs(120)]
+        );
+        assert_eq!(triathlon.moving_time(), Duration::from_secs(3300));
+        assert_eq!(triathlon.total_time(), Duration::from_secs(3720));
+        assert_eq!(triathlon.total_distance_meters(), 3000.0);
+    }
+
+    #[test]
+    fn as_multisport_needs_legs_close_together() {
+        let apart = legs(&[
+            ("Swimming", "2024-06-01T07:00:00Z", 600),
+            ("Running", "2024-06-01T07:30:00Z", 900),
+        ]);
+        assert_eq!(apart.as_multisport(), None);
+        let single = legs(&[("Running", "2024-06-01T07:00:00Z", 900)]);
+        assert_eq!(single.as_multisport(), None);
+    }
+}
@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use super::{Activity, TrackPoint};
+use crate::units::METERS_PER_MILE;
+
+/// One consecutive stretch of a fixed distance, e.g. a mile, as returned by
+/// [Activity::splits].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    /// Position of the split in the activity, starting at 1.
+    pub index: usize,
+    /// Distance covered in meters: the split distance, except for a final partial split.
+    pub distance_meters: f32,
+    pub duration: Duration,
+    /// Average pace over the split in seconds per mile.
+    pub pace_seconds: Duration,
+    /// Average HR over the split, weighted by time. `None` if no trackpoint in it had HR.
+    pub average_hr: Option<usize>,
+}
+
+/// Running totals for the split being walked through.
+#[derive(Default)]
+struct SplitTotals {
+    start: f64,
+    hr_weighted: f64,
+    hr_seconds: f64,
+}
+
+impl SplitTotals {
+    /// Counts the time from `from` to `to` (seconds since the activity started) towards the
+    /// average HR, at the HR of the trackpoint the interval started at.
+    fn add(&mut self, tp: &TrackPoint, from: f64, to: f64) {
+        if let Some(hr) = &tp.hr {
+            self.hr_weighted += hr.value as f64 * (to - from);
+            self.hr_seconds += to - from;
+        }
+    }
+
+    /// The split ending `end` seconds into the activity after covering `meters`.
+    fn finish(&self, index: usize, meters: f32, end: f64) -> Split {
+        let seconds = (end - self.start).max(0.0);
+        Split {
+            index,
+            distance_meters: meters,
+            duration: Duration::from_secs_f64(seconds),
+            pace_seconds: Duration::from_secs_f64(seconds / meters as f64 * METERS_PER_MILE as f64),
+            average_hr: (self.hr_seconds > 0.0)
+                .then(|| (self.hr_weighted / self.hr_seconds).round() as usize),
+        }
+    }
+}
+
+impl Activity {
+    /// The activity cut into consecutive `split_meters` from the start, e.g. mile splits
+    /// for `METERS_PER_MILE`, to see whether a race was negative split. Splits run across
+    /// lap boundaries, and each boundary is interpolated between the trackpoints either side
+    /// of it. Distance left over after the last full split makes a final, shorter split.
+    pub fn splits(&self, split_meters: f32) -> Vec<Split> {
+        let tps: Vec<&TrackPoint> = self.track_points().collect();
+        let Some(first) = tps.first() else {
+            return vec![];
+        };
+        if split_meters <= 0.0 {
+            return vec![];
+        }
+        let elapsed = |tp: &TrackPoint| (tp.time - first.time).num_milliseconds() as f64 / 1000.0;
+
+        let mut splits = vec![];
+        let mut totals = SplitTotals::default();
+        let mut boundary = first.distance + split_meters;
+        for pair in tps.windows(2) {
+            let (previous, tp) = (pair[0], pair[1]);
+            let (t0, t1) = (elapsed(previous), elapsed(tp));
+            let mut from = t0;
+            while tp.distance >= boundary && tp.distance > previous.distance {
+                let fraction = (boundary - previous.distance) / (tp.distance - previous.distance);
+                let crossed = t0 + (t1 - t0) * fraction.max(0.0) as f64;
+                totals.add(previous, from, crossed);
+                splits.push(totals.finish(splits.len() + 1, split_meters, crossed));
+                totals = SplitTotals {
+                    start: crossed,
+                    ..SplitTotals::default()
+                };
+                from = crossed;
+                boundary += split_meters;
+            }
+            totals.add(previous, from, t1);
+        }
+
+        let last = tps[tps.len() - 1];
+        let remaining = last.distance - (boundary - split_meters);
+        if remaining > 0.0 {
+            splits.push(totals.finish(splits.len() + 1, remaining, elapsed(last)));
+        }
+        splits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_str;
+
+    #[test]
+    fn splits_interpolate_boundaries_and_keep_the_remainder() {
+        // 4m/s, with HR rising by 10 every 100s.
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>400</TotalTimeSeconds><DistanceMeters>1600</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:00:00Z</Time><DistanceMeters>0</DistanceMeters><HeartRateBpm><Value>140</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:01:40Z</Time><DistanceMeters>400</DistanceMeters><HeartRateBpm><Value>150</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:03:20Z</Time><DistanceMeters>800</DistanceMeters><HeartRateBpm><Value>160</Value></HeartRateBpm></Trackpoint>
+</Track></Lap>
+<Lap StartTime="2024-03-01T12:03:20Z"><TotalTimeSeconds>200</TotalTimeSeconds><DistanceMeters>800</DistanceMeters><Track>
+<Trackpoint><Time>2024-03-01T12:05:00Z</Time><DistanceMeters>1200</DistanceMeters><HeartRateBpm><Value>170</Value></HeartRateBpm></Trackpoint>
+<Trackpoint><Time>2024-03-01T12:06:40Z</Time><DistanceMeters>1500</DistanceMeters></Trackpoint>
+</Track></Lap></Activity></Activities></TrainingCenterDatabase>"#;
+        let tcb = parse_str(tcx).unwrap();
+        let splits = tcb.get_activity(0).unwrap().splits(1000.0);
+
+        assert_eq!(splits.len(), 2);
+        let (first, last) = (&splits[0], &splits[1]);
+        assert_eq!((first.index, first.distance_meters), (1, 1000.0));
+        assert_eq!(first.duration.as_secs(), 250);
+        assert_eq!(first.pace_seconds.as_secs(), 402);
+        assert_eq!(first.average_hr, Some(148));
+        assert_eq!((last.index, last.distance_meters), (2, 500.0));
+        assert_eq!(last.duration.as_secs(), 150);
+        assert_eq!(last.average_hr, Some(167));
+        assert!(tcb.get_activity(0).unwrap().splits(0.0).is_empty());
+    }
+
+    #[test]
+    fn splits_need_trackpoints() {
+        let tcx = r#"<TrainingCenterDatabase><Activities><Activity Sport="Running"><Id>2024-03-01T12:00:00Z</Id>
+<Lap StartTime="2024-03-01T12:00:00Z"><TotalTimeSeconds>400</TotalTimeSeconds><DistanceMeters>1600</DistanceMeters></Lap>
+</Activity></Activities></TrainingCenterDatabase>"#;
+        let tcb = parse_str(tcx).unwrap();
+        let activity = tcb.get_activity(0).unwrap();
+        assert!(activity.splits(1000.0).is_empty());
+    }
+}
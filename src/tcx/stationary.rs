@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::{Activity, Position, TrackPoint};
+
+/// Farthest, in meters, trackpoints may drift from where a stop began and still count as
+/// standing still. Leaves room for GPS jitter while stationary.
+static STATIONARY_RADIUS_METERS: f64 = 5.0;
+
+/// A stretch of an activity spent standing still while the device kept recording, e.g.
+/// waiting at a crossing. See [Activity::stationary_periods].
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationaryPeriod {
+    pub start: DateTime<Utc>,
+    pub duration: Duration,
+    /// Where the stop began, or `None` if that trackpoint has no position.
+    pub position: Option<Position>,
+}
+
+impl Activity {
+    /// Every period of at least `min_duration` during which consecutive trackpoints stayed
+    /// within [STATIONARY_RADIUS_METERS] of where the period began. Unlike auto-pause,
+    /// these are still recorded, so they count towards the activity's duration. Movement
+    /// is measured by GPS position, or by recorded distance for trackpoints without one.
+    /// [Activity::is_indoor] activities have none, as they don't record moving anywhere.
+    pub fn stationary_periods(&self, min_duration: Duration) -> Vec<StationaryPeriod> {
+        if self.is_indoor() {
+            return vec![];
+        }
+        let points: Vec<&TrackPoint> = self.track_points().collect();
+        let mut periods = vec![];
+        let mut start = 0;
+        while start < points.len() {
+            let anchor = points[start];
+            let end = points[start + 1..]
+                .iter()
+                .position(|tp| meters_moved(anchor, tp) > STATIONARY_RADIUS_METERS)
+                .map_or(points.len(), |moved| start + 1 + moved);
+            let duration = (points[end - 1].time - anchor.time)
+                .to_std()
+                .unwrap_or_default();
+            if !duration.is_zero() && duration >= min_duration {
+                periods.push(StationaryPeriod {
+                    start: anchor.time,
+                    duration,
+                    position: anchor.position,
+                });
+            }
+            start = end;
+        }
+        periods
+    }
+}
+
+fn meters_moved(from: &TrackPoint, to: &TrackPoint) -> f64 {
+    match (from.position, to.position) {
+        (Some(from), Some(to)) => from.distance_to(&to),
+        _ => (to.distance - from.distance).abs() as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    /// A walk with a trackpoint at each `(seconds, latitude)`, along -75° longitude.
+    fn walk(points: &[(u32, &str)]) -> Activity {
+        let trackpoints: String = points
+            .iter()
+            .map(|(seconds, lat)| {
+                format!(
+                    "<Trackpoint><Time>2024-03-01T12:{:02}:{:02}Z</Time><Position>\
+                     <LatitudeDegrees>{lat}</LatitudeDegrees><LongitudeDegrees>-75.0</LongitudeDegrees>\
+                     </Position></Trackpoint>",
+                    seconds / 60,
+                    seconds % 60
+                )
+            })
+            .collect();
+        let tcx = format!(
+            "<TrainingCenterDatabase><Activities><Activity Sport=\"Walking\">\
+             <Id>2024-03-01T12:00:00Z</Id><Lap StartTime=\"2024-03-01T12:00:00Z\">\
+             <TotalTimeSeconds>90</TotalTimeSeconds><DistanceMeters>220</DistanceMeters>\
+             <Track>{trackpoints}</Track></Lap></Activity></Activities></TrainingCenterDatabase>"
+        );
+        parse_str(&tcx).unwrap().get_activity(0).unwrap().clone()
+    }
+
+    #[test]
+    fn stationary_periods_allow_for_gps_jitter() {
+        // About a meter apart while waiting, then 111m apart once moving.
+        let activity = walk(&[
+            (0, "40.00000"),
+            (10, "40.00001"),
+            (70, "40.00002"),
+            (80, "40.001"),
+            (90, "40.002"),
+        ]);
+        let periods = activity.stationary_periods(Duration::from_secs(60));
+        assert_eq!(periods.len(), 1);
+        assert_eq!(periods[0].start.to_rfc3339(), "2024-03-01T12:00:00+00:00");
+        assert_eq!(periods[0].duration, Duration::from_secs(70));
+        assert_eq!(periods[0].position.map(|p| p.lat), Some(40.0));
+        assert!(activity
+            .stationary_periods(Duration::from_secs(80))
+            .is_empty());
+    }
+
+    #[test]
+    fn moving_throughout_has_no_stationary_periods() {
+        let activity = walk(&[(0, "40.000"), (10, "40.001"), (20, "40.002")]);
+        assert!(activity.stationary_periods(Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::OnceLock;
+
+use chrono::DateTime;
+use chrono_tz::Tz;
+use tzf_rs::DefaultFinder;
+
+use super::Activity;
+
+/// Loading the timezone boundaries is slow, so it's only done once.
+fn finder() -> &'static DefaultFinder {
+    static FINDER: OnceLock<DefaultFinder> = OnceLock::new();
+    FINDER.get_or_init(DefaultFinder::new)
+}
+
+impl Activity {
+    /// The timezone at the activity's first GPS position, or `None` without any (e.g.
+    /// indoor activities) or in the rare spots no timezone covers. The first lookup loads
+    /// the bundled timezone boundaries, which takes a moment.
+    pub fn guess_timezone(&self) -> Option<Tz> {
+        let start = self.track_points().find_map(|tp| tp.position)?;
+        finder().get_tz_name(start.long, start.lat).parse().ok()
+    }
+
+    /// [Activity::start_time] in the timezone from [Activity::guess_timezone].
+    pub fn local_start_time(&self) -> Option<DateTime<Tz>> {
+        Some(self.start_time()?.with_timezone(&self.guess_timezone()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_str;
+
+    fn run_at(position: &str) -> Activity {
+        let tcx = format!(
+            "<TrainingCenterDatabase><Activities><Activity Sport=\"Running\">\
+             <Id>2024-03-01T12:00:00Z</Id><Lap StartTime=\"2024-03-01T12:00:00Z\">\
+             <TotalTimeSeconds>1</TotalTimeSeconds><DistanceMeters>3</DistanceMeters><Track>\
+             <Trackpoint><Time>2024-03-01T12:00:00Z</Time>{position}</Trackpoint></Track></Lap>\
+             </Activity></Activities></TrainingCenterDatabase>"
+        );
+        parse_str(&tcx).unwrap().get_activity(0).unwrap().clone()
+    }
+
+    #[test]
+    fn guess_timezone_looks_up_the_first_position() {
+        let boulder = run_at(
+            "<Position><LatitudeDegrees>40.015</LatitudeDegrees>\
+             <LongitudeDegrees>-105.27</LongitudeDegrees></Position>",
+        );
+        assert_eq!(boulder.guess_timezone(), Some(chrono_tz::America::Denver));
+        assert_eq!(
+            boulder.local_start_time().unwrap().to_rfc3339(),
+            "2024-03-01T05:00:00-07:00"
+        );
+    }
+
+    #[test]
+    fn indoor_activities_have_no_timezone() {
+        let treadmill = run_at("");
+        assert_eq!(treadmill.guess_timezone(), None);
+        assert_eq!(treadmill.local_start_time(), None);
+    }
+}
@@ -0,0 +1,30 @@
+//! Fixtures shared by the unit tests.
+
+use std::path::PathBuf;
+
+use crate::{parse_str, stats::ActivityStats};
+
+/// A path named `name` under the system temp dir, unique to this test run.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("tcxrs-{}-{}", std::process::id(), name))
+}
+
+/// A TCX file of one `sport` activity starting at `start`, made of a 10 minute lap covering
+/// `meters` that ends with `lap` (e.g. its average HR or track).
+pub(crate) fn one_lap_tcx(sport: &str, start: &str, meters: f64, lap: &str) -> String {
+    format!(
+        r#"<TrainingCenterDatabase><Activities><Activity Sport="{sport}"><Id>{start}</Id>
+<Lap StartTime="{start}"><TotalTimeSeconds>600</TotalTimeSeconds><DistanceMeters>{meters}</DistanceMeters>{lap}</Lap>
+</Activity></Activities></TrainingCenterDatabase>"#
+    )
+}
+
+/// Stats of the first activity in `tcx`.
+pub(crate) fn stats_of(tcx: &str) -> ActivityStats {
+    ActivityStats::new(parse_str(tcx).unwrap().get_activity(0).unwrap())
+}
+
+/// Stats of a 2 km run starting at `start`, taking 10 minutes.
+pub(crate) fn run_stats(start: &str) -> ActivityStats {
+    stats_of(&one_lap_tcx("Running", start, 2000.0, ""))
+}
@@ -0,0 +1,17 @@
+//! Unit conversions shared by every calculation, so imperial and metric outputs agree.
+
+pub static METERS_PER_MILE: f32 = 1609.344;
+/// Derived from [METERS_PER_MILE] so the two can't drift apart.
+pub static MILES_PER_METER: f32 = 1.0 / METERS_PER_MILE;
+pub static FEET_PER_METER: f64 = 3.28084;
+
+/// Units distances, paces and elevations are written in by the text output. Machine
+/// readable outputs (JSON and CSV) carry both and don't depend on this.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Units {
+    /// Miles, minutes per mile and feet.
+    #[default]
+    Imperial,
+    /// Kilometers, minutes per kilometer and meters.
+    Metric,
+}
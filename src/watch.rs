@@ -0,0 +1,120 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{info, instrument, warn};
+
+use crate::{is_tcx_file, parse_file, stats::ActivityStats, TrainingCenterDatabase};
+
+/// A file event often arrives while the device or sync tool is still writing the file,
+/// so parsing is retried a few times before giving up on it.
+static PARSE_ATTEMPTS: u32 = 5;
+static RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Watches `folder` recursively and calls `on_new` with the stats of every activity
+/// in every TCX file (including `.tcx.gz` with the `archives` feature) created or
+/// modified within it. Runs until the watcher fails.
+///
+/// Events come from the `notify` crate, which uses inotify on Linux, FSEvents on macOS and
+/// ReadDirectoryChangesW on Windows. One save usually produces a burst of events (and
+/// FSEvents may coalesce them), so events are debounced before parsing and a file may
+/// be reported again if it's modified later. Network filesystems may not deliver events.
+#[instrument(skip(on_new))]
+pub async fn watch_folder(folder: &Path, on_new: impl Fn(ActivityStats)) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        // The receiver only goes away once we've stopped watching.
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(folder, RecursiveMode::Recursive)?;
+    info!("Watching for new activities");
+
+    while let Some(event) = rx.recv().await {
+        let mut changed = BTreeSet::new();
+        collect_tcx_paths(event?, &mut changed);
+
+        // Let the rest of a burst arrive so each file is only parsed once.
+        tokio::time::sleep(RETRY_DELAY).await;
+        while let Ok(event) = rx.try_recv() {
+            collect_tcx_paths(event?, &mut changed);
+        }
+
+        for path in changed {
+            match parse_with_retry(&path).await {
+                Ok(mut tcb) => {
+                    for activity in tcb.activities_iter_mut() {
+                        activity.calc_lap_elevations();
+                        on_new(ActivityStats::from(&*activity));
+                    }
+                }
+                Err(e) => warn!("Unable to parse {}: {}", path.display(), e),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn collect_tcx_paths(event: Event, paths: &mut BTreeSet<PathBuf>) {
+    if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        return;
+    }
+    paths.extend(event.paths.into_iter().filter(|p| is_tcx_file(p)));
+}
+
+async fn parse_with_retry(path: &Path) -> Result<TrainingCenterDatabase> {
+    let mut attempt = 1;
+    loop {
+        match parse_file(path).await {
+            Ok(tcb) => return Ok(tcb),
+            Err(e) if attempt >= PARSE_ATTEMPTS => return Err(e),
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, RemoveKind};
+
+    fn event(kind: EventKind, paths: &[&str]) -> Event {
+        paths.iter().fold(Event::new(kind), |event, path| {
+            event.add_path(PathBuf::from(path))
+        })
+    }
+
+    #[test]
+    fn collect_tcx_paths_matches_parse_folder() {
+        let mut paths = BTreeSet::new();
+        collect_tcx_paths(
+            event(
+                EventKind::Create(CreateKind::File),
+                &["run.tcx", "ride.tcx.gz", "notes.txt", "run.tcx.part"],
+            ),
+            &mut paths,
+        );
+        let mut expected = BTreeSet::from([PathBuf::from("run.tcx")]);
+        if cfg!(feature = "archives") {
+            expected.insert(PathBuf::from("ride.tcx.gz"));
+        }
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn collect_tcx_paths_ignores_removals() {
+        let mut paths = BTreeSet::new();
+        collect_tcx_paths(
+            event(EventKind::Remove(RemoveKind::File), &["run.tcx"]),
+            &mut paths,
+        );
+        assert!(paths.is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+/// Max HR assumed for HR-derived metrics when the athlete's own isn't known.
+pub static DEFAULT_MAX_HR: usize = 190;
+
+/// Percent-of-max-HR boundaries for the common 5 zone model.
+pub static DEFAULT_HR_ZONE_PERCENTAGES: [f64; 5] = [50.0, 60.0, 70.0, 80.0, 90.0];
+
+/// Helpers for building the zone boundaries passed to [crate::Activity::hr_zones].
+pub struct HrZones;
+
+impl HrZones {
+    /// Absolute bpm boundaries for zones defined as percentages (e.g. `60.0`) of `max_hr`.
+    pub fn from_max(max_hr: usize, percentages: &[f64]) -> Vec<usize> {
+        percentages
+            .iter()
+            .map(|p| (max_hr as f64 * p / 100.0).round() as usize)
+            .collect()
+    }
+
+    /// Absolute bpm boundaries for zones defined as percentages of heart rate reserve
+    /// (Karvonen), i.e. of the range between `resting_hr` and `max_hr`.
+    pub fn from_reserve(max_hr: usize, resting_hr: usize, percentages: &[f64]) -> Vec<usize> {
+        let reserve = max_hr.saturating_sub(resting_hr) as f64;
+        percentages
+            .iter()
+            .map(|p| (resting_hr as f64 + reserve * p / 100.0).round() as usize)
+            .collect()
+    }
+
+    /// Boundaries for the default 5 zone preset (50/60/70/80/90% of `max_hr`).
+    pub fn default_from_max(max_hr: usize) -> Vec<usize> {
+        HrZones::from_max(max_hr, &DEFAULT_HR_ZONE_PERCENTAGES)
+    }
+}
+
+/// Percent-of-FTP boundaries for Coggan's 7 power zones, from active recovery (below 55%)
+/// up to neuromuscular power (above 150%).
+pub static COGGAN_POWER_ZONE_PERCENTAGES: [f64; 6] = [55.0, 75.0, 90.0, 105.0, 120.0, 150.0];
+
+/// Helpers for building the zone boundaries used by [crate::Activity::power_zones].
+pub struct PowerZones;
+
+impl PowerZones {
+    /// Absolute watt boundaries of Coggan's 7 zones for a functional threshold power of `ftp`.
+    pub fn from_ftp(ftp: usize) -> Vec<usize> {
+        // Percentages of FTP work out the same as percentages of max HR.
+        HrZones::from_max(ftp, &COGGAN_POWER_ZONE_PERCENTAGES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hr_zones_from_max() {
+        assert_eq!(HrZones::default_from_max(190), vec![95, 114, 133, 152, 171]);
+        assert_eq!(HrZones::from_max(200, &[75.0]), vec![150]);
+    }
+
+    #[test]
+    fn hr_zones_from_reserve() {
+        assert_eq!(
+            HrZones::from_reserve(190, 50, &[0.0, 50.0, 100.0]),
+            vec![50, 120, 190]
+        );
+        // A resting HR above max leaves no reserve.
+        assert_eq!(HrZones::from_reserve(60, 70, &[50.0]), vec![70]);
+    }
+
+    #[test]
+    fn power_zones_from_ftp() {
+        assert_eq!(
+            PowerZones::from_ftp(200),
+            vec![110, 150, 180, 210, 240, 300]
+        );
+    }
+}
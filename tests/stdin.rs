@@ -0,0 +1,47 @@
+//! Runs the `tcxrs` binary on a TCX document piped to its stdin.
+#![cfg(all(feature = "charts", feature = "async"))]
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/// The stdout of `tcxrs` run with `args` and `tests/fixtures/run.tcx` on its stdin.
+fn summary_of_stdin(args: &[&str]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_tcxrs"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(include_bytes!("fixtures/run.tcx"))
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stdin_summaries_are_printed() {
+    for args in [&["-"][..], &["--stdin"]] {
+        let summary = summary_of_stdin(args);
+        assert!(
+            summary.starts_with("=== 2024-03-01T12:00:00Z ===\n"),
+            "{summary}"
+        );
+        assert!(
+            summary.contains("  Distance: 1.24mi / 2.00km\n"),
+            "{summary}"
+        );
+        assert!(summary.contains("  Average HR: 150\n"), "{summary}");
+        assert!(
+            summary.contains("  Average Pace: 08:03 / mi\n"),
+            "{summary}"
+        );
+    }
+}